@@ -3,8 +3,10 @@ use chrono::prelude::Local;
 use std::fs;
 
 #[cfg(debug_assertions)]
+#[allow(clippy::redundant_static_lifetimes)]
 const BUILD_TYPE: &'static str = "debug";
 #[cfg(not(debug_assertions))]
+#[allow(clippy::redundant_static_lifetimes)]
 const BUILD_TYPE: &'static str = "release";
 
 fn main() {
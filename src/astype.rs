@@ -0,0 +1,263 @@
+/*
+ * astype.rs
+ * Encoding and decoding of the values stored in NKSC ast:* properties.
+ *
+ * Every property carries an astype:Type telling us how to read its rdf:value:
+ *
+ *   Long    a single 32 bit integer, little-endian, Base64 encoded           e.g. AgAAAA== is 2
+ *   Double  one or more 64 bit IEEE floats, little-endian, Base64 encoded    e.g. GPSLatitude holds degrees, minutes and seconds
+ *   Binary  raw bytes, Base64 encoded                                        e.g. AgIAAA== is GPSVersionID 2.2.0.0
+ *   Ascii   plain text                                                       e.g. WGS-84
+ *
+ * Byte order is always spelled out as little-endian rather than left to whatever machine we happen to be running on.
+ */
+
+use data_encoding::{BASE64, BASE64_NOPAD};
+
+// Define Structures
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsValue
+  {
+    Long(u32),
+    Double(Vec<f64>),
+    Binary(Vec<u8>),
+    Ascii(String),
+  }
+
+
+impl AsValue
+{
+  /** decode
+    fn decode(astype: &str, value: &str) -> Result<AsValue, String>
+      astype: &str = contents of astype:Type
+      value: &str = contents of rdf:value
+
+    Turns a property's rdf:value into a Rust value according to its astype:Type.
+  **/
+  pub fn decode(astype: &str, value: &str) -> Result<AsValue, String>
+  {
+    match astype
+      {
+        "Long" => decode_long(value).map(AsValue::Long),
+        "Double" => decode_doubles(value).map(AsValue::Double),
+        "Binary" => decode_binary(value).map(AsValue::Binary),
+        "Ascii" => Ok(AsValue::Ascii(value.to_string())),
+        _ => Err(format!("unknown astype:Type \"{}\"", astype)),
+      }
+  }
+
+
+  /** astype
+    fn astype(&self) -> &'static str
+
+    Returns the astype:Type name for the value.
+  **/
+  pub fn astype(&self) -> &'static str
+  {
+    match self
+      {
+        AsValue::Long(_) => "Long",
+        AsValue::Double(_) => "Double",
+        AsValue::Binary(_) => "Binary",
+        AsValue::Ascii(_) => "Ascii",
+      }
+  }
+
+
+  /** encode
+    fn encode(&self) -> String
+
+    Turns the value into the text that goes into rdf:value.
+  **/
+  pub fn encode(&self) -> String
+  {
+    match self
+      {
+        AsValue::Long(value) => encode_long(*value),
+        AsValue::Double(values) => encode_doubles(values),
+        AsValue::Binary(bytes) => encode_binary(bytes),
+        AsValue::Ascii(text) => text.clone(),
+      }
+  }
+}
+
+
+/** encode_long
+  fn encode_long(value: u32) -> String
+
+  Encodes a Long as four little-endian bytes in padded Base64.
+**/
+pub fn encode_long(value: u32) -> String
+{
+  BASE64.encode(&value.to_le_bytes())
+}
+
+
+/** decode_long
+  fn decode_long(value: &str) -> Result<u32, String>
+
+  Decodes a Long, which must be exactly four bytes.
+**/
+pub fn decode_long(value: &str) -> Result<u32, String>
+{
+  let bytes = decode_binary(value)?;
+
+  if bytes.len() != 4
+    {
+      return Err(format!("a Long should be 4 bytes, but \"{}\" is {}", value, bytes.len()));
+    }
+
+  Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+
+/** encode_doubles
+  fn encode_doubles(values: &[f64]) -> String
+
+  Encodes an array of Doubles as consecutive little-endian f64s in padded Base64.
+**/
+pub fn encode_doubles(values: &[f64]) -> String
+{
+  let mut raw_bytes: Vec<u8> = Vec::with_capacity(values.len() * 8);
+
+  for value in values
+    {
+      raw_bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+  BASE64.encode(&raw_bytes)
+}
+
+
+/** decode_doubles
+  fn decode_doubles(value: &str) -> Result<Vec<f64>, String>
+
+  Decodes an array of Doubles. The byte count has to be a whole number of f64s.
+**/
+pub fn decode_doubles(value: &str) -> Result<Vec<f64>, String>
+{
+  let bytes = decode_binary(value)?;
+
+  if bytes.is_empty() || bytes.len() % 8 != 0
+    {
+      return Err(format!("a Double should be a multiple of 8 bytes, but \"{}\" is {}", value, bytes.len()));
+    }
+
+  Ok(bytes.chunks_exact(8)
+          .map(|chunk| f64::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3], chunk[4], chunk[5], chunk[6], chunk[7]]))
+          .collect())
+}
+
+
+/** encode_binary
+  fn encode_binary(bytes: &[u8]) -> String
+
+  Encodes raw bytes as padded Base64.
+**/
+pub fn encode_binary(bytes: &[u8]) -> String
+{
+  BASE64.encode(bytes)
+}
+
+
+/** decode_binary
+  fn decode_binary(value: &str) -> Result<Vec<u8>, String>
+
+  Decodes Base64. Older versions of this program wrote some values without the padding, so we take it either way.
+**/
+pub fn decode_binary(value: &str) -> Result<Vec<u8>, String>
+{
+  let value = value.trim();
+
+  BASE64.decode(value.as_bytes())
+        .or_else(|_| BASE64_NOPAD.decode(value.trim_end_matches('=').as_bytes()))
+        .map_err(|e| format!("\"{}\" is not valid Base64: {}", value, e))
+}
+
+
+/** dms_to_degrees
+  fn dms_to_degrees(dms: &[f64]) -> Result<f64, String>
+
+  Turns a degrees, minutes, seconds triple (as stored in GPSLatitude and GPSLongitude) into decimal degrees.
+**/
+pub fn dms_to_degrees(dms: &[f64]) -> Result<f64, String>
+{
+  if dms.len() != 3
+    {
+      return Err(format!("expected degrees, minutes and seconds but got {} values", dms.len()));
+    }
+
+  Ok(dms[0]+(dms[1]/60.0)+(dms[2]/3600.0))
+}
+
+
+/** degrees_to_dms
+  fn degrees_to_dms(degrees: f64) -> Vec<f64>
+
+  Turns (unsigned) decimal degrees into a degrees, minutes, seconds triple, the reverse of dms_to_degrees.
+**/
+pub fn degrees_to_dms(degrees: f64) -> Vec<f64>
+{
+  let degrees = degrees.abs();
+  let whole_degrees = degrees.trunc();
+  let minutes = (degrees - whole_degrees) * 60.0;
+  let whole_minutes = minutes.trunc();
+
+  vec![whole_degrees, whole_minutes, (minutes - whole_minutes) * 60.0]
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  /*
+   * The values the first versions of this program wrote as string constants: an N latitude ref, a W longitude ref and GPSVersionID 2.2.0.0
+   */
+  #[test]
+  fn old_constants_decode()
+  {
+    assert_eq!(AsValue::decode("Long", "AAAAAA=="), Ok(AsValue::Long(0)));
+    assert_eq!(AsValue::decode("Long", "AgAAAA=="), Ok(AsValue::Long(2)));
+    assert_eq!(AsValue::decode("Binary", "AgIAAA=="), Ok(AsValue::Binary(vec![2, 2, 0, 0])));
+    assert_eq!(encode_long(2), "AgAAAA==");
+    assert_eq!(encode_binary(&[2, 2, 0, 0]), "AgIAAA==");
+  }
+
+  #[test]
+  fn byte_order_is_little_endian()
+  {
+    assert_eq!(decode_long("AQIDBA=="), Ok(0x04030201));
+    assert_eq!(decode_binary(&encode_doubles(&[1.0])), Ok(vec![0, 0, 0, 0, 0, 0, 0xf0, 0x3f]));
+  }
+
+  #[test]
+  fn wrong_lengths_are_rejected()
+  {
+    assert!(decode_long("AgAA").is_err());                                     // 3 bytes
+    assert!(decode_long("AgAAAAA=").is_err());                                 // 5 bytes
+    assert!(decode_doubles("").is_err());
+    assert!(decode_doubles("AAAAAAAA8D8A").is_err());                          // 9 bytes
+    assert!(AsValue::decode("Long", "not base64!").is_err());
+    assert!(AsValue::decode("Rational", "AAAAAA==").is_err());
+  }
+
+  #[test]
+  fn doubles_round_trip()
+  {
+    let values = vec![33.0, 51.0, 35.4, -0.000001, f64::MAX, 1e-300];
+
+    assert_eq!(decode_doubles(&encode_doubles(&values)), Ok(values.clone()));
+    assert_eq!(AsValue::decode("Double", &AsValue::Double(values.clone()).encode()), Ok(AsValue::Double(values)));
+  }
+
+  #[test]
+  fn unpadded_values_are_accepted()
+  {
+    assert_eq!(decode_long("AgAAAA"), Ok(2));
+    assert_eq!(decode_binary("AgIAAA"), Ok(vec![2, 2, 0, 0]));
+    assert_eq!(decode_binary(" AgIAAA==\n"), Ok(vec![2, 2, 0, 0]));
+  }
+}
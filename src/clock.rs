@@ -0,0 +1,346 @@
+/*
+ * clock.rs
+ * Camera clock calibration.
+ *
+ * Matching photos to a track only works if we know what the camera's clock was reading against UTC, and no two of our bodies agree.
+ * To find out, take a photo of a GPS (or a phone) showing the time, and tell us what time it showed: the difference between that and
+ * the photo's DateTimeOriginal is what has to be added to that camera's clock to get UTC. Calibrations are kept per body, by serial
+ * number, in a little text file:
+ *
+ *   # serial, camera time, seconds to add to get UTC
+ *   3012345, 2021-05-03 11:02:03, -35998
+ *
+ * One calibration gives a fixed offset. Two or more and we can see the clock drifting, so the offset at any moment is worked out on the
+ * straight line through the two calibrations either side of it (or the nearest two, before the first or after the last).
+ */
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const ZONE_STEP: i64 = 900;                                                    // Every UTC offset in use is a whole number of quarter hours
+const DOUBTFUL_ERROR: i64 = 360;                                               // A clock this far out may really be on the next zone over
+
+// Define Structures
+
+#[derive(Debug, Clone)]
+pub struct Calibration
+  {
+    pub camera_time: NaiveDateTime,                                            // What the camera's clock read
+    pub offset: i64,                                                           // Seconds to add to it to get UTC
+  }
+
+#[derive(Default)]
+pub struct ClockProfiles
+  {
+    bodies: BTreeMap<String, Vec<Calibration>>,                                // Calibrations for each serial number, sorted by camera time
+  }
+
+
+impl ClockProfiles
+{
+  /** load
+    fn load(file: &Path) -> Result<ClockProfiles, String>
+      file: &Path = path to the calibration file
+
+    Reads the calibrations. A file which doesn't exist yet just means we have no calibrations.
+  **/
+  pub fn load(file: &Path) -> Result<ClockProfiles, String>
+  {
+    let mut profiles = ClockProfiles::default();
+
+    if !file.exists()
+      {
+        return Ok(profiles);
+      }
+
+    let text = fs::read_to_string(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+
+    for (number, line) in text.lines().enumerate()
+      {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#')
+          {
+            continue;
+          }
+
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        let calibration = match fields.as_slice()
+          {
+            [_, time, offset] => NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok()
+                                   .zip(offset.parse::<i64>().ok())
+                                   .map(|(camera_time, offset)| Calibration { camera_time, offset }),
+            _ => None,
+          };
+
+        match calibration
+          {
+            Some(calibration) => profiles.add(fields[0], calibration),
+            None => return Err(format!("Line {} of {} is not \"serial, camera time, offset\"", number + 1, file.display())),
+          }
+      }
+
+    Ok(profiles)
+  }
+
+
+  /** save
+    fn save(&self, file: &Path) -> Result<(), String>
+
+    Writes all of the calibrations back out.
+  **/
+  pub fn save(&self, file: &Path) -> Result<(), String>
+  {
+    let mut text = String::from("# serial, camera time, seconds to add to get UTC\n");
+
+    for (serial, calibrations) in &self.bodies
+      {
+        for calibration in calibrations
+          {
+            text.push_str(&format!("{}, {}, {}\n", serial, calibration.camera_time.format(TIME_FORMAT), calibration.offset));
+          }
+      }
+
+    fs::write(file, text).map_err(|e| format!("Could not write {}: {}", file.display(), e))
+  }
+
+
+  /** add
+    fn add(&mut self, serial: &str, calibration: Calibration)
+
+    Adds a calibration for a body. A second calibration at the same camera time replaces the first.
+  **/
+  pub fn add(&mut self, serial: &str, calibration: Calibration)
+  {
+    let calibrations = self.bodies.entry(serial.to_string()).or_default();
+
+    calibrations.retain(|c| c.camera_time != calibration.camera_time);
+    calibrations.push(calibration);
+    calibrations.sort_by_key(|c| c.camera_time);
+  }
+
+
+  /** offset_at
+    fn offset_at(&self, serial: &str, camera_time: NaiveDateTime) -> Option<i64>
+      serial: &str = the body's serial number
+      camera_time: NaiveDateTime = what the camera's clock read when the photo was taken
+
+    Returns the seconds to add to the camera's clock to get UTC at a given moment, or None if the body has never been calibrated.
+  **/
+  pub fn offset_at(&self, serial: &str, camera_time: NaiveDateTime) -> Option<i64>
+  {
+    let calibrations = self.bodies.get(serial)?;
+
+    if calibrations.len() < 2
+      {
+        return calibrations.first().map(|c| c.offset);
+      }
+
+    let idx = calibrations.partition_point(|c| c.camera_time < camera_time).clamp(1, calibrations.len() - 1);
+    let (before, after) = (&calibrations[idx - 1], &calibrations[idx]);
+    Some(before.offset + (seconds_between(before.camera_time, camera_time) * rate_between(before, after)).round() as i64)
+  }
+
+
+  /** drift
+    fn drift(&self, serial: &str) -> Option<f64>
+
+    Returns how many seconds a day the body's clock has been drifting, over its two most recent calibrations.
+  **/
+  pub fn drift(&self, serial: &str) -> Option<f64>
+  {
+    let calibrations = self.bodies.get(serial)?;
+
+    if calibrations.len() < 2
+      {
+        return None;
+      }
+
+    Some(-rate_between(&calibrations[calibrations.len() - 2], &calibrations[calibrations.len() - 1]) * 86400.0)
+  }
+}
+
+
+/** clock_error
+  fn clock_error(offset: i64) -> i64
+    offset: i64 = seconds to add to the camera's clock to get UTC, from offset_at
+
+  Splits the zone the camera was on out of a calibration and returns the rest, the seconds to add to the clock to put it right on its own
+  zone. Zones are whole quarter hours apart, so this holds for any clock less than seven and a half minutes out.
+**/
+pub fn clock_error(offset: i64) -> i64
+{
+  offset - (offset as f64 / ZONE_STEP as f64).round() as i64 * ZONE_STEP
+}
+
+
+/** clock_error_is_doubtful
+  fn clock_error_is_doubtful(error: i64) -> bool
+    error: i64 = seconds the clock is out on its own zone, from clock_error
+
+  True when the clock is so far out that it could as well be the other way out on the neighbouring zone, and clock_error may have picked
+  the wrong one.
+**/
+pub fn clock_error_is_doubtful(error: i64) -> bool
+{
+  error.abs() >= DOUBTFUL_ERROR
+}
+
+
+/** rate_between
+  fn rate_between(before: &Calibration, after: &Calibration) -> f64
+
+  Returns how much the offset changes for each second of camera time between two calibrations.
+**/
+fn rate_between(before: &Calibration, after: &Calibration) -> f64
+{
+  (after.offset - before.offset) as f64 / seconds_between(before.camera_time, after.camera_time)
+}
+
+
+fn seconds_between(from: NaiveDateTime, to: NaiveDateTime) -> f64
+{
+  (to - from).num_seconds() as f64
+}
+
+
+/** default_file
+  fn default_file() -> PathBuf
+
+  Where the calibrations live unless we are told otherwise, .nkscgeosync_clocks in the user's home directory.
+**/
+pub fn default_file() -> PathBuf
+{
+  crate::home_directory().join(".nkscgeosync_clocks")
+}
+
+
+/** parse_reading
+  fn parse_reading(reading: &str, camera_time: NaiveDateTime) -> Result<DateTime<Utc>, String>
+    reading: &str = the time shown on the clock in the photo, [yyyy:mm:dd ]hh:mm:ss with an optional Z or +hh:mm/-hh:mm zone
+    camera_time: NaiveDateTime = the DateTimeOriginal of the photo
+
+  Turns the time read off the clock in the photo into UTC. Without a zone it is taken to already be UTC, as a GPS would show. Without a
+  date, the date nearest the camera's is used, so a photo taken around midnight still works out.
+**/
+pub fn parse_reading(reading: &str, camera_time: NaiveDateTime) -> Result<DateTime<Utc>, String>
+{
+  let reading = reading.trim();
+  let (text, zone) = split_zone(reading).ok_or_else(|| format!("\"{}\" has a zone which isn't Z or +hh:mm", reading))?;
+
+  let local = if let Ok(time) = NaiveTime::parse_from_str(text, "%H:%M:%S")
+    {
+      /*
+       * No date, so take the one which puts it nearest the camera's time
+       */
+      let date = camera_time.date();
+      [date.pred_opt(), Some(date), date.succ_opt()].iter().flatten()
+        .map(|d| d.and_time(time))
+        .min_by_key(|t| (*t - camera_time).num_seconds().abs())
+        .ok_or("the date is out of range")?
+    }
+  else
+    {
+      NaiveDateTime::parse_from_str(&text.replace('-', ":").replace('T', " "), "%Y:%m:%d %H:%M:%S")
+        .map_err(|_| format!("\"{}\" is not a time like 2021:05:03 01:02:03 or 01:02:03", reading))?
+    };
+
+  zone.from_local_datetime(&local).single().map(|t| t.with_timezone(&Utc)).ok_or_else(|| format!("\"{}\" is not a valid time", reading))
+}
+
+
+/** split_zone
+  fn split_zone(reading: &str) -> Option<(&str, FixedOffset)>
+
+  Splits a trailing zone (Z, UTC, or +hh:mm / -hh:mm) off a time.
+**/
+fn split_zone(reading: &str) -> Option<(&str, FixedOffset)>
+{
+  if let Some(text) = reading.strip_suffix('Z').or_else(|| reading.strip_suffix("UTC"))
+    {
+      return Some((text.trim(), FixedOffset::east_opt(0)?));
+    }
+
+  match reading.len().checked_sub(6).and_then(|p| reading.get(p..).map(|zone| (p, zone)))
+    {
+      Some((p, zone)) if (zone.starts_with('+') || zone.starts_with('-')) && zone.as_bytes()[3] == b':' =>
+        {
+          let hours = zone[1..3].parse::<i32>().ok()?;
+          let minutes = zone[4..6].parse::<i32>().ok()?;
+          let seconds = (hours * 3600 + minutes * 60) * if zone.starts_with('-') { -1 } else { 1 };
+
+          Some((reading[..p].trim(), FixedOffset::east_opt(seconds)?))
+        },
+      _ => Some((reading, FixedOffset::east_opt(0)?)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn at(day: u32, time: &str) -> NaiveDateTime
+  {
+    NaiveDateTime::parse_from_str(&format!("2021-05-{:02} {}", day, time), TIME_FORMAT).unwrap()
+  }
+
+  #[test]
+  fn the_zone_is_split_out_of_an_offset()
+  {
+    assert_eq!(clock_error(-35998), 2);                                        // Sydney, 2 s slow
+    assert_eq!(clock_error(-36042), -42);                                      // Sydney, 42 s fast
+    assert_eq!(clock_error(-19800 + 5), 5);                                    // India
+    assert_eq!(clock_error(20700 - 449), -449);                                // Nepal, as far out as can be told
+    assert_eq!(clock_error(0), 0);
+  }
+
+  /*
+   * Past seven and a half minutes the clock looks like it is the other way out on the next zone, so those close to it are doubtful
+   */
+  #[test]
+  fn clock_errors_near_half_a_zone_are_doubtful()
+  {
+    assert_eq!(clock_error(-36000 + 460), -440);
+    assert!(clock_error_is_doubtful(clock_error(-36000 + 460)));
+    assert!(clock_error_is_doubtful(-400));
+    assert!(!clock_error_is_doubtful(clock_error(-36000 + 42)));
+  }
+
+  /*
+   * A clock losing a second a day, calibrated on the 1st, 11th and 21st, with its last calibration showing it put right by 2 s on top
+   */
+  #[test]
+  fn offsets_are_interpolated_between_calibrations()
+  {
+    let mut profiles = ClockProfiles::default();
+
+    profiles.add("3012345", Calibration { camera_time: at(11, "12:00:00"), offset: -35990 });
+    profiles.add("3012345", Calibration { camera_time: at(1, "12:00:00"), offset: -36000 });
+    profiles.add("3012345", Calibration { camera_time: at(21, "12:00:00"), offset: -35978 });
+
+    assert_eq!(profiles.offset_at("3012345", at(1, "12:00:00")), Some(-36000));
+    assert_eq!(profiles.offset_at("3012345", at(6, "12:00:00")), Some(-35995));
+    assert_eq!(profiles.offset_at("3012345", at(16, "12:00:00")), Some(-35984));
+    assert_eq!(profiles.offset_at("3012345", at(1, "00:00:00")), Some(-36001)); // Before the first, on the line through the first two
+    assert_eq!(profiles.offset_at("3012345", at(31, "12:00:00")), Some(-35966)); // After the last, on the line through the last two
+    assert_eq!(profiles.drift("3012345"), Some(-1.2));
+    assert_eq!(profiles.offset_at("3099999", at(6, "12:00:00")), None);
+  }
+
+  #[test]
+  fn one_calibration_is_a_fixed_offset()
+  {
+    let mut profiles = ClockProfiles::default();
+
+    profiles.add("3012345", Calibration { camera_time: at(1, "12:00:00"), offset: -35998 });
+    profiles.add("3012345", Calibration { camera_time: at(1, "12:00:00"), offset: -35999 });
+
+    assert_eq!(profiles.offset_at("3012345", at(30, "12:00:00")), Some(-35999));
+    assert_eq!(profiles.drift("3012345"), None);
+  }
+}
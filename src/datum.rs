@@ -0,0 +1,157 @@
+/*
+ * datum.rs
+ * Converting positions from old map datums to WGS-84.
+ *
+ * Everything NX Studio (and every map it links to) does assumes WGS-84, but a few older receivers, mostly Japanese market ones, record
+ * their positions in a local datum and say so in GPSMapDatum. Tokyo datum positions are out by 400 m or so, ED50 and NAD27 ones by up
+ * to a couple of hundred, so they are worth converting. For the datums we know, the position is turned into earth centred coordinates on
+ * its own ellipsoid, moved with a seven parameter Helmert transformation, and turned back into latitude and longitude on WGS-84.
+ *
+ * The parameters are the usual published ones for each datum as a whole, good to a few metres, which is better than the receivers were.
+ * Datums which are within a metre or two of WGS-84 anyway (NAD83, ETRS89, JGD2000 and the like) are left as they are.
+ */
+
+// Define Structures
+
+struct Datum
+  {
+    names: &'static [&'static str],                                            // What receivers call it, upper case without spaces or punctuation
+    label: &'static str,                                                       // What we call it
+    a: f64,                                                                    // Semi-major axis of its ellipsoid, metres
+    inverse_flattening: f64,
+    translation: [f64; 3],                                                     // Metres, to WGS-84
+    rotation: [f64; 3],                                                        // Arc seconds, position vector convention
+    scale: f64,                                                                // Parts per million
+  }
+
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_INVERSE_FLATTENING: f64 = 298.257_223_563;
+
+const SAME_AS_WGS84: &[&str] = &["WGS84", "WGS1984", "ITRF", "GRS80", "NAD83", "ETRS89", "JGD2000", "JGD2011", "GDA94", "GDA2020", "NZGD2000"];
+
+const DATUMS: &[Datum] =
+  &[
+    Datum { names: &["TOKYO", "TOKYODATUM", "TOKYO1918"], label: "Tokyo", a: 6_377_397.155, inverse_flattening: 299.152_812_8,
+            translation: [-146.414, 507.337, 680.507], rotation: [0.0, 0.0, 0.0], scale: 0.0 },
+    Datum { names: &["ED50", "EUROPEAN1950", "EUROPEANDATUM1950"], label: "ED50", a: 6_378_388.0, inverse_flattening: 297.0,
+            translation: [-87.0, -98.0, -121.0], rotation: [0.0, 0.0, 0.0], scale: 0.0 },
+    Datum { names: &["NAD27", "NORTHAMERICAN1927", "NORTHAMERICANDATUM1927"], label: "NAD27", a: 6_378_206.4, inverse_flattening: 294.978_698_2,
+            translation: [-8.0, 160.0, 176.0], rotation: [0.0, 0.0, 0.0], scale: 0.0 },
+  ];
+
+
+/** to_wgs84
+  fn to_wgs84(datum: &str, latitude: f64, longitude: f64) -> Result<Option<(f64, f64, &'static str)>, String>
+    datum: &str = the GPSMapDatum the position came with
+    latitude, longitude = the position in signed decimal degrees, on that datum
+
+  Converts a position to WGS-84. Returns None if it is already WGS-84 (or as good as), which includes having no datum at all, since that
+  is what every receiver of the last twenty years means. Otherwise returns the converted position and the name of the datum it was
+  converted from. A datum we don't know is an error, rather than a position we quietly pass off as WGS-84.
+**/
+pub fn to_wgs84(datum: &str, latitude: f64, longitude: f64) -> Result<Option<(f64, f64, &'static str)>, String>
+{
+  let name: String = datum.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_uppercase();
+
+  if name.is_empty() || SAME_AS_WGS84.iter().any(|same| name.starts_with(same))
+    {
+      return Ok(None);
+    }
+
+  let datum = DATUMS.iter().find(|d| d.names.contains(&name.as_str())).ok_or_else(|| format!("unknown map datum \"{}\"", datum.trim()))?;
+
+  /*
+   * Ellipsoidal heights don't come into it: the altitudes we carry are above sea level, which moving datum doesn't change, and the
+   * few hundred metres between sea level and the ellipsoid move the latitude and longitude by well under a millimetre.
+   */
+  let [x, y, z] = to_cartesian(latitude, longitude, datum.a, datum.inverse_flattening);
+  let [rx, ry, rz] = datum.rotation.map(|r| (r / 3600.0).to_radians());
+  let [tx, ty, tz] = datum.translation;
+  let scale = 1.0 + datum.scale / 1e6;
+
+  let moved = [tx + scale * (x - rz * y + ry * z),
+               ty + scale * (rz * x + y - rx * z),
+               tz + scale * (-ry * x + rx * y + z)];
+  let (latitude, longitude) = to_geodetic(moved, WGS84_A, WGS84_INVERSE_FLATTENING);
+
+  Ok(Some((latitude, longitude, datum.label)))
+}
+
+
+/** to_cartesian
+  fn to_cartesian(latitude: f64, longitude: f64, a: f64, inverse_flattening: f64) -> [f64; 3]
+
+  Turns a position on the surface of an ellipsoid into earth centred, earth fixed coordinates in metres.
+**/
+fn to_cartesian(latitude: f64, longitude: f64, a: f64, inverse_flattening: f64) -> [f64; 3]
+{
+  let e2 = (2.0 - 1.0 / inverse_flattening) / inverse_flattening;
+  let (phi, lambda) = (latitude.to_radians(), longitude.to_radians());
+  let n = a / (1.0 - e2 * phi.sin().powi(2)).sqrt();
+
+  [n * phi.cos() * lambda.cos(), n * phi.cos() * lambda.sin(), n * (1.0 - e2) * phi.sin()]
+}
+
+
+/** to_geodetic
+  fn to_geodetic(point: [f64; 3], a: f64, inverse_flattening: f64) -> (f64, f64)
+
+  The reverse of to_cartesian, iterating on the latitude, which is down to nothing after a handful of goes.
+**/
+fn to_geodetic(point: [f64; 3], a: f64, inverse_flattening: f64) -> (f64, f64)
+{
+  let [x, y, z] = point;
+  let e2 = (2.0 - 1.0 / inverse_flattening) / inverse_flattening;
+  let p = (x * x + y * y).sqrt();
+  let mut phi = z.atan2(p * (1.0 - e2));
+
+  for _ in 0..10
+    {
+      let n = a / (1.0 - e2 * phi.sin().powi(2)).sqrt();
+      let height = p / phi.cos() - n;
+      phi = z.atan2(p * (1.0 - e2 * n / (n + height)));
+    }
+
+  (phi.to_degrees(), y.atan2(x).to_degrees())
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+  use crate::geo::distance_between;
+
+  fn dms(degrees: f64, minutes: f64, seconds: f64) -> f64
+  {
+    degrees + minutes / 60.0 + seconds / 3600.0
+  }
+
+  /*
+   * The Japanese datum origin in Azabu, as the Geospatial Information Authority of Japan gives it on the Tokyo datum and on JGD2000,
+   * which is within a few centimetres of WGS-84. The single set of parameters for the whole country should get within a few metres.
+   */
+  #[test]
+  fn tokyo_datum_origin()
+  {
+    let (latitude, longitude, label) = to_wgs84("TOKYO", dms(35.0, 39.0, 17.5148), dms(139.0, 44.0, 40.5020)).unwrap().unwrap();
+
+    assert_eq!(label, "Tokyo");
+    assert!(distance_between(latitude, longitude, dms(35.0, 39.0, 29.1572), dms(139.0, 44.0, 28.8759)) < 5.0);
+  }
+
+  #[test]
+  fn wgs84_and_its_equivalents_are_left_alone()
+  {
+    for datum in ["", "WGS-84", "wgs 84", "JGD2000", "NAD83(CSRS)"]
+      {
+        assert!(to_wgs84(datum, 35.0, 139.0).unwrap().is_none(), "{}", datum);
+      }
+  }
+
+  #[test]
+  fn unknown_datum_is_an_error()
+  {
+    assert!(to_wgs84("Pulkovo 1942", 55.0, 37.0).is_err());
+  }
+}
@@ -0,0 +1,428 @@
+/*
+ * dem.rs
+ * Altitudes from a digital elevation model.
+ *
+ * Plenty of our locations come without an altitude: a position typed in by hand, a KML path, a phone that only logged latitude and
+ * longitude. If we have elevation tiles for the area we can look the ground height up instead. Two kinds of tile are understood:
+ *
+ *   SRTM .hgt files, named after their south west corner (e.g. S34E151.hgt), a square grid of big endian 16 bit heights, 1201 a side for
+ *   3" data or 3601 for 1", running from the north west corner. -32768 marks a void.
+ *
+ *   GeoTIFFs in latitude and longitude, uncompressed, with one band of 16 or 32 bit integers or 32 bit floats, e.g. the CGIAR SRTM or
+ *   Copernicus tiles once decompressed with gdal_translate. Projected DEMs are not supported.
+ *
+ * The tiles in the directory are only catalogued up front; each is read the first time a position falls on it, and kept after that.
+ * The height is interpolated bilinearly from the four grid points around the position.
+ */
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use exif::{Context, In, Tag, Value};
+
+const HGT_VOID: i16 = -32768;
+
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_TILE_WIDTH: u16 = 322;
+const TAG_TILE_LENGTH: u16 = 323;
+const TAG_TILE_OFFSETS: u16 = 324;
+const TAG_SAMPLE_FORMAT: u16 = 339;
+const TAG_MODEL_PIXEL_SCALE: u16 = 33550;
+const TAG_MODEL_TIEPOINT: u16 = 33922;
+const TAG_GEO_KEY_DIRECTORY: u16 = 34735;
+const TAG_GDAL_NODATA: u16 = 42113;
+
+const KEY_MODEL_TYPE: u16 = 1024;
+const KEY_RASTER_TYPE: u16 = 1025;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+
+// Define Structures
+
+struct Grid
+  {
+    west: f64,                                                                 // Longitude of the centre of the first column
+    north: f64,                                                                // Latitude of the centre of the first row
+    x_step: f64,                                                               // Degrees of longitude between columns
+    y_step: f64,                                                               // Degrees of latitude between rows, rows run south
+    width: usize,
+    height: usize,
+    samples: Vec<f32>,                                                         // Row by row from the north west, NaN for a void
+  }
+
+#[derive(Default)]
+pub struct Dem
+  {
+    hgt: HashMap<(i32, i32), PathBuf>,                                         // SRTM tiles by the latitude and longitude of their south west corner
+    geotiffs: Vec<(PathBuf, Grid)>,                                            // GeoTIFF tiles, with their geometry but not their samples
+    loaded: RefCell<HashMap<PathBuf, Result<Grid, String>>>,                   // The tiles we have read, or why we couldn't
+  }
+
+
+impl Dem
+{
+  /** load
+    fn load(directory: &Path) -> Result<Dem, String>
+      directory: &Path = the directory holding the DEM tiles
+
+    Catalogues the .hgt and GeoTIFF tiles in a directory. GeoTIFFs we can't use are reported and skipped.
+  **/
+  pub fn load(directory: &Path) -> Result<Dem, String>
+  {
+    let entries = fs::read_dir(directory).map_err(|e| format!("Could not open {}: {}", directory.display(), e))?;
+    let mut dem = Dem::default();
+
+    for path in entries.flatten().map(|e| e.path())
+      {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+        if extension == "hgt"
+          {
+            match path.file_stem().and_then(|s| hgt_corner(&s.to_string_lossy()))
+              {
+                Some(corner) => { dem.hgt.insert(corner, path); },
+                None => println!("Skipping {}: SRTM tiles should be named after their south west corner, e.g. S34E151.hgt", path.display()),
+              }
+          }
+        else if extension == "tif" || extension == "tiff"
+          {
+            match read_geotiff(&path, false)
+              {
+                Ok(grid) => dem.geotiffs.push((path, grid)),
+                Err(e) => println!("Skipping {}: {}", path.display(), e),
+              }
+          }
+      }
+
+    if dem.hgt.is_empty() && dem.geotiffs.is_empty()
+      {
+        return Err(format!("{} has no .hgt or GeoTIFF elevation tiles in it", directory.display()));
+      }
+
+    Ok(dem)
+  }
+
+
+  /** len
+    fn len(&self) -> usize
+
+    Returns how many tiles we have.
+  **/
+  pub fn len(&self) -> usize
+  {
+    self.hgt.len() + self.geotiffs.len()
+  }
+
+
+  /** elevation_at
+    fn elevation_at(&self, latitude: f64, longitude: f64) -> Result<(f64, String), String>
+      latitude, longitude = position in signed decimal degrees
+
+    Returns the height of the ground in metres at a position, and the name of the tile it came from.
+  **/
+  pub fn elevation_at(&self, latitude: f64, longitude: f64) -> Result<(f64, String), String>
+  {
+    let mut candidates: Vec<&Path> = Vec::new();
+
+    if let Some(path) = self.hgt.get(&(latitude.floor() as i32, longitude.floor() as i32))
+      {
+        candidates.push(path);
+      }
+    candidates.extend(self.geotiffs.iter().filter(|(_, grid)| grid.covers(latitude, longitude)).map(|(path, _)| path.as_path()));
+
+    if candidates.is_empty()
+      {
+        return Err(format!("no elevation tile covers {:.6} {:.6}", latitude, longitude));
+      }
+
+    let mut loaded = self.loaded.borrow_mut();
+    let mut problems = Vec::new();
+
+    for path in candidates
+      {
+        let is_hgt = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("hgt"));
+        let grid = loaded.entry(path.to_path_buf()).or_insert_with(|| if is_hgt { read_hgt(path) } else { read_geotiff(path, true) });
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+
+        match grid
+          {
+            Ok(grid) => match grid.elevation_at(latitude, longitude)
+              {
+                Some(elevation) => return Ok((elevation, name)),
+                None => problems.push(format!("{} has a void there", name)),
+              },
+            Err(e) => problems.push(format!("{}: {}", name, e)),
+          }
+      }
+
+    Err(problems.join(", "))
+  }
+}
+
+
+impl Grid
+{
+  /** covers
+    fn covers(&self, latitude: f64, longitude: f64) -> bool
+
+    Returns true if a position falls within the grid, counting half a cell beyond the outside grid points.
+  **/
+  fn covers(&self, latitude: f64, longitude: f64) -> bool
+  {
+    let (x, y) = self.cell_of(latitude, longitude);
+
+    x >= -0.5 && x <= self.width as f64 - 0.5 && y >= -0.5 && y <= self.height as f64 - 0.5
+  }
+
+
+  fn cell_of(&self, latitude: f64, longitude: f64) -> (f64, f64)
+  {
+    ((longitude - self.west) / self.x_step, (self.north - latitude) / self.y_step)
+  }
+
+
+  /** elevation_at
+    fn elevation_at(&self, latitude: f64, longitude: f64) -> Option<f64>
+
+    Interpolates the height bilinearly between the four grid points around a position. Voids are left out and the weights of the rest
+    scaled up to make up for them, so we only give up if all four are voids.
+  **/
+  fn elevation_at(&self, latitude: f64, longitude: f64) -> Option<f64>
+  {
+    if !self.covers(latitude, longitude) || self.width < 2 || self.height < 2
+      {
+        return None;
+      }
+
+    let (x, y) = self.cell_of(latitude, longitude);
+    let column = (x.floor().max(0.0) as usize).min(self.width - 2);
+    let row = (y.floor().max(0.0) as usize).min(self.height - 2);
+    let fx = (x - column as f64).clamp(0.0, 1.0);
+    let fy = (y - row as f64).clamp(0.0, 1.0);
+
+    let mut total = 0.0;
+    let mut weights = 0.0;
+
+    for (dx, dy, weight) in [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)]
+      {
+        let sample = self.samples[(row + dy) * self.width + column + dx];
+
+        if !sample.is_nan() && weight > 0.0
+          {
+            total += sample as f64 * weight;
+            weights += weight;
+          }
+      }
+
+    if weights > 0.0 { Some(total / weights) } else { None }
+  }
+}
+
+
+/** hgt_corner
+  fn hgt_corner(name: &str) -> Option<(i32, i32)>
+
+  Reads the south west corner of an SRTM tile from its name, e.g. S34E151 is (-34, 151).
+**/
+fn hgt_corner(name: &str) -> Option<(i32, i32)>
+{
+  let name = name.to_uppercase();
+  let split = name.find(['E', 'W'])?;
+  let (latitude, longitude) = name.split_at(split);
+
+  let signed = |text: &str, negative: char| -> Option<i32>
+    {
+      let value = text.get(1..)?.parse::<i32>().ok()?;
+      Some(if text.starts_with(negative) { -value } else { value })
+    };
+
+  if !latitude.starts_with(['N', 'S'])
+    {
+      return None;
+    }
+
+  Some((signed(latitude, 'S')?, signed(longitude, 'W')?))
+}
+
+
+/** read_hgt
+  fn read_hgt(file: &Path) -> Result<Grid, String>
+
+  Reads an SRTM tile. Its size tells us its resolution, and the outside rows and columns sit on the whole degrees.
+**/
+fn read_hgt(file: &Path) -> Result<Grid, String>
+{
+  let (south, west) = file.file_stem().and_then(|s| hgt_corner(&s.to_string_lossy())).ok_or("not named after its corner")?;
+  let data = fs::read(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+  let size = ((data.len() / 2) as f64).sqrt() as usize;
+
+  if size < 2 || size * size * 2 != data.len()
+    {
+      return Err("is not a square grid of 16 bit heights".to_string());
+    }
+
+  let samples = data.chunks_exact(2)
+                    .map(|b| i16::from_be_bytes([b[0], b[1]]))
+                    .map(|h| if h == HGT_VOID { f32::NAN } else { h as f32 })
+                    .collect();
+  let step = 1.0 / (size - 1) as f64;
+
+  Ok(Grid { west: west as f64, north: south as f64 + 1.0, x_step: step, y_step: step, width: size, height: size, samples })
+}
+
+
+/** read_geotiff
+  fn read_geotiff(file: &Path, with_samples: bool) -> Result<Grid, String>
+    file: &Path = path to the GeoTIFF
+    with_samples: bool = read the heights as well as the geometry
+
+  Reads the geometry of a GeoTIFF DEM from its tie point and pixel scale, and if asked, its heights from its strips or tiles. Without
+  a raster type in the GeoKey directory, the tie point is taken to be the corner of the first pixel, as the GeoTIFF spec says.
+**/
+fn read_geotiff(file: &Path, with_samples: bool) -> Result<Grid, String>
+{
+  let data = fs::read(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+  let tiff = exif::Reader::new().read_raw(data).map_err(|e| format!("is not a TIFF file ({})", e))?;
+
+  let field = |tag: u16| tiff.get_field(Tag(Context::Tiff, tag), In::PRIMARY).map(|f| &f.value);
+  let number = |tag: u16| field(tag).and_then(|v| v.get_uint(0));
+  let numbers = |tag: u16| -> Vec<u32>
+    {
+      match field(tag)
+        {
+          Some(Value::Short(values)) => values.iter().map(|v| *v as u32).collect(),
+          Some(Value::Long(values)) => values.clone(),
+          _ => Vec::new(),
+        }
+    };
+  let doubles = |tag: u16| -> Vec<f64>
+    {
+      match field(tag) { Some(Value::Double(values)) => values.clone(), _ => Vec::new() }
+    };
+
+  let scale = doubles(TAG_MODEL_PIXEL_SCALE);
+  let tiepoint = doubles(TAG_MODEL_TIEPOINT);
+  if scale.len() < 2 || tiepoint.len() < 6
+    {
+      return Err("has no GeoTIFF tie point and pixel scale".to_string());
+    }
+
+  let geo_keys = numbers(TAG_GEO_KEY_DIRECTORY);
+  let geo_key = |key: u16| geo_keys.get(4..).unwrap_or(&[]).chunks_exact(4).find(|k| k[0] == key as u32 && k[1] == 0).map(|k| k[3] as u16);
+  if geo_key(KEY_MODEL_TYPE).is_some_and(|t| t != MODEL_TYPE_GEOGRAPHIC)
+    {
+      return Err("is projected, only DEMs in latitude and longitude are supported".to_string());
+    }
+
+  let width = number(TAG_IMAGE_WIDTH).ok_or("has no width")? as usize;
+  let height = number(TAG_IMAGE_LENGTH).ok_or("has no height")? as usize;
+  let half = if geo_key(KEY_RASTER_TYPE) == Some(RASTER_PIXEL_IS_POINT) { 0.0 } else { 0.5 };
+  let mut grid = Grid
+    {
+      west: tiepoint[3] + (half - tiepoint[0]) * scale[0],
+      north: tiepoint[4] - (half - tiepoint[1]) * scale[1],
+      x_step: scale[0],
+      y_step: scale[1],
+      width,
+      height,
+      samples: Vec::new(),
+    };
+
+  let compression = number(TAG_COMPRESSION).unwrap_or(1);
+  let samples_per_pixel = number(TAG_SAMPLES_PER_PIXEL).unwrap_or(1);
+  let bits = number(TAG_BITS_PER_SAMPLE).unwrap_or(1);
+  let format = number(TAG_SAMPLE_FORMAT).unwrap_or(1);
+
+  if compression != 1
+    {
+      return Err("is compressed, decompress it first, e.g. gdal_translate -co COMPRESS=NONE".to_string());
+    }
+  if samples_per_pixel != 1 || !matches!((bits, format), (16, 1) | (16, 2) | (32, 1) | (32, 2) | (32, 3))
+    {
+      return Err(format!("has {} band(s) of {} bit samples of format {}, we need one band of 16 or 32 bit heights", samples_per_pixel, bits, format));
+    }
+
+  if !with_samples
+    {
+      return Ok(grid);
+    }
+
+  /*
+   * Strips are just tiles the width of the image
+   */
+  let (tile_width, tile_height, offsets) = match number(TAG_TILE_WIDTH).zip(number(TAG_TILE_LENGTH))
+    {
+      Some((tile_width, tile_height)) => (tile_width as usize, tile_height as usize, numbers(TAG_TILE_OFFSETS)),
+      None => (width, number(TAG_ROWS_PER_STRIP).unwrap_or(height as u32) as usize, numbers(TAG_STRIP_OFFSETS)),
+    };
+  let nodata = match field(TAG_GDAL_NODATA)
+    {
+      Some(Value::Ascii(text)) => text.first().and_then(|t| String::from_utf8_lossy(t).trim().parse::<f32>().ok()),
+      _ => None,
+    };
+
+  let buffer = tiff.buf();
+  let little_endian = tiff.little_endian();
+  let bytes_per_sample = bits as usize / 8;
+  let tiles_across = width.div_ceil(tile_width);
+  grid.samples = vec![f32::NAN; width * height];
+
+  for (n, offset) in offsets.iter().enumerate()
+    {
+      let (tile_x, tile_y) = ((n % tiles_across) * tile_width, (n / tiles_across) * tile_height);
+
+      for row in 0..tile_height.min(height.saturating_sub(tile_y))
+        {
+          for column in 0..tile_width.min(width.saturating_sub(tile_x))
+            {
+              let at = *offset as usize + (row * tile_width + column) * bytes_per_sample;
+              let bytes = buffer.get(at..at + bytes_per_sample).ok_or("its samples run past the end of the file")?;
+              let value = sample(bytes, bits, format, little_endian);
+
+              if Some(value) != nodata
+                {
+                  grid.samples[(tile_y + row) * width + tile_x + column] = value;
+                }
+            }
+        }
+    }
+
+  Ok(grid)
+}
+
+
+/** sample
+  fn sample(bytes: &[u8], bits: u32, format: u32, little_endian: bool) -> f32
+
+  Decodes one height: format 1 is unsigned, 2 signed and 3 floating point.
+**/
+fn sample(bytes: &[u8], bits: u32, format: u32, little_endian: bool) -> f32
+{
+  match (bits, format)
+    {
+      (16, _) =>
+        {
+          let raw = if little_endian { u16::from_le_bytes([bytes[0], bytes[1]]) } else { u16::from_be_bytes([bytes[0], bytes[1]]) };
+          if format == 2 { raw as i16 as f32 } else { raw as f32 }
+        },
+      _ =>
+        {
+          let raw = [bytes[0], bytes[1], bytes[2], bytes[3]];
+          let raw = if little_endian { u32::from_le_bytes(raw) } else { u32::from_be_bytes(raw) };
+          match format
+            {
+              3 => f32::from_bits(raw),
+              2 => raw as i32 as f32,
+              _ => raw as f32,
+            }
+        },
+    }
+}
@@ -0,0 +1,275 @@
+/*
+ * geo.rs
+ * A few bits of geodesy. Nothing fancy, the earth is treated as a sphere which is plenty good enough for telling whether two
+ * photos were taken in the same place.
+ */
+
+const EARTH_RADIUS: f64 = 6_371_008.8;                                         // Mean radius of the earth in metres
+
+
+/** distance_between
+  fn distance_between(latitude1: f64, longitude1: f64, latitude2: f64, longitude2: f64) -> f64
+    latitude1, longitude1 = first position in signed decimal degrees
+    latitude2, longitude2 = second position in signed decimal degrees
+
+  Returns the great-circle distance between two positions in metres, using the haversine formula.
+**/
+pub fn distance_between(latitude1: f64, longitude1: f64, latitude2: f64, longitude2: f64) -> f64
+{
+  let phi1 = latitude1.to_radians();
+  let phi2 = latitude2.to_radians();
+  let delta_phi = (latitude2 - latitude1).to_radians();
+  let delta_lambda = (longitude2 - longitude1).to_radians();
+
+  let a = (delta_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (delta_lambda / 2.0).sin().powi(2);
+
+  2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+
+/** parse_position
+  fn parse_position(text: &str) -> Result<(f64, f64, Option<f64>), String>
+    text: &str = a position as typed on the command line
+
+  Parses a latitude, longitude and optional altitude in metres, written pretty much any way people write them:
+
+    -33.8568, 151.2153
+    -33.8568 151.2153 12.5
+    33.8568S 151.2153E 12.5m
+    48°51'29.6"N 2°17'40.2"E
+    N 48 51 29.6, E 2 17 40.2, 35
+
+  Each of the latitude and longitude can be degrees, degrees and minutes, or degrees, minutes and seconds. Without N/S/E/W or commas to
+  say where one stops and the next starts, we go by the count of numbers. N/S/E/W only count as a hemisphere on their own, next to a
+  number, so words like metres or elevation can follow the altitude.
+**/
+pub fn parse_position(text: &str) -> Result<(f64, f64, Option<f64>), String>
+{
+  #[derive(PartialEq)]
+  enum Token { Number(f64), Word(String), Hemisphere(char), Comma }
+
+  /*
+   * Break it into numbers, words and commas, anything else (degree signs, quotes) only separates them
+   */
+  let mut tokens = Vec::new();
+  let mut number = String::new();
+  let mut word = String::new();
+
+  for c in text.chars().chain(std::iter::once(' '))
+    {
+      if c.is_ascii_alphabetic()
+        {
+          word.push(c.to_ascii_uppercase());
+        }
+      else if !word.is_empty()
+        {
+          tokens.push(Token::Word(std::mem::take(&mut word)));
+        }
+
+      if c.is_ascii_digit() || c == '.' || ((c == '-' || c == '+') && number.is_empty())
+        {
+          number.push(c);
+          continue;
+        }
+
+      if !number.is_empty()
+        {
+          tokens.push(Token::Number(number.parse::<f64>().map_err(|_| format!("\"{}\" is not a number", number))?));
+          number.clear();
+        }
+
+      if c == ',' || c == ';'
+        {
+          tokens.push(Token::Comma);
+        }
+    }
+
+  /*
+   * A lone N, S, E or W next to a number is a hemisphere, and any other word (a trailing m, metres, elevation) is dropped
+   */
+  let next_to_a_number: Vec<bool> = (0..tokens.len())
+    .map(|i| [i.checked_sub(1), Some(i + 1)].iter().flatten().any(|&j| matches!(tokens.get(j), Some(Token::Number(_)))))
+    .collect();
+  let tokens: Vec<Token> = tokens.into_iter().zip(next_to_a_number)
+    .filter_map(|(token, next_to_a_number)| match token
+      {
+        Token::Word(word) if next_to_a_number && ["N", "S", "E", "W"].contains(&word.as_str()) => word.chars().next().map(Token::Hemisphere),
+        Token::Word(_) => None,
+        token => Some(token),
+      })
+    .collect();
+
+  /*
+   * Split it into groups of numbers, each with its hemisphere if it has one
+   */
+  let mut groups: Vec<(Vec<f64>, Option<char>)> = Vec::new();
+  let has_hemispheres = tokens.iter().any(|t| matches!(t, Token::Hemisphere(_)));
+  let prefixed = matches!(tokens.first(), Some(Token::Hemisphere(_)));
+  let mut current: (Vec<f64>, Option<char>) = (Vec::new(), None);
+
+  for token in tokens
+    {
+      match token
+        {
+          Token::Number(n) => current.0.push(n),
+          Token::Hemisphere(h) if prefixed =>
+            {
+              if !current.0.is_empty() || current.1.is_some() { groups.push(current); }
+              current = (Vec::new(), Some(h));
+            },
+          Token::Hemisphere(h) =>
+            {
+              current.1 = Some(h);
+              groups.push(current);
+              current = (Vec::new(), None);
+            },
+          Token::Comma if !current.0.is_empty() && (!has_hemispheres || prefixed) =>
+            {
+              groups.push(current);
+              current = (Vec::new(), None);
+            },
+          Token::Comma | Token::Word(_) => {},
+        }
+    }
+
+  let mut altitude: Option<f64> = None;
+
+  if has_hemispheres || !groups.is_empty()
+    {
+      /*
+       * With hemispheres or commas, whatever follows the latitude and longitude is the altitude
+       */
+      if !current.0.is_empty() || current.1.is_some()
+        {
+          groups.push(current);
+        }
+      if groups.len() == 3 && groups[2].1.is_none() && groups[2].0.len() == 1
+        {
+          altitude = Some(groups[2].0[0]);
+          groups.truncate(2);
+        }
+    }
+  else
+    {
+      let numbers = current.0;
+      let per = match numbers.len()
+        {
+          2 | 3 => 1,
+          4 | 5 => 2,
+          6 | 7 => 3,
+          _ => return Err(format!("\"{}\" doesn't look like a latitude and longitude", text)),
+        };
+      if numbers.len() % 2 == 1
+        {
+          altitude = numbers.last().copied();
+        }
+      groups.push((numbers[0..per].to_vec(), None));
+      groups.push((numbers[per..per * 2].to_vec(), None));
+    }
+
+  if groups.len() != 2
+    {
+      return Err(format!("\"{}\" doesn't look like a latitude and longitude", text));
+    }
+
+  /*
+   * Longitude first is fine, as long as the hemispheres say so
+   */
+  if matches!(groups[0].1, Some('E') | Some('W')) || matches!(groups[1].1, Some('N') | Some('S'))
+    {
+      groups.swap(0, 1);
+    }
+
+  let latitude = group_to_degrees(&groups[0].0, groups[0].1, 'S').ok_or_else(|| format!("\"{}\" has a latitude I can't read", text))?;
+  let longitude = group_to_degrees(&groups[1].0, groups[1].1, 'W').ok_or_else(|| format!("\"{}\" has a longitude I can't read", text))?;
+
+  if latitude.abs() > 90.0 || longitude.abs() > 180.0
+    {
+      return Err(format!("\"{}\" is off the edge of the world", text));
+    }
+
+  Ok((latitude, longitude, altitude))
+}
+
+
+/** group_to_degrees
+  fn group_to_degrees(numbers: &[f64], hemisphere: Option<char>, negative: char) -> Option<f64>
+
+  Turns degrees [minutes [seconds]] into signed decimal degrees, negative in the given hemisphere.
+**/
+fn group_to_degrees(numbers: &[f64], hemisphere: Option<char>, negative: char) -> Option<f64>
+{
+  if numbers.is_empty() || numbers.len() > 3 || numbers[1..].iter().any(|n| *n < 0.0 || *n >= 60.0)
+    {
+      return None;
+    }
+
+  let magnitude = numbers[0].abs() + numbers.get(1).unwrap_or(&0.0) / 60.0 + numbers.get(2).unwrap_or(&0.0) / 3600.0;
+  let sign = if numbers[0] < 0.0 || (numbers[0] == 0.0 && numbers[0].is_sign_negative()) || hemisphere == Some(negative) { -1.0 } else { 1.0 };
+
+  Some(sign * magnitude)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn assert_position(text: &str, latitude: f64, longitude: f64, altitude: Option<f64>)
+  {
+    let position = parse_position(text).unwrap_or_else(|e| panic!("{}", e));
+
+    assert!((position.0 - latitude).abs() < 1e-6 && (position.1 - longitude).abs() < 1e-6, "{} gave {:?}", text, position);
+    assert_eq!(position.2, altitude, "{} gave {:?}", text, position);
+  }
+
+  #[test]
+  fn signed_degrees_with_a_comma()
+  {
+    assert_position("-33.8568, 151.2153", -33.8568, 151.2153, None);
+  }
+
+  #[test]
+  fn signed_degrees_with_an_altitude()
+  {
+    assert_position("-33.8568 151.2153 12.5", -33.8568, 151.2153, Some(12.5));
+  }
+
+  #[test]
+  fn degrees_with_hemispheres_after_them()
+  {
+    assert_position("33.8568S 151.2153E 12.5m", -33.8568, 151.2153, Some(12.5));
+  }
+
+  #[test]
+  fn degrees_minutes_and_seconds()
+  {
+    assert_position("48°51'29.6\"N 2°17'40.2\"E", 48.858222, 2.294500, None);
+  }
+
+  #[test]
+  fn hemispheres_before_the_numbers()
+  {
+    assert_position("N 48 51 29.6, E 2 17 40.2, 35", 48.858222, 2.294500, Some(35.0));
+  }
+
+  /*
+   * Words after the altitude have Ns, Ss, Es and Ws in them, which are not hemispheres
+   */
+  #[test]
+  fn words_after_the_altitude_are_ignored()
+  {
+    assert_position("-33.85 151.21 12 metres", -33.85, 151.21, Some(12.0));
+    assert_position("-33.85, 151.21, 12.5 m elev", -33.85, 151.21, Some(12.5));
+    assert_position("33.85 S 151.21 E 12 metres above sea level", -33.85, 151.21, Some(12.0));
+  }
+
+  #[test]
+  fn nonsense_is_refused()
+  {
+    assert!(parse_position("-33.85").is_err());
+    assert!(parse_position("north of the harbour").is_err());
+    assert!(parse_position("95 151.21").is_err());
+  }
+}
@@ -0,0 +1,382 @@
+/*
+ * geonames.rs
+ * Offline reverse geocoding against a GeoNames dump.
+ *
+ * GeoNames (https://www.geonames.org/) publish their gazetteer as plain tab separated text, one place a line, e.g. cities500.txt or
+ * allCountries.txt. The columns we care about are
+ *
+ *   0 geonameid, 1 name, 2 ASCII name, 3 alternate names, 4 latitude, 5 longitude, 6 feature class, 7 feature code, 8 country code,
+ *   10 admin1 code, 14 population, 15 elevation, 16 DEM elevation
+ *
+ * Only populated places (feature class P) are any use for naming where a photo was taken, so for reverse lookups everything else is dropped
+ * as the file is read. Looking a name up goes the other way, and reads only the lines with that name, whatever they are.
+ * The names of the states and countries are in two more files, admin1CodesASCII.txt and countryInfo.txt, which we pick up from the same
+ * directory if they are there; without them we fall back on the codes.
+ *
+ * The places are put into a grid of one degree cells, so finding the nearest town only means looking at the cells around the photo
+ * rather than at every one of the million or so lines of the bigger dumps.
+ */
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::geo::distance_between;
+
+const MAX_DISTANCE: f64 = 50_000.0;                                            // Any further than this from a town and we're not in it
+const SUBLOCATION_DISTANCE: f64 = 5_000.0;                                     // How close a section of a town has to be to be named
+
+// Define Structures
+
+#[derive(Debug, Clone)]
+pub struct Feature
+  {
+    pub name: String,
+    pub latitude: f64,                                                         // Signed decimal degrees
+    pub longitude: f64,                                                        // Signed decimal degrees
+    pub elevation: Option<f64>,                                                // Metres, the surveyed elevation or failing that the DEM's
+    pub class: String,                                                         // Feature class, e.g. P for a populated place, T for a mountain
+    pub code: String,                                                          // Feature code, e.g. PPL, PPLA, PPLX
+    pub country: String,                                                       // ISO 3166 country code, e.g. AU
+    pub admin1: String,                                                        // State or province code, e.g. 02
+    pub population: u64,
+  }
+
+pub struct PlaceNames
+  {
+    pub city: String,
+    pub state: Option<String>,
+    pub country: Option<String>,
+    pub country_code: String,
+    pub sublocation: Option<String>,
+  }
+
+#[derive(Default)]
+pub struct Gazetteer
+  {
+    features: Vec<Feature>,
+    grid: HashMap<(i32, i32), Vec<usize>>,                                     // Index into features by one degree cell
+    admin1_names: HashMap<String, String>,                                     // "AU.02" => "New South Wales"
+    country_names: HashMap<String, String>,                                    // "AU" => "Australia"
+  }
+
+
+impl Gazetteer
+{
+  /** load
+    fn load(file: &Path) -> Result<Gazetteer, String>
+      file: &Path = path to a GeoNames dump, e.g. cities1000.txt
+
+    Reads the populated places out of a GeoNames dump and indexes them, along with the state and country names if admin1CodesASCII.txt
+    and countryInfo.txt are next to it.
+  **/
+  pub fn load(file: &Path) -> Result<Gazetteer, String>
+  {
+    let mut gazetteer = Gazetteer::read(file, |fields| fields[6] == "P")?;
+
+    if gazetteer.features.is_empty()
+      {
+        return Err(format!("{} has no populated places in it, is it a GeoNames dump?", file.display()));
+      }
+
+    for (i, feature) in gazetteer.features.iter().enumerate()
+      {
+        gazetteer.grid.entry(cell(feature.latitude, feature.longitude)).or_default().push(i);
+      }
+
+    Ok(gazetteer)
+  }
+
+
+  /** search
+    fn search(file: &Path, query: &str) -> Result<Gazetteer, String>
+      file: &Path = path to a GeoNames dump
+      query: &str = the name of a place, optionally followed by a comma and its state, country or country code, e.g. "Paris, FR"
+
+    Looks a place up by name, which can be its GeoNames name, its ASCII name or any of its alternate names, and returns a gazetteer of
+    just the features that match. Unlike reverse lookups, anything with a name will do, mountains and parks as much as towns, since Uluru
+    is as good a place to have taken a photo as Alice Springs. The matches are ranked best first: countries and states last, as a photo
+    "in Mexico" wants the city rather than the middle of the country, then by population, then the kind of feature, then whether it was
+    the main name that matched.
+  **/
+  pub fn search(file: &Path, query: &str) -> Result<Gazetteer, String>
+  {
+    let (name, qualifier) = match query.split_once(',')
+      {
+        Some((name, qualifier)) => (name.trim(), Some(qualifier.trim())),
+        None => (query.trim(), None),
+      };
+    let mut gazetteer = Gazetteer::read(file, |fields| fields[1].eq_ignore_ascii_case(name)
+                                                       || fields[2].eq_ignore_ascii_case(name)
+                                                       || fields[3].split(',').any(|n| n.trim().eq_ignore_ascii_case(name)))?;
+
+    if let Some(qualifier) = qualifier
+      {
+        let features = std::mem::take(&mut gazetteer.features);
+        gazetteer.features = features.into_iter()
+                                     .filter(|f| f.country.eq_ignore_ascii_case(qualifier)
+                                                 || gazetteer.country_name(f).eq_ignore_ascii_case(qualifier)
+                                                 || gazetteer.state_name(f).is_some_and(|s| s.eq_ignore_ascii_case(qualifier)))
+                                     .collect();
+      }
+
+    gazetteer.features.sort_by_key(|f| (f.class == "A", std::cmp::Reverse(f.population), class_rank(&f.class), !f.name.eq_ignore_ascii_case(name)));
+
+    Ok(gazetteer)
+  }
+
+
+  /** read
+    fn read(file: &Path, keep: impl Fn(&[&str]) -> bool) -> Result<Gazetteer, String>
+
+    Reads the lines of a GeoNames dump we want to keep, along with the state and country names if admin1CodesASCII.txt and countryInfo.txt
+    are next to it.
+  **/
+  fn read(file: &Path, keep: impl Fn(&[&str]) -> bool) -> Result<Gazetteer, String>
+  {
+    let bytes = fs::read(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+    let text = String::from_utf8_lossy(&bytes);
+    let mut gazetteer = Gazetteer::default();
+
+    for line in text.lines()
+      {
+        let fields: Vec<&str> = line.split('\t').collect();
+
+        if fields.len() < 17 || !keep(&fields)
+          {
+            continue;
+          }
+
+        let (latitude, longitude) = match (fields[4].parse::<f64>(), fields[5].parse::<f64>())
+          {
+            (Ok(latitude), Ok(longitude)) => (latitude, longitude),
+            _ => continue,
+          };
+
+        gazetteer.features.push(Feature
+          {
+            name: fields[1].to_string(),
+            latitude,
+            longitude,
+            elevation: fields[15].parse::<f64>().ok()
+                                 .or_else(|| fields[16].parse::<f64>().ok().filter(|dem| *dem > -9000.0)),   // -9999 means no data
+            class: fields[6].to_string(),
+            code: fields[7].to_string(),
+            country: fields[8].to_string(),
+            admin1: fields[10].to_string(),
+            population: fields[14].parse::<u64>().unwrap_or(0),
+          });
+      }
+
+    let directory = file.parent().unwrap_or(Path::new("."));
+
+    if let Ok(text) = fs::read_to_string(directory.join("admin1CodesASCII.txt"))
+      {
+        for line in text.lines()
+          {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() >= 2
+              {
+                gazetteer.admin1_names.insert(fields[0].to_string(), fields[1].to_string());
+              }
+          }
+      }
+
+    if let Ok(text) = fs::read_to_string(directory.join("countryInfo.txt"))
+      {
+        for line in text.lines().filter(|l| !l.starts_with('#'))
+          {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() >= 5
+              {
+                gazetteer.country_names.insert(fields[0].to_string(), fields[4].to_string());
+              }
+          }
+      }
+
+    Ok(gazetteer)
+  }
+
+
+  /** features
+    fn features(&self) -> &[Feature]
+
+    Returns the features, best first if they came from a search.
+  **/
+  pub fn features(&self) -> &[Feature]
+  {
+    &self.features
+  }
+
+
+  /** describe
+    fn describe(&self, feature: &Feature) -> String
+
+    Returns a feature as one line, e.g. "Uluru, Northern Territory, Australia (T.RK, -25.34450, 131.03650)", with its population if it has one.
+  **/
+  pub fn describe(&self, feature: &Feature) -> String
+  {
+    let mut text = feature.name.clone();
+
+    if let Some(state) = self.state_name(feature)
+      {
+        text.push_str(&format!(", {}", state));
+      }
+    text.push_str(&format!(", {} ({}.{}, {:.5}, {:.5}", self.country_name(feature), feature.class, feature.code, feature.latitude, feature.longitude));
+    if feature.population > 0
+      {
+        text.push_str(&format!(", population {}", feature.population));
+      }
+    text.push(')');
+
+    text
+  }
+
+
+  fn state_name(&self, feature: &Feature) -> Option<&str>
+  {
+    self.admin1_names.get(&format!("{}.{}", feature.country, feature.admin1)).map(|s| s.as_str())
+  }
+
+
+  fn country_name<'a>(&'a self, feature: &'a Feature) -> &'a str
+  {
+    self.country_names.get(&feature.country).map(|s| s.as_str()).unwrap_or(&feature.country)
+  }
+
+
+  /** len
+    fn len(&self) -> usize
+
+    Returns how many populated places were read.
+  **/
+  pub fn len(&self) -> usize
+  {
+    self.features.len()
+  }
+
+
+  /** names_at
+    fn names_at(&self, latitude: f64, longitude: f64) -> Option<PlaceNames>
+      latitude, longitude = position in signed decimal degrees
+
+    Works out the city, state and country for a position: the city is the nearest town within 50 km, and if there is a named section of
+    a town (GeoNames' PPLX) within a few km, that becomes the sublocation.
+  **/
+  pub fn names_at(&self, latitude: f64, longitude: f64) -> Option<PlaceNames>
+  {
+    let city = self.nearest(latitude, longitude, MAX_DISTANCE, |f| !matches!(f.code.as_str(), "PPLX" | "PPLH" | "PPLQ" | "PPLW"))?;
+    let sublocation = self.nearest(latitude, longitude, SUBLOCATION_DISTANCE, |f| f.code == "PPLX");
+
+    Some(PlaceNames
+      {
+        city: city.name.clone(),
+        state: self.state_name(city).map(|s| s.to_string()),
+        country: self.country_names.get(&city.country).cloned(),
+        country_code: city.country.clone(),
+        sublocation: sublocation.map(|s| s.name.clone()),
+      })
+  }
+
+
+  /** nearest
+    fn nearest(&self, latitude: f64, longitude: f64, limit: f64, wanted: impl Fn(&Feature) -> bool) -> Option<&Feature>
+
+    Finds the nearest wanted feature no more than limit metres away. We search the grid in growing rings of cells around the position,
+    and once something turns up, one more ring to be sure nothing in a neighbouring cell is closer.
+  **/
+  fn nearest(&self, latitude: f64, longitude: f64, limit: f64, wanted: impl Fn(&Feature) -> bool) -> Option<&Feature>
+  {
+    let (row, column) = cell(latitude, longitude);
+    let rings = (limit / 111_000.0 / latitude.to_radians().cos().max(0.01)).ceil().min(180.0) as i32;
+    let mut best: Option<(f64, &Feature)> = None;
+    let mut last_ring = rings;
+
+    for ring in 0..=rings
+      {
+        if ring > last_ring
+          {
+            break;
+          }
+
+        for r in (row - ring)..=(row + ring)
+          {
+            for c in (column - ring)..=(column + ring)
+              {
+                if (r - row).abs() != ring && (c - column).abs() != ring
+                  {
+                    continue;                                                  // inside the ring, already looked at
+                  }
+
+                let wrapped = (c + 180).rem_euclid(360) - 180;                 // the grid wraps round at the 180th meridian
+                for feature in self.grid.get(&(r, wrapped)).into_iter().flatten().map(|i| &self.features[*i]).filter(|f| wanted(f))
+                  {
+                    let distance = distance_between(latitude, longitude, feature.latitude, feature.longitude);
+                    if distance <= limit && best.is_none_or(|(d, _)| distance < d)
+                      {
+                        best = Some((distance, feature));
+                      }
+                  }
+              }
+          }
+
+        if best.is_some() && last_ring == rings
+          {
+            last_ring = ring + 1;
+          }
+      }
+
+    best.map(|(_, feature)| feature)
+  }
+}
+
+
+impl PlaceNames
+{
+  /** describe
+    fn describe(&self) -> String
+
+    Returns the names as one line, e.g. "Circular Quay, Sydney, New South Wales, Australia".
+  **/
+  pub fn describe(&self) -> String
+  {
+    [self.sublocation.as_deref(), Some(self.city.as_str()), self.state.as_deref(), Some(self.country.as_deref().unwrap_or(&self.country_code))]
+      .iter()
+      .flatten()
+      .copied()
+      .collect::<Vec<&str>>()
+      .join(", ")
+  }
+}
+
+
+/** class_rank
+  fn class_rank(class: &str) -> u8
+
+  Orders the GeoNames feature classes by how likely they are to be what someone means by a place name when the populations don't settle it:
+  towns, then mountains and other landmarks, parks and areas, buildings and spots, water, and last whole countries and states.
+**/
+fn class_rank(class: &str) -> u8
+{
+  match class
+    {
+      "P" => 0,
+      "T" => 1,
+      "L" => 2,
+      "S" => 3,
+      "H" => 4,
+      "A" => 5,
+      _ => 6,
+    }
+}
+
+
+/** cell
+  fn cell(latitude: f64, longitude: f64) -> (i32, i32)
+
+  Returns the one degree grid cell a position falls in.
+**/
+fn cell(latitude: f64, longitude: f64) -> (i32, i32)
+{
+  (latitude.floor() as i32, longitude.floor() as i32)
+}
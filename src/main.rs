@@ -12,33 +12,310 @@
 #![allow(unused_parens)]
 #![allow(non_snake_case)]
 
+mod astype;
+mod clock;
+mod datum;
+mod dem;
+mod geo;
+mod geonames;
+mod neighbours;
+mod nef;
+mod nksc;
+mod places;
+mod timezone;
+mod track;
+mod validate;
+mod xmp;
+
 // Import Identifers
 
 use std::env;
 use std::fs;
 use std::fs::File;
 use std::path::{Path,PathBuf};
-use std::io::{BufReader,Write};
-use std::io::prelude::*;
+use std::io::BufReader;
 use std::ffi::OsStr;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 
-use data_encoding::BASE64_NOPAD;
-use data_encoding::BASE64;
 use ansi_term::Colour;
 use ansi_term::Style;
 use exif::{ In, Value, Tag};
+use chrono::{DateTime, Duration, NaiveDateTime, Timelike, Utc};
+
+use astype::{AsValue, dms_to_degrees, degrees_to_dms};
+use clock::{Calibration, ClockProfiles};
+use dem::Dem;
+use geonames::{Gazetteer, PlaceNames};
+use nksc::NkscDocument;
+use places::{Place, Places};
+use timezone::Zone;
+use track::{Track, TrackPoint};
 
 // Define Structures
 
+#[derive(Default, Clone)]
 struct LocationData
   {
-    GPSLatitudeRef: String,
-    GPSLatitude: String,
-    GPSLongitudeRef: String,
-    GPSLongitude: String,
-    GPSAltitude: String,
-    GPSDateStamp: String,
-    GPSTimeStamp: String
+    GPSLatitudeRef: String,                                                    // N or S
+    GPSLatitude: Vec<f64>,                                                     // Degrees, minutes, seconds
+    GPSLongitudeRef: String,                                                   // E or W
+    GPSLongitude: Vec<f64>,                                                    // Degrees, minutes, seconds
+    GPSAltitudeRef: u8,                                                        // ABOVE_SEA_LEVEL or BELOW_SEA_LEVEL
+    GPSAltitude: Option<f64>,                                                  // Metres, always positive, GPSAltitudeRef says which way
+    GPSDateStamp: String,                                                      // YYYY:MM:DD
+    GPSTimeStamp: Vec<f64>,                                                    // Hours, minutes, seconds (UTC)
+    GPSSatellites: String,                                                     // The satellites used for the fix, in whatever form the receiver likes
+    GPSMeasureMode: String,                                                    // 2 or 3, for a 2D or 3D fix
+    GPSDOP: Option<f64>,                                                       // Dilution of precision of the fix
+    GPSSpeedRef: String,                                                       // K, M or N, for km/h, mph or knots
+    GPSSpeed: Option<f64>,                                                     // How fast the receiver was moving
+    GPSTrackRef: String,                                                       // T or M, for true or magnetic north
+    GPSTrack: Option<f64>,                                                     // Degrees, the direction the receiver was moving in
+    GPSImgDirectionRef: String,                                                // T or M, for true or magnetic north
+    GPSImgDirection: Option<f64>,                                              // Degrees, the direction the camera was pointing
+    GPSDestBearingRef: String,                                                 // T or M, for true or magnetic north
+    GPSDestBearing: Option<f64>,                                               // Degrees, the direction to whatever the photo is of
+    GPSStatus: String,                                                         // A if the receiver had a fix, V if it was still looking
+    GPSDifferential: Option<u32>,                                              // 1 if the fix was differentially corrected, 0 if not
+    GPSHPositioningError: Option<f64>,                                         // Metres, how far out the position could be
+    GPSProcessingMethod: Vec<u8>,                                              // GPS, CELLID, WLAN or MANUAL after an 8 byte character code
+    GPSMapDatum: String,                                                       // e.g. WGS-84, empty if the source didn't say
+  }
+
+
+impl LocationData
+{
+  /** from_track_point
+    fn from_track_point(point: &TrackPoint) -> LocationData
+      point: &TrackPoint = a position from a track log
+
+    Fills in a LocationData from a position in signed decimal degrees, the way get_location_data_from_exif would have from the NEF.
+    The GPS date and time stamps are the (UTC) time of the position.
+  **/
+  fn from_track_point(point: &TrackPoint) -> LocationData
+  {
+    LocationData
+      {
+        GPSDateStamp: point.time.format("%Y:%m:%d").to_string(),
+        GPSTimeStamp: vec![point.time.hour() as f64, point.time.minute() as f64,
+                           point.time.second() as f64 + point.time.nanosecond() as f64 / 1e9],
+        ..LocationData::from_position(point.latitude, point.longitude, point.altitude)
+      }
+  }
+
+
+  /** from_position
+    fn from_position(latitude: f64, longitude: f64, altitude: Option<f64>) -> LocationData
+      latitude, longitude = position in signed decimal degrees
+      altitude = metres, negative below sea level, if we know it
+
+    Fills in a LocationData from a position with no time attached, e.g. one given on the command line.
+  **/
+  fn from_position(latitude: f64, longitude: f64, altitude: Option<f64>) -> LocationData
+  {
+    let mut Location = LocationData
+      {
+        GPSLatitudeRef: if latitude < 0.0 { "S".to_string() } else { "N".to_string() },
+        GPSLatitude: degrees_to_dms(latitude),
+        GPSLongitudeRef: if longitude < 0.0 { "W".to_string() } else { "E".to_string() },
+        GPSLongitude: degrees_to_dms(longitude),
+        ..LocationData::default()
+      };
+
+    Location.set_altitude(altitude);
+    Location
+  }
+
+
+  /** altitude
+    fn altitude(&self) -> Option<f64>
+
+    Returns the altitude in metres, negative below sea level.
+  **/
+  fn altitude(&self) -> Option<f64>
+  {
+    self.GPSAltitude.map(|altitude| if self.GPSAltitudeRef == BELOW_SEA_LEVEL { -altitude.abs() } else { altitude })
+  }
+
+
+  /** move_to_wgs84
+    fn move_to_wgs84(&mut self) -> Result<Option<&'static str>, String>
+
+    Moves the position onto WGS-84 if its GPSMapDatum says it is on another datum we know, and relabels it. Returns the datum it was
+    converted from, if it was. With a datum we don't know the position is left alone, still labelled with its own datum, and we say so.
+  **/
+  fn move_to_wgs84(&mut self) -> Result<Option<&'static str>, String>
+  {
+    let (latitude, longitude) = match (self.latitude(), self.longitude())
+      {
+        (Some(latitude), Some(longitude)) => (latitude, longitude),
+        _ => return Ok(None),
+      };
+
+    match datum::to_wgs84(&self.GPSMapDatum, latitude, longitude)?
+      {
+        Some((latitude, longitude, from)) =>
+          {
+            let converted = LocationData::from_position(latitude, longitude, None);
+
+            self.GPSLatitudeRef = converted.GPSLatitudeRef;
+            self.GPSLatitude = converted.GPSLatitude;
+            self.GPSLongitudeRef = converted.GPSLongitudeRef;
+            self.GPSLongitude = converted.GPSLongitude;
+            self.GPSMapDatum = "WGS-84".to_string();
+            Ok(Some(from))
+          },
+        None => Ok(None),
+      }
+  }
+
+
+  /** set_altitude
+    fn set_altitude(&mut self, altitude: Option<f64>)
+      altitude = metres, negative below sea level
+
+    Sets the altitude and its reference from a signed height, the way every source other than EXIF gives us one.
+  **/
+  fn set_altitude(&mut self, altitude: Option<f64>)
+  {
+    self.GPSAltitudeRef = if altitude.is_some_and(|a| a < 0.0) { BELOW_SEA_LEVEL } else { ABOVE_SEA_LEVEL };
+    self.GPSAltitude = altitude.map(f64::abs);
+  }
+
+
+  /** latitude
+    fn latitude(&self) -> Option<f64>
+
+    Returns the latitude in signed decimal degrees, negative in the southern hemisphere.
+  **/
+  fn latitude(&self) -> Option<f64>
+  {
+    let degrees = dms_to_degrees(&self.GPSLatitude).ok()?;
+
+    if self.GPSLatitudeRef == "S" { Some(-degrees) } else { Some(degrees) }
+  }
+
+
+  /** longitude
+    fn longitude(&self) -> Option<f64>
+
+    Returns the longitude in signed decimal degrees, negative west of Greenwich.
+  **/
+  fn longitude(&self) -> Option<f64>
+  {
+    let degrees = dms_to_degrees(&self.GPSLongitude).ok()?;
+
+    if self.GPSLongitudeRef == "W" { Some(-degrees) } else { Some(degrees) }
+  }
+
+
+  /** gps_time
+    fn gps_time(&self) -> Option<DateTime<Utc>>
+
+    Returns the GPSDateStamp and GPSTimeStamp as a UTC time, if there are both.
+  **/
+  fn gps_time(&self) -> Option<DateTime<Utc>>
+  {
+    if self.GPSTimeStamp.len() != 3
+      {
+        return None;
+      }
+
+    let date = NaiveDateTime::parse_from_str(&format!("{} 00:00:00", self.GPSDateStamp), "%Y:%m:%d %H:%M:%S").ok()?;
+    let seconds = self.GPSTimeStamp[0] * 3600.0 + self.GPSTimeStamp[1] * 60.0 + self.GPSTimeStamp[2];
+
+    if !seconds.is_finite()
+      {
+        return None;
+      }
+
+    Some(DateTime::from_naive_utc_and_offset(date, Utc) + Duration::milliseconds((seconds * 1000.0).round() as i64))
+  }
+
+
+  /** processing_method
+    fn processing_method(&self) -> Option<String>
+
+    Returns the GPSProcessingMethod as text, without the 8 byte character code in front of it, e.g. "GPS" or "CELLID".
+  **/
+  fn processing_method(&self) -> Option<String>
+  {
+    let text = self.GPSProcessingMethod.get(8..).unwrap_or_default();
+    let text = String::from_utf8_lossy(text).trim_end_matches('\0').trim().to_string();
+
+    Some(text).filter(|t| !t.is_empty())
+  }
+
+
+  /** describe
+    fn describe(&self) -> String
+
+    Returns the location as a human readable line: decimal degrees, altitude and the GPS date and time.
+  **/
+  fn describe(&self) -> String
+  {
+    let (latitude, longitude) = match (self.latitude(), self.longitude())
+      {
+        (Some(latitude), Some(longitude)) => (latitude, longitude),
+        _ => return "no location data".to_string(),
+      };
+    let mut description = format!("{:>11.6} {:>11.6}", latitude, longitude);
+
+    match self.altitude()
+      {
+        Some(altitude) => description.push_str(&format!("  {:>8.1} m", altitude)),
+        None => description.push_str("           "),
+      }
+
+    if !self.GPSDateStamp.is_empty()
+      {
+        description.push_str(&format!("  {}", self.GPSDateStamp));
+      }
+
+    if self.GPSTimeStamp.len() == 3
+      {
+        description.push_str(&format!(" {:02}:{:02}:{:02} UTC", self.GPSTimeStamp[0] as u32, self.GPSTimeStamp[1] as u32, self.GPSTimeStamp[2] as u32));
+      }
+
+    if let Some(direction) = self.GPSImgDirection
+      {
+        description.push_str(&format!("  facing {:.1}°{}", direction, if self.GPSImgDirectionRef == "M" { " magnetic" } else { "" }));
+      }
+
+    description
+  }
+}
+
+struct GeoOptions
+  {
+    check_conflicts: bool,                                                     // Compare the locations when both the NEF and the NKSC have one
+    tolerance: f64,                                                            // How far apart (metres) the two can be before it is a conflict
+    altitude_tolerance: Option<f64>,                                           // How far apart (metres) the altitudes can be, None for the same
+    prefer_nef: bool,                                                          // Settle conflicts by copying the NEF location into the NKSC
+    prefer_nksc: bool,                                                         // Settle conflicts by copying the NKSC location into the NEF
+    force: bool,                                                               // Overwrite the NKSC location with the NEF one even if it already has one
+    track: Track,                                                              // Every point from every track log we were given
+    max_gap: f64,                                                              // The longest gap (seconds) in a track we will interpolate across
+    clock_offset: Option<i64>,                                                 // Seconds to add to the camera's clock to get UTC, None to use its calibration
+    clocks: ClockProfiles,                                                     // Clock calibrations for each of our bodies
+    time_zone: Option<Zone>,                                                   // The zone the camera's clock was set to, if we were told
+    zone_finder: Option<tzf_rs::DefaultFinder>,                                // Time zone boundaries, for when we have to work the zone out
+    given_location: Option<LocationData>,                                      // A location given on the command line, for every NEF
+    fill_window: Option<f64>,                                                  // Fill in untagged NEFs from geotagged ones shot within this many seconds
+    neighbours: RefCell<BTreeMap<PathBuf, Vec<neighbours::Neighbour>>>,        // The geotagged NEFs in each directory, read as we get to it
+    gazetteer: Option<Gazetteer>,                                              // Place names to fill in the city, state and country from
+    dem: Option<Dem>,                                                          // Elevation tiles to fill in missing altitudes from
+    unchecked: bool,                                                           // Write locations which fail the sanity checks, with a warning
+    to_xmp: bool,                                                              // Copy the NKSC location out to the NEF's .xmp sidecar
+    to_nef: bool,                                                              // Copy the NKSC location into the NEF's own GPS IFD
+  }
+
+struct PrivacyOptions
+  {
+    strip: bool,                                                               // Remove the location from every sidecar
+    precision: Option<f64>,                                                    // Round positions to a multiple of this many degrees
+    geofence: Option<Places>,                                                  // Remove locations inside the radius of any of these places
   }
 
 // Custom Macros
@@ -60,8 +337,20 @@ macro_rules! verbose
 // Global Variables
 
 static mut VERBOSE:bool=false;                                                 // Used to tell us if we are going to be verbose
+#[allow(clippy::redundant_static_lifetimes)]
 const VERSION_STRING: &'static str = env!("VERSION_STRING");
-
+const GPS_VERSION_ID: [u8; 4] = [2, 2, 0, 0];                                  // The GPSVersionID NX Studio writes, 2.2.0.0
+const ABOVE_SEA_LEVEL: u8 = 0;                                                 // GPSAltitudeRef for an altitude above sea level
+const BELOW_SEA_LEVEL: u8 = 1;                                                 // GPSAltitudeRef for an altitude below sea level
+const PHOTOSHOP_NAMESPACE: &str = "http://ns.adobe.com/photoshop/1.0/";         // Where the IPTC City, State and Country live in XMP
+const IPTC_CORE_NAMESPACE: &str = "http://iptc.org/std/Iptc4xmpCore/1.0/xmlns/"; // Where the IPTC Sublocation and CountryCode live
+const ONLY_FOR_GEOTAGGING: [&str; 23] = ["--conflicts", "--tolerance", "--altitude-tolerance", "--prefer-nef", "--prefer-nksc", "--set-location", "--place",
+                                         "--place-name", "--geonames", "--dem", "--gpx", "--nmea", "--kml", "--geojson", "--fit", "--tcx",
+                                         "--photos", "--takeout", "--max-accuracy", "--fill-gaps", "--max-gap", "--clock-offset", "--tz"];
+const PRECISE_GPS_PROPERTIES: [&str; 7] = ["GPSAltitude", "GPSImgDirection", "GPSDest", "GPSDateStamp", "GPSTimeStamp", "GPSSpeed", "GPSTrack"]; // Prefixes of what --coarsen removes
+
+#[allow(noop_method_call, clippy::unnecessary_to_owned, clippy::print_literal, clippy::len_zero, clippy::bool_comparison)]
+#[quit::main]                                                                  // quit 2 panics in with_code unless main has this
 fn main()
 {
   let mut search_extension = ".".clone().to_owned()+&"nef".clone().to_owned(); // Default extension to search for
@@ -73,6 +362,26 @@ fn main()
   let mut best_quality:bool=false;                                             // Change the noise reduction from Fastest to Best
   let mut edge:bool=false;                                                     // Enable edge noise reduction  
   let mut enable_geo_sync:bool=false;                                           // Process the geo location data 
+  let mut enable_privacy:bool=false;                                           // Strip or coarsen the locations in the sidecars
+  let mut privacy_options = PrivacyOptions{strip: false, precision: None, geofence: None};
+  let mut geofence:bool=false;                                                 // Strip locations inside the radius of a bookmarked place
+  let mut geo_options = GeoOptions{check_conflicts: false, tolerance: 10.0, altitude_tolerance: None, prefer_nef: false, prefer_nksc: false, force: false,
+                                     track: Track::default(), max_gap: 300.0, clock_offset: None,
+                                     clocks: ClockProfiles::default(), time_zone: None, zone_finder: None,
+                                     given_location: None, fill_window: None, neighbours: RefCell::new(BTreeMap::new()),
+                                     gazetteer: None, dem: None, unchecked: false,
+                                     to_xmp: false, to_nef: false};
+  let mut track_files = Vec::new();                                            // Track log file name pointers, and the reader for each
+  let mut takeout_files = Vec::new();                                          // Google Takeout location history file name pointers
+  let mut max_accuracy:f64=f64::INFINITY;                                      // Skip location history fixes less accurate than this (metres)
+  let mut calibrate_idx: Option<usize> = None;                                 // Pointer to the NEF (followed by the time it shows) to calibrate from
+  let mut clocks_file = clock::default_file();                                 // Where the clock calibrations are kept
+  let mut place_idx: Option<usize> = None;                                     // Pointer to the name of the bookmarked place to geotag with
+  let mut add_place_idx: Option<usize> = None;                                 // Pointer to the name (followed by the NEF) of a place to bookmark
+  let mut places_file = places::default_file();                                // Where the bookmarked places are kept
+  let mut geonames_idx: Option<usize> = None;                                  // Pointer to the GeoNames dump to name places from
+  let mut place_name_idx: Option<usize> = None;                                // Pointer to the name of a place to look up in the GeoNames dump
+  let mut dem_idx: Option<usize> = None;                                       // Pointer to the directory of elevation tiles
   let args: Vec<String> = wild::args().collect();                              // Command line arguments
   let mut file_names = Vec::new();                                             // File name pointers   
   let mut directory_names = Vec::new();                                        // Directory name pointers
@@ -103,6 +412,55 @@ fn main()
                         \x20  --best          Set noise reduction to \"Best\".\n\
                         \x20  --edge          Set \"Edge Noise Reduction\" to \"On\".\n\
                         \x20  --geo           Execute the geosync code.\n\
+                        \x20  --conflicts     Report files where the NEF and NKSC both have a location, but they don't agree.\n\
+                        \x20  --tolerance <m> How far apart in metres the locations can be before they conflict. Default 10.\n\
+                        \x20  --altitude-tolerance <m>\n\
+                        \x20                  How far apart in metres their altitudes can be. Default the same as --tolerance.\n\
+                        \x20  --prefer-nef    Settle conflicts by copying the NEF location into the NKSC.\n\
+                        \x20  --prefer-nksc   Settle conflicts by copying the NKSC location into the NEF.\n\
+                        \x20  --force         Overwrite the location in the NKSC with the one in the NEF, even if the NKSC already has one.\n\
+                        \x20  --to-xmp        Copy the location in the NKSC out to an .xmp sidecar for darktable, Lightroom, digiKam and the like.\n\
+                        \x20  --to-nef        Copy the location in the NKSC into the NEF, adding GPS tags if it has none.\n\
+                        \x20  --unchecked     Write locations that fail the sanity checks (0° 0', out of range, no N/S or E/W, a stale fix)\n\
+                        \x20                  anyway, with a warning, rather than leaving the NKSC alone.\n\
+                        \x20  --set-location <position>\n\
+                        \x20                  Geotag every NEF with the given position, in decimal degrees or degrees, minutes and seconds, and\n\
+                        \x20                  optionally an altitude in metres, e.g. \"48°51'29.6\\\"N 2°17'40.2\\\"E 35m\" or \"-33.8568, 151.2153\".\n\
+                        \x20  --place <name>  Geotag every NEF with a bookmarked place.\n\
+                        \x20  --place-name <name>\n\
+                        \x20                  Geotag every NEF with a place looked up by name in the --geonames dump, e.g. \"Uluru\" or \"Paris, FR\".\n\
+                        \x20  --add-place <name> <file>\n\
+                        \x20                  Bookmark the location of a geotagged NEF as a place called <name>.\n\
+                        \x20  --places <file> Keep the bookmarked places in <file> rather than .nkscgeosync_places in your home directory.\n\
+                        \x20  --geonames <file>\n\
+                        \x20                  Fill in the city, state, country and sublocation of the NKSC from a GeoNames dump, e.g. cities1000.txt.\n\
+                        \x20  --dem <dir>     Fill in missing altitudes from the SRTM .hgt or GeoTIFF elevation tiles in <dir>.\n\
+                        \x20  --gpx <file>    Geotag NEFs without a location of their own from a GPX track log. Can be given more than once.\n\
+                        \x20  --nmea <file>   As --gpx, but for an NMEA log from a Nikon GP-1/GP-1A or a camera's built in logger.\n\
+                        \x20  --kml <file>    As --gpx, but for a KML or KMZ track.\n\
+                        \x20  --geojson <file>\n\
+                        \x20                  As --gpx, but for a GeoJSON track.\n\
+                        \x20  --fit <file>    As --gpx, but for a Garmin FIT activity file.\n\
+                        \x20  --tcx <file>    As --gpx, but for a Garmin TCX activity file.\n\
+                        \x20  --photos <dir>  Use the locations of a folder of phone photos (JPEG or HEIC) as a track log.\n\
+                        \x20  --takeout <file>\n\
+                        \x20                  Use Google Takeout location history (Records.json, Semantic Location History, or a folder of them).\n\
+                        \x20  --max-accuracy <m>\n\
+                        \x20                  Skip location history fixes with an accuracy radius bigger than this many metres.\n\
+                        \x20  --fill-gaps <s> Give NEFs without a location one from the geotagged NEFs in the same directory shot within <s> seconds.\n\
+                        \x20  --max-gap <s>   The longest gap in seconds between two track points that will be interpolated across. Default 300.\n\
+                        \x20  --clock-offset <[-][[hh:]mm:]ss>\n\
+                        \x20                  What to add to the camera's clock to get UTC.\n\
+                        \x20  --tz <zone>     The time zone the camera's clock was set to, e.g. Australia/Sydney or +10:00.\n\
+                        \x20  --calibrate <file> <time>\n\
+                        \x20                  Calibrate a camera's clock from a photo of a GPS or phone showing <time> ([yyyy:mm:dd ]hh:mm:ss,\n\
+                        \x20                  UTC unless followed by Z or +hh:mm). Geotagging uses the calibration unless --clock-offset is given.\n\
+                        \x20  --clocks <file> Keep the clock calibrations in <file> rather than .nkscgeosync_clocks in your home directory.\n\
+                        \x20  --strip         Remove the location, and the place names, from the NKSC.\n\
+                        \x20  --coarsen <degrees>\n\
+                        \x20                  Round the location in the NKSC to a multiple of <degrees>, e.g. 0.01, removing the altitude, heading,\n\
+                        \x20                  GPS time, speed and course.\n\
+                        \x20  --geofence      Remove the location from the NKSC if it is within the radius of any bookmarked place.\n\
                         \x20  --noback        Do not back up the original file\n\
                         \x20  --nosync        Only show the NKSC file which are out of sync with NEF files.\n\
                         \x20  -d <dir name>   Specify a directory to search, or additional directories to search.\n\
@@ -150,6 +508,238 @@ fn main()
            {
              enable_geo_sync = true;
            }
+        else if (argument == "--conflicts")
+           {
+             enable_geo_sync = true;
+             geo_options.check_conflicts = true;
+           }
+        else if (argument == "--tolerance")
+           {
+             i+=1;
+             geo_options.tolerance = match args.get(i).map(|a| a.parse::<f64>())
+               {
+                 Some(Ok(tolerance)) if tolerance >= 0.0 => tolerance,
+                 _ => { println!("--tolerance needs a distance in metres after it."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--altitude-tolerance")
+           {
+             i+=1;
+             geo_options.altitude_tolerance = match args.get(i).map(|a| a.parse::<f64>())
+               {
+                 Some(Ok(tolerance)) if tolerance >= 0.0 => Some(tolerance),
+                 _ => { println!("--altitude-tolerance needs a height in metres after it."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--prefer-nef")
+           {
+             enable_geo_sync = true;
+             geo_options.check_conflicts = true;
+             geo_options.prefer_nef = true;
+           }
+        else if (argument == "--prefer-nksc")
+           {
+             enable_geo_sync = true;
+             geo_options.check_conflicts = true;
+             geo_options.prefer_nksc = true;
+           }
+        else if (argument == "--force")
+           {
+             enable_geo_sync = true;
+             geo_options.force = true;
+           }
+        else if (argument == "--to-xmp")
+           {
+             enable_geo_sync = true;
+             geo_options.to_xmp = true;
+           }
+        else if (argument == "--to-nef")
+           {
+             enable_geo_sync = true;
+             geo_options.to_nef = true;
+           }
+        else if (argument == "--unchecked")
+           {
+             enable_geo_sync = true;
+             geo_options.unchecked = true;
+           }
+        else if (argument == "--set-location")
+           {
+             enable_geo_sync = true;
+             i+=1;
+             geo_options.given_location = match args.get(i).map(|a| geo::parse_position(a))
+               {
+                 Some(Ok((latitude, longitude, altitude))) => Some(LocationData::from_position(latitude, longitude, altitude)),
+                 Some(Err(e)) => { println!("--set-location: {}", e); quit::with_code(2); },
+                 None => { println!("--set-location needs a position like \"-33.8568, 151.2153\" or \"48°51'29.6\\\"N 2°17'40.2\\\"E 35m\" after it."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--place")
+           {
+             enable_geo_sync = true;
+             place_idx = Some(i+1);
+             i+=1;
+           }
+        else if (argument == "--place-name")
+           {
+             enable_geo_sync = true;
+             place_name_idx = Some(i+1);
+             i+=1;
+           }
+        else if (argument == "--add-place")
+           {
+             add_place_idx = Some(i+1);
+             i+=2;
+           }
+        else if (argument == "--places")
+           {
+             i+=1;
+             places_file = match args.get(i)
+               {
+                 Some(file) => PathBuf::from(file),
+                 None => { println!("--places needs a file name after it."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--strip")
+           {
+             enable_privacy = true;
+             privacy_options.strip = true;
+           }
+        else if (argument == "--coarsen")
+           {
+             enable_privacy = true;
+             i+=1;
+             privacy_options.precision = match args.get(i).map(|a| a.trim_end_matches('°').parse::<f64>())
+               {
+                 Some(Ok(degrees)) if degrees > 0.0 && degrees.is_finite() => Some(degrees),
+                 _ => { println!("--coarsen needs a number of degrees after it, e.g. 0.01."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--geofence")
+           {
+             enable_privacy = true;
+             geofence = true;
+           }
+        else if (argument == "--geonames")
+           {
+             enable_geo_sync = true;
+             i+=1;
+             geonames_idx = Some(i);
+           }
+        else if (argument == "--dem")
+           {
+             enable_geo_sync = true;
+             i+=1;
+             dem_idx = Some(i);
+           }
+        else if (argument == "--gpx")
+           {
+             enable_geo_sync = true;
+             track_files.push((i+1, track::gpx::read as track::Reader));
+             i+=1;
+           }
+        else if (argument == "--nmea")
+           {
+             enable_geo_sync = true;
+             track_files.push((i+1, track::nmea::read as track::Reader));
+             i+=1;
+           }
+        else if (argument == "--kml")
+           {
+             enable_geo_sync = true;
+             track_files.push((i+1, track::kml::read as track::Reader));
+             i+=1;
+           }
+        else if (argument == "--geojson")
+           {
+             enable_geo_sync = true;
+             track_files.push((i+1, track::geojson::read as track::Reader));
+             i+=1;
+           }
+        else if (argument == "--fit")
+           {
+             enable_geo_sync = true;
+             track_files.push((i+1, track::fit::read as track::Reader));
+             i+=1;
+           }
+        else if (argument == "--tcx")
+           {
+             enable_geo_sync = true;
+             track_files.push((i+1, track::tcx::read as track::Reader));
+             i+=1;
+           }
+        else if (argument == "--photos")
+           {
+             enable_geo_sync = true;
+             track_files.push((i+1, track::photos::read as track::Reader));
+             i+=1;
+           }
+        else if (argument == "--takeout")
+           {
+             enable_geo_sync = true;
+             takeout_files.push(i+1);
+             i+=1;
+           }
+        else if (argument == "--max-accuracy")
+           {
+             i+=1;
+             max_accuracy = match args.get(i).map(|a| a.parse::<f64>())
+               {
+                 Some(Ok(metres)) if metres > 0.0 => metres,
+                 _ => { println!("--max-accuracy needs a number of metres after it."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--fill-gaps")
+           {
+             enable_geo_sync = true;
+             i+=1;
+             geo_options.fill_window = match args.get(i).map(|a| a.parse::<f64>())
+               {
+                 Some(Ok(window)) if window >= 0.0 => Some(window),
+                 _ => { println!("--fill-gaps needs a number of seconds after it."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--max-gap")
+           {
+             i+=1;
+             geo_options.max_gap = match args.get(i).map(|a| a.parse::<f64>())
+               {
+                 Some(Ok(max_gap)) if max_gap >= 0.0 => max_gap,
+                 _ => { println!("--max-gap needs a number of seconds after it."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--clock-offset")
+           {
+             i+=1;
+             geo_options.clock_offset = match args.get(i).and_then(|a| parse_offset(a))
+               {
+                 Some(offset) => Some(offset),
+                 None => { println!("--clock-offset needs an offset like -01:30:00, 90:00 or 5400 after it."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--tz")
+           {
+             i+=1;
+             geo_options.time_zone = match args.get(i).and_then(|a| Zone::parse(a))
+               {
+                 Some(zone) => Some(zone),
+                 None => { println!("--tz needs a time zone like Australia/Sydney, UTC or +10:00 after it."); quit::with_code(2); }
+               };
+           }
+        else if (argument == "--calibrate")
+           {
+             calibrate_idx = Some(i+1);
+             i+=2;
+           }
+        else if (argument == "--clocks")
+           {
+             i+=1;
+             clocks_file = match args.get(i)
+               {
+                 Some(file) => PathBuf::from(file),
+                 None => { println!("--clocks needs a file name after it."); quit::with_code(2); }
+               };
+           }
         else if (argument == "--noback")
            {
              i_want_to_save_the_original_file = false;
@@ -197,85 +787,340 @@ fn main()
    * Sanity test - see if we have provided something on the command line which will do something
    */
 
-   if (enable_geo_sync==false) && (edge==false) && (astro==false) && (best_quality ==false)
+   if geo_options.prefer_nef && geo_options.prefer_nksc
     {
-      println!("No valid command line option seem to have be given. At least try one of {}, {}, {} or {}. \nType {} for more help.",
-                Style::new().italic().bold().paint("--geo"),
-                Style::new().italic().bold().paint("--astro"),
-                Style::new().italic().bold().paint("--best"),
-                Style::new().italic().bold().paint("--edge"),
-                Style::new().italic().bold().paint("nkscgeosync -?")
-              );
-      quit::with_code(2);        
+      println!("{} and {} can't both be given, the program can't prefer both.",
+                Style::new().italic().bold().paint("--prefer-nef"),
+                Style::new().italic().bold().paint("--prefer-nksc"));
+      quit::with_code(2);
     }
 
-
   /*
-   * If we have been given any file names on the command line, we will now walk through them and process each individually
+   * Copying the NKSC location out doesn't look for a location anywhere else, so anything that would find or settle one would be ignored.
+   * --force and --unchecked are fine, they still mean overwrite a location already there and write one that fails the sanity checks.
+   * The privacy options aren't: they run after the copy, so would take the location out of the NKSC with it left in full beside it.
    */
-  if (file_names.len()>0)
+  if geo_options.to_xmp || geo_options.to_nef
     {
-      for file_idx in file_names
+      let given: Vec<String> = args.iter().skip(1).map(|a| a.to_lowercase()).collect();
+      let ignored: Vec<&str> = given.iter().map(|a| a.as_str()).filter(|a| ONLY_FOR_GEOTAGGING.contains(a)).collect();
+      let private: Vec<&str> = given.iter().map(|a| a.as_str()).filter(|a| ["--strip", "--coarsen", "--geofence"].contains(a)).collect();
+      let copying = match (geo_options.to_xmp, geo_options.to_nef) { (true, true) => "--to-xmp and --to-nef", (true, false) => "--to-xmp", _ => "--to-nef" };
+
+      if !ignored.is_empty()
         {
-          let path = PathBuf::from(args[file_idx].to_lowercase());
+          println!("{} can't be given with {}: the location already in the NKSC is copied out, not a new one found.",
+                    Style::new().italic().bold().paint(ignored.join(", ")),
+                    Style::new().italic().bold().paint(copying));
+          quit::with_code(2);
+        }
+      if !private.is_empty()
+        {
+          println!("{} can't be given with {}: the location would be copied out of the NKSC in full before it was removed or rounded.",
+                    Style::new().italic().bold().paint(private.join(", ")),
+                    Style::new().italic().bold().paint(copying));
+          quit::with_code(2);
+        }
+    }
 
-          if enable_geo_sync
+  /*
+   * Calibrating a camera's clock is a job on its own, do it and we're done
+   */
+  if let Some(calibrate_idx) = calibrate_idx
+    {
+      match (args.get(calibrate_idx), args.get(calibrate_idx+1))
+        {
+          (Some(nef), Some(reading)) =>
             {
-              geo_sync_a_file(&path, &search_extension, i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything);
-            }
-
-          if astro==true || best_quality==true || edge==true
-            {  
-              set_noise_reduction_in_a_file(&path, &search_extension, i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything, astro, best_quality,edge);
-            }
+              if let Err(e) = calibrate_clock(Path::new(nef), reading, &clocks_file, i_want_to_save_changes)
+                {
+                  println!("Could not calibrate from {}: {}", nef, e);
+                  quit::with_code(2);
+                }
+            },
+          _ => { println!("--calibrate needs a NEF and the time shown in it, e.g. --calibrate gps.nef \"2021:05:03 01:02:03\"."); quit::with_code(2); },
         }
+      quit::with_code(0);
     }
 
-
   /*
-   * Now we will start the business. A few things can happen here, if no directories were specified on the command line, or indeed there was no command line, then the current
-   * directory will be searched. Can be useful I guess.
-   * When one or more directory names are given they will be parsed one at a time.
-   * When the recursive flag is given, all subdirectories will also be searched. If you have a subdirectory also specified as one of the directories on the command line, the
-   * behaviours will be unspecified, but possibly harmless. Maybe. Hopefully. Having symlinks nested within a recursive search could be another matter entirely, that probably
-   * will be a very bad thing and will doubtless end in an endless loop.
+   * Bookmarking a place is a job on its own too
    */
-
-  if (directory_names.len()>0)
+  if let Some(add_place_idx) = add_place_idx
     {
-      for dir_idx in directory_names
+      match (args.get(add_place_idx), args.get(add_place_idx+1))
         {
-          let SearchDirectory = Path::new(&args[dir_idx]).to_path_buf();
-
-          WalkDirectory(&SearchDirectory, &search_extension, recursive,i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything,enable_geo_sync,
-                        astro,best_quality,edge);
+          (Some(name), Some(nef)) =>
+            {
+              if let Err(e) = bookmark_place(name, Path::new(nef), &places_file, i_want_to_save_changes)
+                {
+                  println!("Could not bookmark {} from {}: {}", name, nef, e);
+                  quit::with_code(2);
+                }
+            },
+          _ => { println!("--add-place needs a name and a geotagged NEF, e.g. --add-place Studio DSC_0001.NEF."); quit::with_code(2); },
         }
+      quit::with_code(0);
     }
-  else // We were not given any directory paths to process, so we'll use the current directory instead
-    {
-      let SearchDirectory = env::current_dir().expect("Could not find the starting directory to look for files.");
 
-      WalkDirectory(&SearchDirectory, &search_extension, recursive,i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything,enable_geo_sync,
-                    astro,best_quality,edge);
+  if let Some(place_idx) = place_idx
+    {
+      if geo_options.given_location.is_some()
+        {
+          println!("{} and {} can't both be given.", Style::new().italic().bold().paint("--place"), Style::new().italic().bold().paint("--set-location"));
+          quit::with_code(2);
+        }
+
+      let places = match Places::load(&places_file)
+        {
+          Ok(places) => places,
+          Err(e) => { println!("{}", e); quit::with_code(2); }
+        };
+
+      let name = match args.get(place_idx)
+        {
+          Some(name) => name,
+          None => { println!("--place needs a name after it."); quit::with_code(2); }
+        };
+
+      match places.find(name)
+        {
+          Some(place) =>
+            {
+              println!("Geotagging with {}", place.name);
+              geo_options.given_location = Some(LocationData::from_position(place.latitude, place.longitude, place.altitude));
+            },
+          None =>
+            {
+              println!("{} is not one of the places bookmarked in {}: {}", name, places_file.display(), places.names().join(", "));
+              quit::with_code(2);
+            },
+        }
     }
-}
 
+  /*
+   * Look a place name up in the GeoNames dump. The best match is used, but if the name is shared show the others, so the user can
+   * tell if we picked the wrong one
+   */
+  if let Some(place_name_idx) = place_name_idx
+    {
+      if geo_options.given_location.is_some()
+        {
+          println!("Only one of {}, {} and {} can be given.", Style::new().italic().bold().paint("--place-name"),
+                   Style::new().italic().bold().paint("--place"), Style::new().italic().bold().paint("--set-location"));
+          quit::with_code(2);
+        }
+
+      let (name, geonames_file) = match (args.get(place_name_idx), geonames_idx.and_then(|i| args.get(i)))
+        {
+          (Some(name), Some(geonames_file)) => (name, geonames_file),
+          (None, _) => { println!("--place-name needs a name after it."); quit::with_code(2); },
+          (_, None) => { println!("--place-name needs a GeoNames dump to look the name up in, given with --geonames."); quit::with_code(2); },
+        };
 
-/** check_if_this_is_already_in
-  fn check_if_this_is_already_in(file: &PathBuf, this: &str) -> bool
-    file: &PathBuf = path to an nksc file
+      let matches = match Gazetteer::search(Path::new(geonames_file), name)
+        {
+          Ok(matches) => matches,
+          Err(e) => { println!("{}", e); quit::with_code(2); }
+        };
 
-  Takes a fully qualified path as a parameter and returns true if the file has "this" in it,
-  and false if it does not. I' sure there is an easier way to write this since it is basically a
-  single line function, but off the top of my head I can't quite remember how.
-**/
-fn check_if_this_is_already_in(file: &PathBuf, this: &str) -> bool
-{
- if (fs::read_to_string(file).expect("Could not open nksc file to check its contents").find(this)==None)
-   {
-     return false;
-   }
- true
+      match matches.features().first()
+        {
+          Some(best) =>
+            {
+              println!("Geotagging with {}", matches.describe(best));
+              geo_options.given_location = Some(LocationData::from_position(best.latitude, best.longitude, best.elevation));
+            },
+          None =>
+            {
+              println!("There is no place called {} in {}", name, geonames_file);
+              quit::with_code(2);
+            },
+        }
+
+      if matches.features().len() > 1
+        {
+          let name = name.split(',').next().unwrap_or(name).trim();
+          println!("{} other places are called {}:", matches.features().len() - 1, name);
+          for (n, feature) in matches.features().iter().enumerate().skip(1).take(9)
+            {
+              println!("  {}. {}", n + 1, matches.describe(feature));
+            }
+          println!("Add the state or country after a comma to pick one of them, e.g. --place-name \"{}, {}\".", name, matches.features()[1].country);
+        }
+    }
+
+  /*
+   * Without an offset on the command line, each camera's clock is corrected by its calibration
+   */
+  if enable_geo_sync && geo_options.clock_offset.is_none()
+    {
+      geo_options.clocks = match ClockProfiles::load(&clocks_file)
+        {
+          Ok(clocks) => clocks,
+          Err(e) => { println!("{}", e); quit::with_code(2); }
+        };
+    }
+
+  /*
+   * Read in any track logs we were given, all of them end up in the one track
+   */
+  for (track_idx, reader) in track_files
+    {
+      match args.get(track_idx).map(|f| reader(Path::new(f)))
+        {
+          Some(Ok(points)) =>
+            {
+              println!("Read {} track points from {}", points.len(), args[track_idx]);
+              geo_options.track.add(points);
+            },
+          Some(Err(e)) => { println!("{}", e); quit::with_code(2); },
+          None => { println!("{} needs a file name after it.", args[track_idx-1]); quit::with_code(2); },
+        }
+    }
+
+  for takeout_idx in takeout_files
+    {
+      match args.get(takeout_idx).map(|f| track::takeout::read(Path::new(f), max_accuracy))
+        {
+          Some(Ok((points, stays, skipped))) =>
+            {
+              if skipped > 0
+                {
+                  println!("Read {} track points and {} place visits from {} ({} less accurate than {} m skipped)", points.len(), stays.len(),
+                           args[takeout_idx], skipped, max_accuracy);
+                }
+              else
+                {
+                  println!("Read {} track points and {} place visits from {}", points.len(), stays.len(), args[takeout_idx]);
+                }
+              geo_options.track.add_stays(stays);
+              geo_options.track.add(points);
+            },
+          Some(Err(e)) => { println!("{}", e); quit::with_code(2); },
+          None => { println!("--takeout needs a file or folder name after it."); quit::with_code(2); },
+        }
+    }
+
+  /*
+   * The place names are only looked up once we have somewhere to write them, but read them in now so a bad file stops us before we start
+   */
+  if let Some(geonames_idx) = geonames_idx
+    {
+      match args.get(geonames_idx).map(|f| Gazetteer::load(Path::new(f)))
+        {
+          Some(Ok(gazetteer)) =>
+            {
+              println!("Read {} places from {}", gazetteer.len(), args[geonames_idx]);
+              geo_options.gazetteer = Some(gazetteer);
+            },
+          Some(Err(e)) => { println!("{}", e); quit::with_code(2); },
+          None => { println!("--geonames needs a file name after it."); quit::with_code(2); },
+        }
+    }
+
+  if let Some(dem_idx) = dem_idx
+    {
+      match args.get(dem_idx).map(|d| Dem::load(Path::new(d)))
+        {
+          Some(Ok(dem)) =>
+            {
+              println!("Found {} elevation tiles in {}", dem.len(), args[dem_idx]);
+              geo_options.dem = Some(dem);
+            },
+          Some(Err(e)) => { println!("{}", e); quit::with_code(2); },
+          None => { println!("--dem needs a directory name after it."); quit::with_code(2); },
+        }
+    }
+
+  /*
+   * The geofences are the bookmarked places with a radius
+   */
+  if geofence
+    {
+      match Places::load(&places_file)
+        {
+          Ok(places) if places.count_with_radius() > 0 => privacy_options.geofence = Some(places),
+          Ok(_) => { println!("None of the places bookmarked in {} have a radius to fence off.", places_file.display()); quit::with_code(2); },
+          Err(e) => { println!("{}", e); quit::with_code(2); },
+        }
+    }
+
+  /*
+   * With nothing else to go on, the camera's clock is taken to be local time wherever the track was, so load up the zone boundaries
+   */
+  if enable_geo_sync && !geo_options.track.is_empty() && geo_options.clock_offset.is_none() && geo_options.time_zone.is_none()
+    {
+      geo_options.zone_finder = Some(tzf_rs::DefaultFinder::new());
+    }
+
+   if !enable_geo_sync && !enable_privacy && !edge && !astro && !best_quality
+    {
+      println!("No valid command line option seem to have be given. At least try one of {}, {}, {}, {} or {}. \nType {} for more help.",
+                Style::new().italic().bold().paint("--geo"),
+                Style::new().italic().bold().paint("--strip"),
+                Style::new().italic().bold().paint("--astro"),
+                Style::new().italic().bold().paint("--best"),
+                Style::new().italic().bold().paint("--edge"),
+                Style::new().italic().bold().paint("nkscgeosync -?")
+              );
+      quit::with_code(2);        
+    }
+
+
+  /*
+   * If we have been given any file names on the command line, we will now walk through them and process each individually
+   */
+  if (file_names.len()>0)
+    {
+      for file_idx in file_names
+        {
+          let path = PathBuf::from(args[file_idx].to_lowercase());
+
+          if enable_geo_sync
+            {
+              geo_sync_a_file(&path, &search_extension, i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything,&geo_options);
+            }
+
+          if enable_privacy
+            {
+              privacy_a_file(&path, &search_extension, i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything,&privacy_options);
+            }
+
+          if astro==true || best_quality==true || edge==true
+            {  
+              set_noise_reduction_in_a_file(&path, &search_extension, i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything, astro, best_quality,edge);
+            }
+        }
+    }
+
+
+  /*
+   * Now we will start the business. A few things can happen here, if no directories were specified on the command line, or indeed there was no command line, then the current
+   * directory will be searched. Can be useful I guess.
+   * When one or more directory names are given they will be parsed one at a time.
+   * When the recursive flag is given, all subdirectories will also be searched. If you have a subdirectory also specified as one of the directories on the command line, the
+   * behaviours will be unspecified, but possibly harmless. Maybe. Hopefully. Having symlinks nested within a recursive search could be another matter entirely, that probably
+   * will be a very bad thing and will doubtless end in an endless loop.
+   */
+
+  if (directory_names.len()>0)
+    {
+      for dir_idx in directory_names
+        {
+          let SearchDirectory = Path::new(&args[dir_idx]).to_path_buf();
+
+          WalkDirectory(&SearchDirectory, &search_extension, recursive,i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything,enable_geo_sync,
+                        astro,best_quality,edge,&geo_options,enable_privacy,&privacy_options);
+        }
+    }
+  else // We were not given any directory paths to process, so we'll use the current directory instead
+    {
+      let SearchDirectory = env::current_dir().expect("Could not find the starting directory to look for files.");
+
+      WalkDirectory(&SearchDirectory, &search_extension, recursive,i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything,enable_geo_sync,
+                    astro,best_quality,edge,&geo_options,enable_privacy,&privacy_options);
+    }
 }
 
 
@@ -288,17 +1133,29 @@ fn check_if_this_is_already_in(file: &PathBuf, this: &str) -> bool
 **/
 fn check_if_there_is_location_data_in(file: &Path) -> bool
 {
-  let file = File::open(file).expect(format!("Could not open {:?}",file.file_name()).as_str());
-  let exif = exif::Reader::new().read_from_container(&mut BufReader::new(&file)).expect("Could not read EXIF data");
+  try_check_if_there_is_location_data_in(file).unwrap_or_else(|e| panic!("{}", e))
+}
+
+
+/** try_check_if_there_is_location_data_in
+  fn try_check_if_there_is_location_data_in(file: &Path) -> Result<bool, String>
+   file: &Path = path to an exif file
+
+  As check_if_there_is_location_data_in, but a file which can't be opened or has no EXIF comes back as an error rather than stopping us.
+**/
+fn try_check_if_there_is_location_data_in(file: &Path) -> Result<bool, String>
+{
+  let file = File::open(file).map_err(|e| format!("Could not open {:?}: {}", file.file_name(), e))?;
+  let exif = exif::Reader::new().read_from_container(&mut BufReader::new(&file)).map_err(|e| format!("Could not read EXIF data: {}", e))?;
 
   for f in exif.fields()
     {
       if (format!("{}",f.tag)=="GPSVersionID")
         {
-          return true;
+          return Ok(true);
         }
     }
-  false
+  Ok(false)
 }
 
 
@@ -309,519 +1166,1704 @@ fn check_if_there_is_location_data_in(file: &Path) -> bool
     LocationData: &mut LocationData = pointer to a structure to hold our location data
 
   Function opens up a given NEF file and then uses the KAMADAK EXIF reader to interrogate the file and get out the raw exif data for the GPSLocation data.
-  After that it munges the data for each of the types of data into the different formats which are required. Some of the data is encoded in plain ASCII,
-  some in un-padded Base64 and others in padded Base64.
+  The values are kept as plain numbers and letters, turning them into what the sidecar wants is left to the astype codec when they are written.
 
 **/
 fn get_location_data_from_exif(file: &Path, LocationData: &mut LocationData)
 {
-  let file = File::open(file).expect(format!("Could not open {:?}",file.file_name()).as_str());
-  let exif = exif::Reader::new().read_from_container(&mut BufReader::new(&file)).expect("Could not read the exif data from an image file");
+  try_get_location_data_from_exif(file, LocationData).unwrap_or_else(|e| panic!("{}", e));
+}
+
+
+/** try_get_location_data_from_exif
+  fn try_get_location_data_from_exif(file: &Path, LocationData: &mut LocationData) -> Result<(), String>
+
+  As get_location_data_from_exif, but a file which can't be opened or has no EXIF comes back as an error rather than stopping us.
+**/
+fn try_get_location_data_from_exif(file: &Path, LocationData: &mut LocationData) -> Result<(), String>
+{
+  let file = File::open(file).map_err(|e| format!("Could not open {:?}: {}", file.file_name(), e))?;
+  let exif = exif::Reader::new().read_from_container(&mut BufReader::new(&file)).map_err(|e| format!("Could not read the exif data from an image file: {}", e))?;
   
   
   /*
-   * Read the GPSLatitudeRef from the exif data and convert it to a pre-computed un-padded BASE64 value
+   * Read the GPSLatitudeRef from the exif data, N or S
    */
   if let Some(field) = exif.get_field(Tag::GPSLatitudeRef, In::PRIMARY)
     {
-      if format!("{}",field.display_value())=="N"  // 0
-        {
-          verbose!("North");
-          LocationData.GPSLatitudeRef=String::from("AAAAAA==");
-        }
-      else // South = 1
+      LocationData.GPSLatitudeRef=format!("{}",field.display_value());
+      verbose!("GPSLatitudeRef: {}", LocationData.GPSLatitudeRef);
+    }
+
+
+  /*
+   * Read the GPSLongitudeRef from the exif data, E or W
+   */
+  if let Some(field) = exif.get_field(Tag::GPSLongitudeRef, In::PRIMARY)
+    {
+      LocationData.GPSLongitudeRef=format!("{}",field.display_value());
+      verbose!("GPSLongitudeRef: {}", LocationData.GPSLongitudeRef);
+    }
+
+
+  /*
+   * Read the Latitude from the exif data which is stored as 3 rationals, degrees, minutes and seconds
+   */
+  if let Some(field) = exif.get_field(Tag::GPSLatitude, In::PRIMARY)
+    {
+      if let Value::Rational(ref latitude) = field.value
         {
-          verbose!("South");
-          LocationData.GPSLatitudeRef=String::from("AQAAAA==");
+          LocationData.GPSLatitude=latitude.iter().map(|r| r.to_f64()).collect();
+          verbose!("Field Value: {:?}\nDecimal Degrees: {:?}", field.value, dms_to_degrees(&LocationData.GPSLatitude));
         }
     }
 
 
   /*
-   * Read the GPSLongitudeRef from the exif data and convert it to a pre-computed un-padded BASE64 value
+   * Read the Longitude from the exif data which is stored as 3 rationals, degrees, minutes and seconds
    */
-  if let Some(field) = exif.get_field(Tag::GPSLongitudeRef, In::PRIMARY)
+  if let Some(field) = exif.get_field(Tag::GPSLongitude, In::PRIMARY)
     {
-      if format!("{}",field.display_value())=="E" // 2
+      if let Value::Rational(ref longitude) = field.value
         {
-          verbose!("East");
-          LocationData.GPSLongitudeRef=String::from("AgAAAA==");
+          LocationData.GPSLongitude=longitude.iter().map(|r| r.to_f64()).collect();
+          verbose!("Field Value: {:?}\nGPSLongitude Decimal Degrees: {:?}", field.value, dms_to_degrees(&LocationData.GPSLongitude));
         }
-      else // W = 3
+    }
+
+
+  /*
+   * Read the GPSAltitudeRef, a single byte, 0 for above sea level and 1 for below
+   */
+  if let Some(field) = exif.get_field(Tag::GPSAltitudeRef, In::PRIMARY)
+    {
+      if let Value::Byte(ref AltitudeRef) = field.value
         {
-          verbose!("West");
-          LocationData.GPSLongitudeRef=String::from("AwAAAA==");
+          LocationData.GPSAltitudeRef=if AltitudeRef.first() == Some(&BELOW_SEA_LEVEL) { BELOW_SEA_LEVEL } else { ABOVE_SEA_LEVEL };
+          verbose!("Field Value: {:?}\nGPSAltitudeRef: {}", field.value, LocationData.GPSAltitudeRef);
         }
     }
 
 
   /*
-   * Read the Latitude from the exif data which is stored a 3 f64 values and convert it to an un-padded BASE64 value
-   * Unfortunately to do the conversion, we have to do it in a super convoluted way, namely we have to map each of our three variables to three buffers
-   * using the transmute function, then we have to in-turn copy those three individual mapped buffers into a continuous memory block to get Base64 encoded.
-   * Finally we reformat the output as a string and copy it into our location data structure.
+   * Read the GPSAltitude from the exif data, a single rational in metres
    */
-  if let Some(field) = exif.get_field(Tag::GPSLatitude, In::PRIMARY)
+  if let Some(field) = exif.get_field(Tag::GPSAltitude, In::PRIMARY)
     {
-      match field.value
+      if let Value::Rational(ref Altitude) = field.value
         {
-          Value::Rational(ref latitude) =>
-            {
-              let mut raw_bytes: [u8; 24]= [0;24];
-              let raw_bytes0: [u8; 8] = unsafe { std::mem::transmute(latitude[0].to_f64()) };
-              let raw_bytes1: [u8; 8] = unsafe { std::mem::transmute(latitude[1].to_f64()) };
-              let raw_bytes2: [u8; 8] = unsafe { std::mem::transmute(latitude[2].to_f64()) };
+          LocationData.GPSAltitude=Altitude.first().map(|r| r.to_f64());
+          verbose!("Field Value: {:?}\nGPSAltitude: {:?}", field.value, LocationData.GPSAltitude);
+        }
+    }
 
-              for i in 0..8
-                {
-                  raw_bytes[i]=raw_bytes0[i];
-                  raw_bytes[i+8]=raw_bytes1[i];
-                  raw_bytes[i+16]=raw_bytes2[i];
-                }
 
-              let b64=BASE64_NOPAD.encode(&raw_bytes);
-              LocationData.GPSLatitude=String::from(format!("{}", b64));
+  /*
+   * Read the GPSDateStamp
+   */
+  if let Some(field) = exif.get_field(Tag::GPSDateStamp, In::PRIMARY)
+    {
+      LocationData.GPSDateStamp=format!("{}",field.display_value()).replace("-",":");
+      verbose!("\nGPSDateStamp: {}", LocationData.GPSDateStamp);
+    }
 
-              unsafe
-                {
-                  if (VERBOSE==true)
-                    {
-                      let Latitude: f64=latitude[0].to_f64()+(latitude[1].to_f64()/60.0)+(latitude[2].to_f64()/3600.0);
 
-                      println!("Field Value: {:?}\nDecimal Degrees: {}", field.value,Latitude);
-                      println!("GPSLatitude BASE64: {}", LocationData.GPSLatitude);
-                    }
-                }
-            },
-          _ => {},
+  /*
+   * Read the GPSTimeStamp from the exif data, 3 rationals for hours, minutes and seconds
+   */
+  if let Some(field) = exif.get_field(Tag::GPSTimeStamp, In::PRIMARY)
+    {
+      if let Value::Rational(ref TimeStamp) = field.value
+        {
+          LocationData.GPSTimeStamp=TimeStamp.iter().map(|r| r.to_f64()).collect();
+          verbose!("GPSTimeStamp: {:?}", LocationData.GPSTimeStamp);
         }
     }
 
 
   /*
-   * Read the Longitude from the exif data and convert it to an un-padded BASE64 value
+   * Read the rest of the GPS IFD. The letters and text are kept as they are, the single rationals as plain numbers.
    */
-  if let Some(field) = exif.get_field(Tag::GPSLongitude, In::PRIMARY)
+  for (tag, text) in [(Tag::GPSSatellites, &mut LocationData.GPSSatellites),
+                      (Tag::GPSMeasureMode, &mut LocationData.GPSMeasureMode),
+                      (Tag::GPSSpeedRef, &mut LocationData.GPSSpeedRef),
+                      (Tag::GPSTrackRef, &mut LocationData.GPSTrackRef),
+                      (Tag::GPSImgDirectionRef, &mut LocationData.GPSImgDirectionRef),
+                      (Tag::GPSDestBearingRef, &mut LocationData.GPSDestBearingRef),
+                      (Tag::GPSStatus, &mut LocationData.GPSStatus),
+                      (Tag::GPSMapDatum, &mut LocationData.GPSMapDatum)]
     {
-      match field.value
+      if let Some(field) = exif.get_field(tag, In::PRIMARY)
         {
-          Value::Rational(ref longitude) =>
+          if let Value::Ascii(ref Text) = field.value
             {
-              let mut raw_bytes: [u8; 24]= [0;24];
-              let raw_bytes0: [u8; 8] = unsafe { std::mem::transmute(longitude[0].to_f64()) };
-              let raw_bytes1: [u8; 8] = unsafe { std::mem::transmute(longitude[1].to_f64()) };
-              let raw_bytes2: [u8; 8] = unsafe { std::mem::transmute(longitude[2].to_f64()) };
+              *text=Text.first().map(|t| String::from_utf8_lossy(t).trim_end_matches('\0').trim().to_string()).unwrap_or_default();
+              verbose!("{}: {}", tag, text);
+            }
+        }
+    }
 
-              for i in 0..8
-                {
-                  raw_bytes[i]=raw_bytes0[i];
-                  raw_bytes[i+8]=raw_bytes1[i];
-                  raw_bytes[i+16]=raw_bytes2[i];
-                }
+  for (tag, number) in [(Tag::GPSDOP, &mut LocationData.GPSDOP),
+                        (Tag::GPSSpeed, &mut LocationData.GPSSpeed),
+                        (Tag::GPSTrack, &mut LocationData.GPSTrack),
+                        (Tag::GPSImgDirection, &mut LocationData.GPSImgDirection),
+                        (Tag::GPSDestBearing, &mut LocationData.GPSDestBearing),
+                        (Tag::GPSHPositioningError, &mut LocationData.GPSHPositioningError)]
+    {
+      if let Some(field) = exif.get_field(tag, In::PRIMARY)
+        {
+          if let Value::Rational(ref Number) = field.value
+            {
+              *number=Number.first().map(|r| r.to_f64()).filter(|n| n.is_finite());
+              verbose!("{}: {:?}", tag, number);
+            }
+        }
+    }
 
-              let b64=BASE64_NOPAD.encode(&raw_bytes);
-              LocationData.GPSLongitude=String::from(format!("{}", b64));
+  if let Some(field) = exif.get_field(Tag::GPSDifferential, In::PRIMARY)
+    {
+      LocationData.GPSDifferential=field.value.get_uint(0);
+      verbose!("GPSDifferential: {:?}", LocationData.GPSDifferential);
+    }
 
-              unsafe
-                {
-                  if (VERBOSE==true)
-                    {
-                      let Longitude: f64=longitude[0].to_f64()+(longitude[1].to_f64()/60.0)+(longitude[2].to_f64()/3600.0);
-                      println!("Field Value: {:?}\nGPSLongitude Decimal Degrees: {}", field.value,Longitude);
-                      println!("GPSLongitude BASE64: {}", LocationData.GPSLongitude);
-                    }
-                }
-            },
-          _ => {},
+  if let Some(field) = exif.get_field(Tag::GPSProcessingMethod, In::PRIMARY)
+    {
+      if let Value::Undefined(ref Bytes, _) = field.value
+        {
+          LocationData.GPSProcessingMethod=Bytes.clone();
+          verbose!("GPSProcessingMethod: {}", field.display_value());
+        }
+    }
+
+  Ok(())
+}
+
+
+/** get_location_data_from_nksc
+  fn get_location_data_from_nksc(nksc: &NkscDocument, LocationData: &mut LocationData) -> Result<(), String>
+
+    nksc: &NkscDocument = the sidecar to read from
+    LocationData: &mut LocationData = pointer to a structure to hold our location data
+
+  The reverse of create_new_nksc_file: decodes the ast:GPS* properties of a sidecar back into plain numbers and letters.
+  Properties which are missing are left empty, ones which are there but can't be decoded are an error.
+**/
+fn get_location_data_from_nksc(nksc: &NkscDocument, LocationData: &mut LocationData) -> Result<(), String>
+{
+  if let Some(value) = nksc.get_value("GPSLatitudeRef")
+    {
+      if let AsValue::Long(reference) = value?
+        {
+          LocationData.GPSLatitudeRef=exif_ref_from_nksc(reference).to_string();
+        }
+    }
+
+  if let Some(value) = nksc.get_value("GPSLongitudeRef")
+    {
+      if let AsValue::Long(reference) = value?
+        {
+          LocationData.GPSLongitudeRef=exif_ref_from_nksc(reference).to_string();
         }
     }
 
+  if let Some(value) = nksc.get_value("GPSLatitude")
+    {
+      if let AsValue::Double(latitude) = value?
+        {
+          LocationData.GPSLatitude=latitude;
+        }
+    }
+
+  if let Some(value) = nksc.get_value("GPSLongitude")
+    {
+      if let AsValue::Double(longitude) = value?
+        {
+          LocationData.GPSLongitude=longitude;
+        }
+    }
+
+  if let Some(value) = nksc.get_value("GPSAltitudeRef")
+    {
+      if let AsValue::Binary(reference) = value?
+        {
+          LocationData.GPSAltitudeRef=if reference.first() == Some(&BELOW_SEA_LEVEL) { BELOW_SEA_LEVEL } else { ABOVE_SEA_LEVEL };
+        }
+    }
+
+  if let Some(value) = nksc.get_value("GPSAltitude")
+    {
+      if let AsValue::Double(altitude) = value?
+        {
+          LocationData.GPSAltitude=altitude.first().copied();
+        }
+    }
+
+  if let Some(value) = nksc.get_value("GPSDateStamp")
+    {
+      if let AsValue::Ascii(date) = value?
+        {
+          LocationData.GPSDateStamp=date;
+        }
+    }
+
+  if let Some(value) = nksc.get_value("GPSTimeStamp")
+    {
+      if let AsValue::Double(time) = value?
+        {
+          LocationData.GPSTimeStamp=time;
+        }
+    }
+
+  for (name, text) in [("GPSSatellites", &mut LocationData.GPSSatellites),
+                       ("GPSMeasureMode", &mut LocationData.GPSMeasureMode),
+                       ("GPSSpeedRef", &mut LocationData.GPSSpeedRef),
+                       ("GPSTrackRef", &mut LocationData.GPSTrackRef),
+                       ("GPSImgDirectionRef", &mut LocationData.GPSImgDirectionRef),
+                       ("GPSDestBearingRef", &mut LocationData.GPSDestBearingRef),
+                       ("GPSStatus", &mut LocationData.GPSStatus),
+                       ("GPSMapDatum", &mut LocationData.GPSMapDatum)]
+    {
+      if let Some(value) = nksc.get_value(name)
+        {
+          if let AsValue::Ascii(value) = value?
+            {
+              *text=value;
+            }
+        }
+    }
+
+  for (name, number) in [("GPSDOP", &mut LocationData.GPSDOP),
+                         ("GPSSpeed", &mut LocationData.GPSSpeed),
+                         ("GPSTrack", &mut LocationData.GPSTrack),
+                         ("GPSImgDirection", &mut LocationData.GPSImgDirection),
+                         ("GPSDestBearing", &mut LocationData.GPSDestBearing),
+                         ("GPSHPositioningError", &mut LocationData.GPSHPositioningError)]
+    {
+      if let Some(value) = nksc.get_value(name)
+        {
+          if let AsValue::Double(value) = value?
+            {
+              *number=value.first().copied();
+            }
+        }
+    }
+
+  if let Some(value) = nksc.get_value("GPSDifferential")
+    {
+      if let AsValue::Long(differential) = value?
+        {
+          LocationData.GPSDifferential=Some(differential);
+        }
+    }
+
+  if let Some(value) = nksc.get_value("GPSProcessingMethod")
+    {
+      if let AsValue::Binary(method) = value?
+        {
+          LocationData.GPSProcessingMethod=method;
+        }
+    }
+
+  Ok(())
+}
+
+
+/** get_date_time_original
+  fn get_date_time_original(file: &Path) -> Result<NaiveDateTime, String>
+    file: &Path = path to the NEF
+
+  Returns when the photo was taken according to the camera's clock, from the DateTimeOriginal exif tag. There is no time zone on it.
+**/
+fn get_date_time_original(file: &Path) -> Result<NaiveDateTime, String>
+{
+  let file = File::open(file).map_err(|e| format!("Could not open {:?}: {}",file.file_name(),e))?;
+  let exif = exif::Reader::new().read_from_container(&mut BufReader::new(&file)).map_err(|e| format!("Could not read the exif data: {}",e))?;
+  let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).ok_or("there is no DateTimeOriginal")?;
+
+  match field.value
+    {
+      Value::Ascii(ref text) if !text.is_empty() =>
+        {
+          let text = String::from_utf8_lossy(&text[0]);
+          NaiveDateTime::parse_from_str(text.trim_end_matches('\0').trim(), "%Y:%m:%d %H:%M:%S").map_err(|e| format!("DateTimeOriginal \"{}\" is not a date: {}", text, e))
+        },
+      _ => Err("DateTimeOriginal is not text".to_string()),
+    }
+}
+
+
+/** get_location_data_from_neighbours
+  fn get_location_data_from_neighbours(file: &Path, search_extension: &str, window: f64, geo_options: &GeoOptions, LocationData: &mut LocationData)
+    -> Result<String, String>
+    file: &Path = path to the NEF
+    search_extension = file extension
+    window: f64 = how many seconds away a geotagged NEF can have been shot and still be used
+    geo_options: &GeoOptions = holds the geotagged NEFs we've already found in each directory
+    LocationData: &mut LocationData = pointer to a structure to hold our location data
+
+  Works out where a NEF was taken from the geotagged NEFs shot either side of it in the same directory, and returns which ones were used.
+  The GPS date and time are only filled in if the neighbours tell us how far the camera's clock was out.
+**/
+fn get_location_data_from_neighbours(file: &Path, search_extension: &str, window: f64, geo_options: &GeoOptions, LocationData: &mut LocationData)
+  -> Result<String, String>
+{
+  let taken = get_date_time_original(file)?;
+  let directory = file.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+  let mut cache = geo_options.neighbours.borrow_mut();
+  let found = cache.entry(directory.clone()).or_insert_with(||
+    {
+      let (found, unreadable) = neighbours::find(&directory, search_extension);
+      for note in unreadable
+        {
+          verbose!("{}", note);
+        }
+      found
+    });
+  let (point, used) = neighbours::position_at(found, taken, window)?;
+
+  *LocationData = LocationData::from_track_point(&point);
+  if !neighbours::has_gps_time(&used)
+    {
+      LocationData.GPSDateStamp.clear();
+      LocationData.GPSTimeStamp.clear();
+    }
+
+  Ok(neighbours::describe(&used))
+}
+
+
+/** get_camera_serial
+  fn get_camera_serial(file: &Path) -> Result<String, String>
+    file: &Path = path to the NEF
+
+  Returns the BodySerialNumber of the camera that took a photo. Not every body writes one outside its makernote, so failing that the make
+  and model will have to do, which is fine as long as we only have one of each.
+**/
+fn get_camera_serial(file: &Path) -> Result<String, String>
+{
+  let file = File::open(file).map_err(|e| format!("Could not open {:?}: {}",file.file_name(),e))?;
+  let exif = exif::Reader::new().read_from_container(&mut BufReader::new(&file)).map_err(|e| format!("Could not read the exif data: {}",e))?;
+  let text = |tag: Tag| -> Option<String>
+    {
+      match exif.get_field(tag, In::PRIMARY).map(|f| &f.value)
+        {
+          Some(Value::Ascii(text)) if !text.is_empty() =>
+            {
+              let text = String::from_utf8_lossy(&text[0]).trim_end_matches('\0').trim().to_string();
+              if text.is_empty() { None } else { Some(text) }
+            },
+          _ => None,
+        }
+    };
+
+  text(Tag::BodySerialNumber)
+    .or_else(|| text(Tag::Model).map(|model| format!("{} {}", text(Tag::Make).unwrap_or_default(), model).trim().to_string()))
+    .ok_or_else(|| "there is no BodySerialNumber, Make or Model".to_string())
+}
+
+
+/** calibrate_clock
+  fn calibrate_clock(file: &Path, reading: &str, clocks_file: &Path, i_want_to_save_changes: bool) -> Result<(), String>
+    file: &Path = path to a NEF of a GPS or phone showing the time
+    reading: &str = the time it shows
+    clocks_file: &Path = where the calibrations are kept
+    i_want_to_save_changes = save the calibration, as opposed to just showing it
+
+  Works out what has to be added to a camera's clock to get UTC, from a photo of a clock, and adds it to the camera's calibrations.
+**/
+fn calibrate_clock(file: &Path, reading: &str, clocks_file: &Path, i_want_to_save_changes: bool) -> Result<(), String>
+{
+  let serial = get_camera_serial(file)?;
+  let taken = get_date_time_original(file)?;
+  let shown = clock::parse_reading(reading, taken)?;
+  let offset = (shown - DateTime::from_naive_utc_and_offset(taken, Utc)).num_seconds();
+  let mut clocks = ClockProfiles::load(clocks_file)?;
+
+  println!("{}: the camera read {} when it was {}, so {:+} s has to be added to its clock to get UTC.",
+            serial, taken.format("%Y:%m:%d %H:%M:%S"), shown.format("%Y:%m:%d %H:%M:%S UTC"), offset);
+
+  clocks.add(&serial, Calibration { camera_time: taken, offset });
+  if let Some(drift) = clocks.drift(&serial)
+    {
+      println!("{}: going by the last two calibrations, the clock is drifting {:+.2} s a day.", serial, drift);
+    }
+
+  if i_want_to_save_changes
+    {
+      clocks.save(clocks_file)?;
+      println!("Saved to {}", clocks_file.display());
+    }
+
+  Ok(())
+}
+
+
+/** bookmark_place
+  fn bookmark_place(name: &str, file: &Path, places_file: &Path, i_want_to_save_changes: bool) -> Result<(), String>
+    name: &str = what to call the place
+    file: &Path = path to a geotagged NEF taken there
+    places_file: &Path = where the bookmarks are kept
+    i_want_to_save_changes = save the bookmark, as opposed to just showing it
+
+  Bookmarks the location of a geotagged NEF under a name, for --place to use later.
+**/
+fn bookmark_place(name: &str, file: &Path, places_file: &Path, i_want_to_save_changes: bool) -> Result<(), String>
+{
+  if !file.exists() || !try_check_if_there_is_location_data_in(file)?
+    {
+      return Err("it has no location".to_string());
+    }
+
+  let mut Location = LocationData::default();
+  try_get_location_data_from_exif(file, &mut Location)?;
+  Location.move_to_wgs84()?;
+
+  let (latitude, longitude) = match (Location.latitude(), Location.longitude())
+    {
+      (Some(latitude), Some(longitude)) => (latitude, longitude),
+      _ => return Err("its location could not be read".to_string()),
+    };
+  let mut places = Places::load(places_file)?;
+
+  if let Some(place) = places.containing(latitude, longitude).filter(|p| !p.name.eq_ignore_ascii_case(name))
+    {
+      println!("Note that it is already within the radius of {}.", place.name);
+    }
+
+  let replaced = places.add(Place { name: name.to_string(), latitude, longitude, altitude: Location.altitude(), radius: None });
+  println!("{} {}: {}", if replaced { "Moved" } else { "Bookmarked" }, name, Location.describe());
+
+  if i_want_to_save_changes
+    {
+      places.save(places_file)?;
+      println!("Saved to {}", places_file.display());
+    }
+
+  Ok(())
+}
+
+
+/** home_directory
+  fn home_directory() -> PathBuf
+
+  Returns the user's home directory, where our little settings files live, or failing that the current directory.
+**/
+fn home_directory() -> PathBuf
+{
+  env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(PathBuf::from).unwrap_or_default()
+}
+
+
+/** get_location_data_from_track
+  fn get_location_data_from_track(file: &Path, geo_options: &GeoOptions, LocationData: &mut LocationData) -> Result<TrackPoint, String>
+    file: &Path = path to the NEF
+    geo_options: &GeoOptions = holds the track, the camera clock offset and how big a gap in the track we'll put up with
+    LocationData: &mut LocationData = pointer to a structure to hold our location data
+
+  Works out when the photo was taken in UTC from DateTimeOriginal, and looks up where the track says we were at the time. What the camera's
+  clock was set to comes from, in order of preference:
+
+    --clock-offset
+    --tz, after putting the clock right by its calibration, if it has one
+    the camera's clock calibration
+    the OffsetTimeOriginal the camera recorded
+    the time zone the track was in where it starts, or where the photo turns out to have been taken if that is in a different zone
+**/
+fn get_location_data_from_track(file: &Path, geo_options: &GeoOptions, LocationData: &mut LocationData) -> Result<TrackPoint, String>
+{
+  let taken = get_date_time_original(file)?;
+  let taken_utc: DateTime<Utc>;
+
+  if let Some(offset) = geo_options.clock_offset
+    {
+      taken_utc = DateTime::from_naive_utc_and_offset(taken, Utc) + Duration::seconds(offset);
+    }
+  else if let Some(zone) = &geo_options.time_zone
+    {
+      /*
+       * The zone comes from --tz, but the calibration still knows how far out the clock is and how fast it is drifting
+       */
+      match get_clock_calibration(file, geo_options, taken)
+        {
+          Some((serial, offset)) =>
+            {
+              let error = clock::clock_error(offset);
+              if clock::clock_error_is_doubtful(error)
+                {
+                  println!("The calibration says the clock of {} is {} s out on its own zone, which is close enough to half of a quarter hour that it may be the other way out on the next zone. Check the calibration, or give --clock-offset.", serial, error);
+                }
+              verbose!("Correcting the clock of {} by {} s from its calibration, then taking it to be on {} time", serial, error, zone.name());
+              taken_utc = camera_time_to_utc(file, zone, taken + Duration::seconds(error));
+            },
+          None => taken_utc = camera_time_to_utc(file, zone, taken),
+        }
+    }
+  else if let Some((serial, offset)) = get_clock_calibration(file, geo_options, taken)
+    {
+      verbose!("Using the clock calibration of {}, {} s", serial, offset);
+      taken_utc = DateTime::from_naive_utc_and_offset(taken, Utc) + Duration::seconds(offset);
+    }
+  else if let Some(zone) = get_offset_time_original(file)
+    {
+      verbose!("Using the OffsetTimeOriginal of {}, {}", file.display(), zone.name());
+      taken_utc = camera_time_to_utc(file, &zone, taken);
+    }
+  else if let (Some(finder), Some(start)) = (&geo_options.zone_finder, geo_options.track.first())
+    {
+      /*
+       * Going by the zone the track starts in, find where we were, then make sure that was still in the same zone
+       */
+      let zone = timezone::zone_at(finder, start.latitude, start.longitude).ok_or("the track starts outside any time zone, so give --tz or --clock-offset")?;
+      let guess = geo_options.track.position_at(camera_time_to_utc(file, &zone, taken), geo_options.max_gap)?;
+
+      match timezone::zone_at(finder, guess.latitude, guess.longitude)
+        {
+          Some(there) if there.name() != zone.name() =>
+            {
+              verbose!("Taking the camera's clock to be on {} time, where {} was taken", there.name(), file.display());
+              taken_utc = camera_time_to_utc(file, &there, taken);
+            },
+          _ =>
+            {
+              verbose!("Taking the camera's clock to be on {} time, where the track starts", zone.name());
+              taken_utc = camera_time_to_utc(file, &zone, taken);
+            },
+        }
+    }
+  else
+    {
+      taken_utc = DateTime::from_naive_utc_and_offset(taken, Utc);
+    }
+
+  let point = geo_options.track.position_at(taken_utc, geo_options.max_gap)?;
+
+  *LocationData = LocationData::from_track_point(&point);
+  Ok(point)
+}
+
+
+/** get_clock_calibration
+  fn get_clock_calibration(file: &Path, geo_options: &GeoOptions, taken: NaiveDateTime) -> Option<(String, i64)>
+
+  Returns the serial number of the camera and the seconds its calibration says to add to its clock to get UTC, if it has been calibrated.
+**/
+fn get_clock_calibration(file: &Path, geo_options: &GeoOptions, taken: NaiveDateTime) -> Option<(String, i64)>
+{
+  let serial = get_camera_serial(file).ok()?;
+  let offset = geo_options.clocks.offset_at(&serial, taken)?;
+
+  Some((serial, offset))
+}
+
+
+/** camera_time_to_utc
+  fn camera_time_to_utc(file: &Path, zone: &Zone, taken: NaiveDateTime) -> DateTime<Utc>
+
+  Converts the camera's time to UTC, and grumbles if daylight saving left us guessing.
+**/
+fn camera_time_to_utc(file: &Path, zone: &Zone, taken: NaiveDateTime) -> DateTime<Utc>
+{
+  let (utc, note) = zone.to_utc(taken);
+
+  if let Some(note) = note
+    {
+      println!("{} was taken at {} {} time, which {}.", file.display(), taken.format("%Y:%m:%d %H:%M:%S"), zone.name(), note);
+    }
+
+  utc
+}
+
+
+/** get_offset_time_original
+  fn get_offset_time_original(file: &Path) -> Option<Zone>
+
+  Returns the zone the camera's clock was set to, if it recorded it in OffsetTimeOriginal (e.g. "+10:00").
+**/
+fn get_offset_time_original(file: &Path) -> Option<Zone>
+{
+  let file = File::open(file).ok()?;
+  let exif = exif::Reader::new().read_from_container(&mut BufReader::new(&file)).ok()?;
+
+  match exif.get_field(Tag::OffsetTimeOriginal, In::PRIMARY).map(|f| &f.value)
+    {
+      Some(Value::Ascii(text)) if !text.is_empty() => Zone::parse(String::from_utf8_lossy(&text[0]).trim_end_matches('\0')),
+      _ => None,
+    }
+}
+
+
+/** parse_offset
+  fn parse_offset(offset: &str) -> Option<i64>
+    offset: &str = an offset as [-][[hh:]mm:]ss, e.g. -01:30:00, 90:00 or 5400
+
+  Turns a time offset given on the command line into seconds.
+**/
+fn parse_offset(offset: &str) -> Option<i64>
+{
+  let (sign, offset) = match offset.strip_prefix('-')
+    {
+      Some(rest) => (-1, rest),
+      None => (1, offset.strip_prefix('+').unwrap_or(offset)),
+    };
+  let mut seconds: i64 = 0;
+  let parts: Vec<&str> = offset.split(':').collect();
+
+  if parts.len() > 3
+    {
+      return None;
+    }
+
+  for part in parts
+    {
+      seconds = seconds * 60 + part.trim().parse::<u32>().ok()? as i64;
+    }
+
+  Some(sign * seconds)
+}
+
+
+/** nksc_ref_from_exif
+  fn nksc_ref_from_exif(reference: &str) -> u32
+    reference: &str = an EXIF GPSLatitudeRef or GPSLongitudeRef, i.e. N, S, E or W
+
+  The sidecar doesn't store the reference as a letter, but as a Long: North is 0, South 1, East 2 and West 3.
+**/
+fn nksc_ref_from_exif(reference: &str) -> u32
+{
+  match reference
+    {
+      "S" => 1,
+      "E" => 2,
+      "W" => 3,
+      _ => 0,
+    }
+}
+
+
+/** exif_ref_from_nksc
+  fn exif_ref_from_nksc(reference: u32) -> &'static str
+    reference: u32 = the Long stored in GPSLatitudeRef or GPSLongitudeRef of a sidecar
+
+  The reverse of nksc_ref_from_exif.
+**/
+fn exif_ref_from_nksc(reference: u32) -> &'static str
+{
+  match reference
+    {
+      1 => "S",
+      2 => "E",
+      3 => "W",
+      _ => "N",
+    }
+}
+
+
+/** create_new_nksc_file
+  fn create_new_nksc_file(file: &Path, nksc: &mut NkscDocument, Location: &mut LocationData, gazetteer: Option<&Gazetteer>,
+                          i_want_to_save_the_original_file: bool) -> Result<(), String>
+
+    file: &Path = path to the sidecar file we wish to amend
+    nksc: &mut NkscDocument = the sidecar, already loaded from file
+    LocationData: &mut LocationData = pointer to a structure with our location data in it
+    gazetteer: Option<&Gazetteer> = place names to fill in the city, state and country from, if we were given any
+    i_want_to_save_the_original_file: bool = make a back up of the original file before making changes
+
+  Function will insert the location data into an nksc file and save it back to disk. Properties the sidecar already has are updated in place,
+  anything missing is added to the end of the rdf:Description. Whatever location the sidecar had before is replaced as a whole, so an altitude
+  or time left over from an old location is removed rather than left to sit alongside the new one, and no property ends up in there twice.
+  The rest of the GPS IFD the location came with is written too: heading, speed, track, bearing to the subject, satellites, status,
+  measure mode, DOP, differential correction, positioning error and processing method. The GPSMapDatum is the location's own if it has
+  one, otherwise it and the GPSVersionID already in the sidecar are kept, unless they are not what we would have written anyway. With a
+  gazetteer, the city, state, country and sublocation are written as well, and any left over from the old location are removed. Returns
+  why the sidecar couldn't be saved, if it couldn't.
+**/
+fn create_new_nksc_file(file: &Path, nksc: &mut NkscDocument, Location: &mut LocationData, gazetteer: Option<&Gazetteer>,
+                        i_want_to_save_the_original_file: bool) -> Result<(), String>
+{
+  nksc.set_value("GPSLatitudeRef", &AsValue::Long(nksc_ref_from_exif(&Location.GPSLatitudeRef)))?;
+  nksc.set_value("GPSLatitude", &AsValue::Double(Location.GPSLatitude.clone()))?;
+  nksc.set_value("GPSLongitudeRef", &AsValue::Long(nksc_ref_from_exif(&Location.GPSLongitudeRef)))?;
+  nksc.set_value("GPSLongitude", &AsValue::Double(Location.GPSLongitude.clone()))?;
+
+  if let Some(altitude) = Location.GPSAltitude
+    {
+      nksc.set_value("GPSAltitudeRef", &AsValue::Binary(vec![Location.GPSAltitudeRef]))?;
+      nksc.set_value("GPSAltitude", &AsValue::Double(vec![altitude.abs()]))?;
+    }
+  else
+    {
+      nksc.remove("GPSAltitudeRef")?;
+      nksc.remove("GPSAltitude")?;
+    }
+
+  if (!Location.GPSDateStamp.is_empty())
+    {
+      nksc.set_value("GPSDateStamp", &AsValue::Ascii(Location.GPSDateStamp.clone()))?;
+    }
+  else
+    {
+      nksc.remove("GPSDateStamp")?;
+    }
+
+  if (!Location.GPSTimeStamp.is_empty())
+    {
+      nksc.set_value("GPSTimeStamp", &AsValue::Double(Location.GPSTimeStamp.clone()))?;
+    }
+  else
+    {
+      nksc.remove("GPSTimeStamp")?;
+    }
+
+  /*
+   * The rest of the GPS IFD goes in as the same types the NEF has them, text as Ascii, rationals as Double, GPSDifferential (a short) as
+   * Long and GPSProcessingMethod (undefined bytes) as Binary. Like the altitude, anything the new location doesn't have is removed rather
+   * than left over from the old one.
+   */
+  for (name, text) in [("GPSSatellites", &Location.GPSSatellites),
+                       ("GPSMeasureMode", &Location.GPSMeasureMode),
+                       ("GPSSpeedRef", &Location.GPSSpeedRef),
+                       ("GPSTrackRef", &Location.GPSTrackRef),
+                       ("GPSImgDirectionRef", &Location.GPSImgDirectionRef),
+                       ("GPSDestBearingRef", &Location.GPSDestBearingRef),
+                       ("GPSStatus", &Location.GPSStatus)]
+    {
+      if text.is_empty()
+        {
+          nksc.remove(name)?;
+        }
+      else
+        {
+          nksc.set_value(name, &AsValue::Ascii(text.clone()))?;
+        }
+    }
+
+  for (name, number) in [("GPSDOP", Location.GPSDOP),
+                         ("GPSSpeed", Location.GPSSpeed),
+                         ("GPSTrack", Location.GPSTrack),
+                         ("GPSImgDirection", Location.GPSImgDirection),
+                         ("GPSDestBearing", Location.GPSDestBearing),
+                         ("GPSHPositioningError", Location.GPSHPositioningError)]
+    {
+      match number
+        {
+          Some(number) => nksc.set_value(name, &AsValue::Double(vec![number]))?,
+          None => { nksc.remove(name)?; },
+        }
+    }
+
+  match Location.GPSDifferential
+    {
+      Some(differential) => nksc.set_value("GPSDifferential", &AsValue::Long(differential))?,
+      None => { nksc.remove("GPSDifferential")?; },
+    }
+
+  if Location.GPSProcessingMethod.is_empty()
+    {
+      nksc.remove("GPSProcessingMethod")?;
+    }
+  else
+    {
+      nksc.set_value("GPSProcessingMethod", &AsValue::Binary(Location.GPSProcessingMethod.clone()))?;
+    }
+
+  /*
+   * Use the map datum the location came with. Failing that, keep the one the sidecar already has if it is sane, otherwise write our own.
+   * Setting them to what they already are still tidies up any duplicates, and the same goes for the version.
+   */
+  let map_datum = match nksc.get_value("GPSMapDatum")
+    {
+      _ if !Location.GPSMapDatum.is_empty() => Location.GPSMapDatum.clone(),
+      Some(Ok(AsValue::Ascii(datum))) if datum.trim().eq_ignore_ascii_case("WGS-84") || datum.trim().eq_ignore_ascii_case("WGS84") => datum,
+      _ => "WGS-84".to_string(),
+    };
+  nksc.set_value("GPSMapDatum", &AsValue::Ascii(map_datum))?;
+
+  let version_id = match nksc.get_value("GPSVersionID")
+    {
+      Some(Ok(AsValue::Binary(version))) if version.len() == 4 && version[0] == 2 => version,
+      _ => GPS_VERSION_ID.to_vec(),
+    };
+  nksc.set_value("GPSVersionID", &AsValue::Binary(version_id))?;
+
+  if let Some(gazetteer) = gazetteer
+    {
+      match Location.latitude().zip(Location.longitude()).and_then(|(latitude, longitude)| gazetteer.names_at(latitude, longitude))
+        {
+          Some(names) => write_place_names(nksc, names)?,
+          None => verbose!("{}: nowhere in the gazetteer is near enough, so the place names are left as they were", file.display()),
+        }
+    }
+
+  nksc.save(file, i_want_to_save_the_original_file)
+}
+
+
+/** write_place_names
+  fn write_place_names(nksc: &mut NkscDocument, names: PlaceNames) -> Result<(), String>
+    nksc: &mut NkscDocument = the sidecar
+    names: PlaceNames = the names of where the photo was taken
+
+  Writes the place names into the IPTC location properties NX Studio shows, photoshop:City, photoshop:State and photoshop:Country, along
+  with Iptc4xmpCore:Location for the part of town and Iptc4xmpCore:CountryCode. Anything the gazetteer has no name for is removed, so the
+  names never describe somewhere other than the location in the sidecar. Where the gazetteer has nothing at all we don't call this, and
+  whatever names the sidecar already had, perhaps typed in by hand, are left alone.
+**/
+fn write_place_names(nksc: &mut NkscDocument, names: PlaceNames) -> Result<(), String>
+{
+  for ((prefix, namespace, name), value) in place_name_properties().into_iter().zip(vec![Some(names.city), names.state, names.country,
+                                                                                          Some(names.country_code), names.sublocation])
+    {
+      match value.filter(|v| !v.is_empty())
+        {
+          Some(value) => nksc.set_text_property(prefix, namespace, name, &value)?,
+          None => nksc.remove_text_property(namespace, name)?,
+        }
+    }
+
+  Ok(())
+}
+
+
+/** remove_place_names
+  fn remove_place_names(nksc: &mut NkscDocument) -> Result<(), String>
+
+  Removes every IPTC place name from a sidecar, whoever wrote it, for when the location itself is being taken out.
+**/
+fn remove_place_names(nksc: &mut NkscDocument) -> Result<(), String>
+{
+  for (_, namespace, name) in place_name_properties()
+    {
+      nksc.remove_text_property(namespace, name)?;
+    }
+
+  Ok(())
+}
+
+
+/** place_name_properties
+  fn place_name_properties() -> Vec<(&'static str, &'static str, &'static str)>
+
+  The prefix, namespace and name of each of the IPTC place name properties, city, state, country, country code and sublocation.
+**/
+fn place_name_properties() -> Vec<(&'static str, &'static str, &'static str)>
+{
+  vec![("photoshop", PHOTOSHOP_NAMESPACE, "City"),
+       ("photoshop", PHOTOSHOP_NAMESPACE, "State"),
+       ("photoshop", PHOTOSHOP_NAMESPACE, "Country"),
+       ("Iptc4xmpCore", IPTC_CORE_NAMESPACE, "CountryCode"),
+       ("Iptc4xmpCore", IPTC_CORE_NAMESPACE, "Location")]
+}
+
+
+/** fit_name_in
+  fn fit_name_in(path: &String,nChars: usize) -> String
+    path: &String = string to truncate or pad
+    nChars: usize = number of characters to pad or truncate to
+
+  Function takes a path and number of character as a parameter and either truncate the file name to
+  fit with the space, or pads it out.
+**/
+#[allow(clippy::needless_return)]
+fn fit_name_in(path: &String,nChars: usize) -> String
+{
+  let mut me = path.clone().to_owned();
+  
+  if (me.len()>(nChars))
+    {
+      me.replace_range(..(me.len()-nChars+3), "...");
+      return me;
+    }
+  else if (me.len()<(nChars))
+    {
+      for _i in 0..(nChars)-me.len()
+        {
+          me.push(' ');
+        }
+      return me;
+    }
+    return path.to_string()
+}
+
+
+/**  WalkDirectory
+  fn WalkDirectory(WhichDirectory: &PathBuf, search_extension: &str, recursive: bool, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool,
+                 i_want_to_see_everything: bool, enable_geo_sync: bool, astro: bool, best_quality: bool, edge: bool, geo_options: &GeoOptions,
+                 enable_privacy: bool, privacy_options: &PrivacyOptions)
+
+    WhichDirectory: &PathBuf = directory to search in
+    search_extension: &str, = the file extension to look for (default .nef)
+    recursive: bool = flag to indicate if a recursive search is to be performed
+    i_want_to_save_changes: bool = flag to indicate if we are doing a "listing" run without actually processing teh file
+    i_want_to_save_the_original_file: bool = flag to indicate if we wish to save a back up of the original file before modifying it
+    i_want_to_see_everything: bool = flag which basically says if I want to see all files, whether they are in sync or not.
+    enable_geo_sync: bool = let it do the geo sync
+    astro: bool = process astro flag
+    best_quality: bool = process best_quality flag
+    edge: bool = set edge noise reduction on
+    geo_options: &GeoOptions = settings for the geo sync beyond simply filling in missing locations
+    enable_privacy: bool = strip or coarsen the locations in the sidecars
+    privacy_options: &PrivacyOptions = which of those to do
+
+  Function which walks through a given directory and basically does all of the work.
+*/
+#[allow(clippy::too_many_arguments, clippy::bool_comparison)]
+fn WalkDirectory(WhichDirectory: &PathBuf, search_extension: &str, recursive: bool, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool,
+                 i_want_to_see_everything: bool, enable_geo_sync: bool,astro: bool, best_quality: bool, edge: bool, geo_options: &GeoOptions,
+                 enable_privacy: bool, privacy_options: &PrivacyOptions)
+{
+  if WhichDirectory.is_dir() // sanity check, probably not necessary, but this is Rust and Rust is all about "safety"
+    {
+      let paths = fs::read_dir(WhichDirectory).expect("Could not scan the directory");
+
+      println!("Processing: {}", WhichDirectory.display());
+      for each_path in paths
+        {
+          let nef_path = each_path.unwrap();
+
+          if (nef_path.path().is_file())
+            {
+              if (enable_geo_sync==true)
+                {
+                  geo_sync_a_file(&nef_path.path().to_path_buf(), search_extension, i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything,geo_options);
+                }
+
+              if enable_privacy
+                {
+                  privacy_a_file(&nef_path.path().to_path_buf(), search_extension, i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything,privacy_options);
+                }
+              
+              if (astro==true || best_quality==true || edge==true)
+                {
+                  set_noise_reduction_in_a_file(&nef_path.path().to_path_buf(), search_extension, i_want_to_save_changes,
+                                                  i_want_to_save_the_original_file,i_want_to_see_everything, astro, best_quality, edge);
+                }
+            }
+          else // Directory
+            {
+              if (recursive == true) && (nef_path.path().file_stem() != Some(OsStr::new("NKSC_PARAM")))
+                {
+                  verbose!("DIR: {}", nef_path.path().display());
+                  WalkDirectory(&nef_path.path().to_path_buf(), search_extension, recursive, i_want_to_save_changes,i_want_to_save_the_original_file,
+                                i_want_to_see_everything,enable_geo_sync,astro,best_quality, edge, geo_options, enable_privacy, privacy_options);
+                }
+            }
+        }
+    }
+  else
+    {
+      println!("Something went gravely wrong: {:?}", WhichDirectory.file_name());
+    }
+}
+
+/**  geo_sync_a_file
+  fn geo_sync_a_file(nef_path: &PathBuf, search_extension: &str, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, i_want_to_see_everything: bool,
+                     geo_options: &GeoOptions)
+
+    nef_path = path to file 
+    search_extension = file extension 
+    i_want_to_save_changes = save changes, as opposed to just walking through the files and seeing what is going on inside them
+    i_want_to_save_the_original_file = make a back up of the original file before making changes
+    i_want_to_see_everything = be quite verbose in the information we print out
+    geo_options = settings for the geo sync beyond simply filling in missing locations
+
+  Function which processes an individual file.
+*/
+#[allow(clippy::needless_borrow)]
+fn geo_sync_a_file(nef_path: &PathBuf, search_extension: &str, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, i_want_to_see_everything: bool,
+                   geo_options: &GeoOptions)
+{
+  let mut column_width:usize = 39;
+  
+  /*
+   * Work out how wide our terminal is, so we can use as much real estate as we possibly can 
+   */
+
+    if let Some((w, _h)) = term_size::dimensions() 
+      {
+        column_width=(w-10)/2;
+      } 
+
+  /*
+   * Process the Nikon Sidecar File and the NEF to see if there is any GEO data and sync them if there is
+   */
+  if (format!(".{:?}",nef_path.extension().expect("Hoped to find some NEF files, but I could not.")).to_lowercase().replace("\\\\","\\").replace("\"","")==search_extension)
+    {
+      let mut nksc_path:String=format!("{:?}\\\\NKSC_PARAM\\\\{:?}.nksc",nef_path.parent().unwrap(), nef_path.file_name().unwrap());
+      nksc_path=nksc_path.replace("\\\\","\\").replace("\"","");
+      let nksc_Path=Path::new(&nksc_path);
+
+      if nksc_Path.exists()
+        {
+          let mut Location = LocationData::default();
+          let nef:String=format!("{}",nef_path.display());
+                    
+          /*
+            * Check to see if the nksc file doesn't already have location data, if it doesn't, then we will
+            * see if there is any location data in the NEF file, if there is, we will then try to extract
+            * that data and update the nksc file with the data from the exif tag in the NEF.
+            */
+
+          let mut nksc = match NkscDocument::load(nksc_Path)
+            {
+              Ok(nksc) => nksc,
+              Err(e) => { println!("{}", e); return; }
+            };
+          let there_is_location_data_in_nksc:bool = nksc.has("GPSLatitude");
+
+          for property in nksc.properties().iter().filter(|p| p.name.starts_with("GPS"))
+            {
+              match nksc.get_value(&property.name)
+                {
+                  Some(Ok(value)) => verbose!("{}: {:?}", property.name, value),
+                  _ => verbose!("{}: {} ({}) could not be decoded", property.name, property.value, property.astype),
+                }
+            }
+          let there_is_location_data_in_nef:bool = check_if_there_is_location_data_in(&nef_path);
+
+          /*
+           * Going the other way, the location NX Studio put in the NKSC is copied out to where other programs can see it
+           */
+          if geo_options.to_xmp || geo_options.to_nef
+            {
+              write_back_a_file(nef_path, &nef, &nksc_path, &nksc, there_is_location_data_in_nef, geo_options, i_want_to_save_changes,
+                                i_want_to_save_the_original_file, i_want_to_see_everything, column_width);
+              return;
+            }
+
+          /*
+           * A location given on the command line goes on everything, whatever the NEF says
+           */
+          let mut there_is_a_given_location:bool = false;
+
+          if let Some(given) = &geo_options.given_location
+            {
+              if !there_is_location_data_in_nksc || geo_options.force
+                {
+                  Location = given.clone();
+                  there_is_a_given_location = true;
+                }
+            }
+
+          /*
+           * If the NEF doesn't have a location of its own, see if the track logs can tell us where it was taken
+           */
+          let mut there_is_location_data_in_track:bool = false;
+
+          if !there_is_location_data_in_nef && !there_is_a_given_location && (!geo_options.track.is_empty())
+             && (!there_is_location_data_in_nksc || geo_options.force)
+            {
+              match get_location_data_from_track(nef_path, geo_options, &mut Location)
+                {
+                  Ok(point) =>
+                    {
+                      verbose!("{} matched the track at {}", nef, point.time.format("%Y-%m-%d %H:%M:%S UTC"));
+                      there_is_location_data_in_track = true;
+                    },
+                  Err(e) => verbose!("{}: {}", nef, e),
+                }
+            }
+
+          /*
+           * Failing that, see if the geotagged frames either side of it can
+           */
+          let mut filled_from = String::new();
+
+          if let Some(window) = geo_options.fill_window
+            {
+              if !there_is_location_data_in_nef && !there_is_a_given_location && !there_is_location_data_in_track
+                 && (!there_is_location_data_in_nksc || geo_options.force)
+                {
+                  match get_location_data_from_neighbours(nef_path, search_extension, window, geo_options, &mut Location)
+                    {
+                      Ok(used) => filled_from = used,
+                      Err(e) => verbose!("{}: {}", nef, e),
+                    }
+                }
+            }
+          let there_is_location_data_in_neighbours:bool = !filled_from.is_empty();
+
+          let there_is_a_location_to_write:bool = there_is_location_data_in_nef || there_is_a_given_location || there_is_location_data_in_track
+                                                  || there_is_location_data_in_neighbours;
+
+          if !there_is_location_data_in_nksc || (geo_options.force && there_is_a_location_to_write)
+            {
+              if (there_is_a_location_to_write)||(i_want_to_see_everything)
+                  {
+                    print!("Geo:  {}  ",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nksc_path,column_width)));
+                  }
+
+              if there_is_a_location_to_write
+                {
+                  let mut datum_note = String::new();
+
+                  if there_is_location_data_in_nef && !there_is_a_given_location
+                    {
+                      get_location_data_from_exif(&nef_path,&mut Location);
+
+                      /*
+                       * Locations on an old map datum are moved onto WGS-84, which is what everything reading the sidecar expects
+                       */
+                      match Location.move_to_wgs84()
+                        {
+                          Ok(Some(from)) => datum_note = format!("Converted from the {} datum to WGS-84", from),
+                          Ok(None) => {},
+                          Err(e) => datum_note = format!("Warning: {}, the location is written as it is and labelled with it", e),
+                        }
+                    }
+
+                  /*
+                   * Locations that can't be real, 0° 0', rationals divided by zero, fixes left over from hours before, are kept out of the NKSC
+                   */
+                  let taken = if there_is_location_data_in_nef && !there_is_a_given_location { get_date_time_original(nef_path).ok() } else { None };
+                  let camera_zone = if taken.is_some() { get_offset_time_original(nef_path) } else { None };
+                  let problems = validate::problems(&Location, taken, geo_options.time_zone.as_ref().or(camera_zone.as_ref()));
+                  let rejected = !problems.is_empty() && !geo_options.unchecked;
+
+                  /*
+                   * A location with no altitude gets the height of the ground there, if we have the elevation tiles for it
+                   */
+                  let mut altitude_from = String::new();
+
+                  if let (false, None, Some(dem), Some(latitude), Some(longitude)) = (rejected, Location.GPSAltitude, &geo_options.dem, Location.latitude(), Location.longitude())
+                    {
+                      match dem.elevation_at(latitude, longitude)
+                        {
+                          Ok((altitude, tile)) =>
+                            {
+                              Location.set_altitude(Some(altitude));
+                              altitude_from = tile;
+                            },
+                          Err(e) => verbose!("{}: no altitude, {}", nef, e),
+                        }
+                    }
+
+                  if rejected
+                    {
+                      println!("{}",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nef,column_width)));
+                    }
+                  else if i_want_to_save_changes
+                    {
+                      print!("{}",Colour::Black.on(Colour::Yellow).paint(fit_name_in(&nef,column_width)));
+
+                      let saved = create_new_nksc_file(&nksc_Path,&mut nksc,&mut Location, geo_options.gazetteer.as_ref(), i_want_to_save_the_original_file);
+
+                      for _i in 0..(column_width*2)+2 {print!("\x08")}; // Erase the contents of the line from the screen
+                      match saved
+                        {
+                          Ok(()) => println!("Geo:  {}  {}",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nksc_path,column_width)),Colour::Blue.on(Colour::Green).paint(fit_name_in(&nef,column_width))),
+                          Err(e) => println!("Geo:  {}  {}\n        Could not update the NKSC: {}",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nksc_path,column_width)),Colour::Blue.on(Colour::Green).paint(fit_name_in(&nef,column_width)), e),
+                        }
+                    }
+                  else
+                    {
+                      println!("{}",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nef,column_width)));
+                    }
+
+                  if there_is_location_data_in_neighbours
+                    {
+                      println!("        Filled from {}", filled_from);
+                    }
+
+                  if !altitude_from.is_empty()
+                    {
+                      println!("        Altitude from {}", altitude_from);
+                    }
+
+                  if !datum_note.is_empty()
+                    {
+                      println!("        {}", datum_note);
+                    }
+
+                  for problem in &problems
+                    {
+                      println!("        {}: {}", if rejected { "Rejected" } else { "Warning" }, problem);
+                    }
+
+                  if let Some(names) = geo_options.gazetteer.as_ref().filter(|_| !rejected).zip(Location.latitude().zip(Location.longitude()))
+                                                        .and_then(|(g, (latitude, longitude))| g.names_at(latitude, longitude))
+                    {
+                      println!("        Place: {}", names.describe());
+                    }
+                }
+              else
+                {
+                  if (there_is_a_location_to_write)||(i_want_to_see_everything)
+                      {
+                      println!("{}",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nef,column_width)));
+                      }
+                }
+            }
+          else if (there_is_location_data_in_nef) && (geo_options.check_conflicts) // both have location data, but do they agree?
+            {
+              get_location_data_from_exif(&nef_path,&mut Location);
+              if let Err(e) = Location.move_to_wgs84()
+                {
+                  verbose!("{}: {}", nef, e);
+                }
+              check_for_a_conflict(&nef, &nksc_path, &mut nksc, &mut Location, geo_options, i_want_to_save_changes, i_want_to_save_the_original_file,
+                                   i_want_to_see_everything, column_width);
+            }
+          else  // there IS already location data in the NKSC_PARAM file
+            {
+              if (!there_is_location_data_in_nef)||(i_want_to_see_everything)
+                {
+                  print!("Geo:  {}  ",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nksc_path,column_width)));
+                  get_location_data_from_exif(&nef_path,&mut Location);
+                  let _ = Location.move_to_wgs84();                            // only for showing, a bad datum is reported when it is written
+                  if there_is_location_data_in_nef
+                    {
+                      println!("{}",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nef,column_width)));
+                    }
+                  else
+                    {
+                      println!("{}",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nef,column_width)));
+                    }
+                }
+            }
+
+          /*
+           * If we are only listing, show what is actually stored in each of the files rather than just whether something is stored
+           */
+          let forced = there_is_location_data_in_nksc && there_is_a_location_to_write && geo_options.force;
+          let conflict_checked = there_is_location_data_in_nksc && there_is_location_data_in_nef && geo_options.check_conflicts && !forced;
+
+          if !i_want_to_save_changes && !conflict_checked && ((there_is_a_location_to_write!=there_is_location_data_in_nksc)||(forced)||(i_want_to_see_everything))
+            {
+              let mut nksc_Location = LocationData::default();
+
+              if there_is_a_given_location
+                {
+                  println!("        Given:{}", Location.describe());
+                }
+              else if there_is_location_data_in_nef
+                {
+                  println!("        NEF:  {}", Location.describe());
+                }
+              else if there_is_location_data_in_track
+                {
+                  println!("        Track:{}", Location.describe());
+                }
+              else if there_is_location_data_in_neighbours
+                {
+                  println!("        Frames:{}", Location.describe());
+                }
+              else
+                {
+                  println!("        NEF:  no location data");
+                }
+
+              match get_location_data_from_nksc(&nksc, &mut nksc_Location)
+                {
+                  Ok(()) => println!("        NKSC: {}", nksc_Location.describe()),
+                  Err(e) => println!("        NKSC: could not decode the location data ({})", e),
+                }
+            }
+        }
+    }
+}
+
+/**  check_for_a_conflict
+  fn check_for_a_conflict(nef: &str, nksc_path: &str, nksc: &mut NkscDocument, Location: &mut LocationData, geo_options: &GeoOptions,
+                          i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, i_want_to_see_everything: bool, column_width: usize)
+
+    nef = path to the NEF
+    nksc_path = path to the sidecar
+    nksc = the sidecar, already loaded
+    Location = the location already read from the NEF
+    geo_options = tolerances and which way to settle a conflict
+    i_want_to_save_changes = settle the conflict, as opposed to just reporting it
+    i_want_to_save_the_original_file = make a back up of the original file before making changes
+    i_want_to_see_everything = show the pair even if they agree
+    column_width = how wide to print the file names
+
+  Called when both the NEF and the NKSC have a location. Works out how far apart the two are and, if that is more than the tolerance (or
+  their altitudes differ by more than the altitude tolerance, the same one unless we were given another), reports it and (if asked to) overwrites one with the other. This happens when a NEF gets re-geotagged in GeoSetter after NX Studio has
+  already written a location to the sidecar.
+*/
+#[allow(clippy::too_many_arguments)]
+fn check_for_a_conflict(nef: &str, nksc_path: &str, nksc: &mut NkscDocument, Location: &mut LocationData, geo_options: &GeoOptions,
+                        i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, i_want_to_see_everything: bool, column_width: usize)
+{
+  let mut nksc_Location = LocationData::default();
+
+  if let Err(e) = get_location_data_from_nksc(nksc, &mut nksc_Location)
+    {
+      println!("Geo:  {}  could not decode the location data ({})", Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nksc_path.to_string(),column_width)), e);
+      return;
+    }
+
+  let (distance, altitude_difference) = match (Location.latitude(), Location.longitude(), nksc_Location.latitude(), nksc_Location.longitude())
+    {
+      (Some(nef_latitude), Some(nef_longitude), Some(nksc_latitude), Some(nksc_longitude)) =>
+        {
+          (geo::distance_between(nef_latitude, nef_longitude, nksc_latitude, nksc_longitude),
+           match (Location.altitude(), nksc_Location.altitude())
+             {
+               (Some(nef_altitude), Some(nksc_altitude)) => (nef_altitude - nksc_altitude).abs(),
+               _ => 0.0,
+             })
+        },
+      (Some(_), Some(_), _, _) =>
+        {
+          println!("Geo:  {}  the position in the NKSC can't be read, so it can't be compared", Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nksc_path.to_string(),column_width)));
+          return;
+        },
+      _ =>
+        {
+          println!("Geo:  {}  the position in the NEF can't be read, so it can't be compared", Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nef.to_string(),column_width)));
+          return;
+        },
+    };
+
+  verbose!("{} is {:.1} m from its sidecar, altitude differs by {:.1} m", nef, distance, altitude_difference);
+
+  if (distance <= geo_options.tolerance) && (altitude_difference <= geo_options.altitude_tolerance.unwrap_or(geo_options.tolerance))
+    {
+      if i_want_to_see_everything
+        {
+          println!("Geo:  {}  {}",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nksc_path.to_string(),column_width)),
+                                  Colour::Blue.on(Colour::Green).paint(fit_name_in(&nef.to_string(),column_width)));
+        }
+      return;
+    }
+
+  println!("Geo:  {}  {}",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nksc_path.to_string(),column_width)),
+                          Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nef.to_string(),column_width)));
+  println!("        Conflict: {:.1} m apart, altitude differs by {:.1} m", distance, altitude_difference);
+  println!("        NEF:  {}", Location.describe());
+  println!("        NKSC: {}", nksc_Location.describe());
+
+  if i_want_to_save_changes
+    {
+      if geo_options.prefer_nef
+        {
+          let taken = get_date_time_original(Path::new(nef)).ok();
+          let camera_zone = get_offset_time_original(Path::new(nef));
+
+          if report_problems(&validate::problems(Location, taken, geo_options.time_zone.as_ref().or(camera_zone.as_ref())), geo_options.unchecked)
+            {
+              match create_new_nksc_file(Path::new(nksc_path), nksc, Location, geo_options.gazetteer.as_ref(), i_want_to_save_the_original_file)
+                {
+                  Ok(()) => println!("        NKSC updated from the NEF"),
+                  Err(e) => println!("        Could not update the NKSC: {}", e),
+                }
+            }
+        }
+      else if geo_options.prefer_nksc && report_problems(&validate::problems(&nksc_Location, None, None), geo_options.unchecked)
+        {
+          match nef::write_location(Path::new(nef), &nksc_Location, i_want_to_save_the_original_file)
+            {
+              Ok(skipped) if skipped.is_empty() => println!("        NEF updated from the NKSC"),
+              Ok(skipped) => println!("        NEF updated from the NKSC, but it has nowhere to put {}", skipped.join(", ")),
+              Err(e) => println!("        Could not update the NEF: {}", e),
+            }
+        }
+    }
+}
+
+
+/** write_back_a_file
+  fn write_back_a_file(nef_path: &Path, nef: &str, nksc_path: &str, nksc: &NkscDocument, there_is_location_data_in_nef: bool, geo_options: &GeoOptions,
+                       i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, i_want_to_see_everything: bool, column_width: usize)
+
+    nef_path = path to the NEF
+    nef = the same, for printing
+    nksc_path = path to the sidecar
+    nksc = the sidecar, already loaded
+    there_is_location_data_in_nef = the NEF already has a location of its own
+    geo_options = whether to write the .xmp sidecar, the NEF or both, and whether to overwrite a location already there
+    i_want_to_save_changes = write the location, as opposed to just listing what would be written
+    i_want_to_save_the_original_file = make a back up of each file before changing it
+    i_want_to_see_everything = show the files which already have a location too
+    column_width = how wide to print the file names
+
+  The reverse of the geo sync: the location in the NKSC, typically put there by dropping the photo on NX Studio's map, is decoded and
+  written to the .xmp sidecar and/or the NEF. Like the geo sync, a file that already has a location is left alone unless --force is given.
+  A NEF with a position has its GPS tags overwritten in place, and one without gets a new GPS IFD added to the end, see nef.rs.
+**/
+#[allow(clippy::too_many_arguments)]
+fn write_back_a_file(nef_path: &Path, nef: &str, nksc_path: &str, nksc: &NkscDocument, there_is_location_data_in_nef: bool, geo_options: &GeoOptions,
+                     i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, i_want_to_see_everything: bool, column_width: usize)
+{
+  let nksc_path = nksc_path.to_string();
+
+  if !nksc.has("GPSLatitude")
+    {
+      if i_want_to_see_everything
+        {
+          println!("Geo:  {}  {}",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nksc_path,column_width)),
+                                  Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nef.to_string(),column_width)));
+        }
+      return;
+    }
+
+  let mut nksc_Location = LocationData::default();
+
+  if let Err(e) = get_location_data_from_nksc(nksc, &mut nksc_Location)
+    {
+      println!("Geo:  {}  could not decode the location data ({})", Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nksc_path,column_width)), e);
+      return;
+    }
+
+  let problems = validate::problems(&nksc_Location, None, None);
 
   /*
-   * Read the GPSAltitude from the exif data and convert it from a single f64 into a padded BASE64 value
+   * Each place the location can go: the .xmp sidecars, then the NEF itself
    */
-  if let Some(field) = exif.get_field(Tag::GPSAltitude, In::PRIMARY)
+  let mut destinations: Vec<(String, LocationData)> = Vec::new();
+
+  if geo_options.to_xmp
     {
-      match field.value
+      for sidecar in xmp::sidecars_for(nef_path)
         {
-          Value::Rational(ref Altitude) =>
+          match xmp::read_location(&sidecar)
             {
-              let raw_bytes0: [u8; 8] = unsafe { std::mem::transmute(Altitude[0].to_f64()) };
-              let b64_00 = BASE64.encode(&raw_bytes0);
-              LocationData.GPSAltitude=String::from(format!("{}", b64_00));
-              unsafe
-                {
-                  if (VERBOSE==true)
-                    {
-                      let GPSAltitude: f64=Altitude[0].to_f64();
-                      println!("Field Value: {:?}\nGPSAltitude: {}", field.value,GPSAltitude);
-                      println!("GPSAltitude BASE64: {}", LocationData.GPSAltitude);
-                    }
-                }
-            },
-          _ => {},
+              Ok(existing) => destinations.push((sidecar.display().to_string(), existing)),
+              Err(e) => println!("Geo:  {}  could not read the location ({})", Colour::Yellow.on(Colour::Red).paint(fit_name_in(&sidecar.display().to_string(),column_width)), e),
+            }
         }
     }
 
-
-  /*
-   * Read the GPSDateStamp
-   */
-  if let Some(field) = exif.get_field(Tag::GPSDateStamp, In::PRIMARY)
+  if geo_options.to_nef
     {
-      LocationData.GPSDateStamp=String::from(format!("{}",field.display_value()).replace("-",":"));
-      verbose!("\nGPSDateStamp: {}", LocationData.GPSDateStamp);
-    }
+      let mut existing = LocationData::default();
 
+      match if there_is_location_data_in_nef { try_get_location_data_from_exif(nef_path, &mut existing) } else { Ok(()) }
+        {
+          Ok(()) => destinations.push((nef.to_string(), existing)),
+          Err(e) => println!("Geo:  {}  could not read the location ({})", Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nef.to_string(),column_width)), e),
+        }
+    }
 
-  /*
-   * Read the GPSTimeStamp from the exif data and convert it to an un-padded BASE64 value
-   */
-  if let Some(field) = exif.get_field(Tag::GPSTimeStamp, In::PRIMARY)
+  for (destination, existing) in destinations
     {
-      match field.value
+      if existing.latitude().is_some() && !geo_options.force
         {
-          Value::Rational(ref TimeStamp) =>
+          if i_want_to_see_everything
             {
-              let mut raw_bytes: [u8; 24]= [0;24];
-              let raw_bytes0: [u8; 8] = unsafe { std::mem::transmute(TimeStamp[0].to_f64()) };
-              let raw_bytes1: [u8; 8] = unsafe { std::mem::transmute(TimeStamp[1].to_f64()) };
-              let raw_bytes2: [u8; 8] = unsafe { std::mem::transmute(TimeStamp[2].to_f64()) };
+              println!("Geo:  {}  {}",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nksc_path,column_width)),
+                                      Colour::Blue.on(Colour::Green).paint(fit_name_in(&destination,column_width)));
+            }
+          continue;
+        }
 
-              for i in 0..8
-                {
-                  raw_bytes[i]=raw_bytes0[i];
-                  raw_bytes[i+8]=raw_bytes1[i];
-                  raw_bytes[i+16]=raw_bytes2[i];
-                }
+      println!("Geo:  {}  {}",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nksc_path,column_width)),
+                              Colour::Yellow.on(Colour::Red).paint(fit_name_in(&destination,column_width)));
 
-              let b64=BASE64_NOPAD.encode(&raw_bytes);
-              LocationData.GPSTimeStamp=String::from(format!("{}",b64));
+      if !report_problems(&problems, geo_options.unchecked)
+        {
+          continue;
+        }
 
-              unsafe
-                {
-                  if (VERBOSE==true)
-                    {
-                      println!("GPSTimeStamp BASE64: {}", LocationData.GPSTimeStamp);
-                    }
-                }
-            },
-          _ => {},
+      if !i_want_to_save_changes
+        {
+          println!("        NKSC: {}", nksc_Location.describe());
+          if existing.latitude().is_some()
+            {
+              println!("        Was:  {}", existing.describe());
+            }
+        }
+      else if destination == nef
+        {
+          match nef::write_location(nef_path, &nksc_Location, i_want_to_save_the_original_file)
+            {
+              Ok(skipped) if skipped.is_empty() => println!("        NEF updated from the NKSC"),
+              Ok(skipped) => println!("        NEF updated from the NKSC, but it has nowhere to put {}", skipped.join(", ")),
+              Err(e) => println!("        Could not update the NEF: {}", e),
+            }
+        }
+      else
+        {
+          match xmp::write_location(Path::new(&destination), &nksc_Location, i_want_to_save_the_original_file)
+            {
+              Ok(()) => println!("        XMP sidecar updated from the NKSC"),
+              Err(e) => println!("        Could not update the XMP sidecar: {}", e),
+            }
         }
     }
-
 }
 
 
-/** create_new_nksc_file
-  fn create_new_nksc_file(file: &Path, LocationData: &mut LocationData)
+/** report_problems
+  fn report_problems(problems: &[String], unchecked: bool) -> bool
+    problems = what validate::problems found wrong with a location
+    unchecked = write it anyway
 
-    file: &Path = path to the sidecar file we wish to amend
-    LocationData: &mut LocationData = pointer to a structure with our location data in it
+  Prints what is wrong with a location we are about to copy, and returns true if it should still be copied.
+**/
+fn report_problems(problems: &[String], unchecked: bool) -> bool
+{
+  for problem in problems
+    {
+      println!("        {}: {}", if unchecked { "Warning" } else { "Rejected" }, problem);
+    }
+
+  problems.is_empty() || unchecked
+}
+
+
+/**  privacy_a_file
+  fn privacy_a_file(nef_path: &Path, search_extension: &str, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool,
+                    i_want_to_see_everything: bool, privacy_options: &PrivacyOptions)
 
-  Function will open up an existing nksc file and insert into it the update location data.
-  Although the nksc is an XML file and I could probably have used an XML library for writing the data, it is such a basic and small file format
-  that we are just going to open it up into memory and make the changes there then save it back to disk.
+    nef_path = path to file
+    search_extension = file extension
+    i_want_to_save_changes = save changes, as opposed to just walking through the files and seeing what is going on inside them
+    i_want_to_save_the_original_file = make a back up of the original file before making changes
+    i_want_to_see_everything = show the sidecars which are left alone too
+    privacy_options = whether to strip, fence off or coarsen the location
+
+  Takes the location out of a sidecar, or makes it vaguer, before the NEFs and their sidecars are handed to someone else. Stripping
+  removes every ast:GPS property along with the place names, which give the location away just as well. Inside a geofence the location
+  is stripped, anywhere else it is rounded if a precision was given, and the altitude, heading, bearing and sublocation, any of which
+  could narrow it down again, are removed. So are the GPS time, speed and course: matched against a track published elsewhere the time
+  gives the exact position back, and from the photo before or after it the speed and course do. A location we can't read is stripped rather than chance it being inside a fence. The NEF is
+  left alone, whatever it has in it.
 **/
-fn create_new_nksc_file(file: &Path, Location: &mut LocationData, i_want_to_save_the_original_file: bool, there_is_a_GPSVersionID_in_nksc: bool)
-{
-  let mut fr = File::open(file).expect("Could not open file.");
-  let mut nksc = String::new();
-
-  fr.read_to_string(&mut nksc).expect("Unable to read from the file");
-  drop(fr);
-
-
-  let idx = nksc.find("</rdf:Description>").expect("Could not find a valid XML tag to hook in to."); // find the end of the nksc XML data, where we will insert our new fragment
-
-  if (idx>0)
-    {
-      for i in (0..idx).rev() // The tag we found before will be indented, so walk back to the start of the line and then insert out new stuff there
-        {
-          if (nksc.chars().nth(i)==Some('\n'))
-            {
-              let mut xml:String=format!("\n        <ast:GPSLatitudeRef rdf:parseType=\"Resource\">\n\
-                                          \x20           <rdf:value>{}</rdf:value>\n\
-                                          \x20           <astype:Type>Long</astype:Type>\n\
-                                          \x20        </ast:GPSLatitudeRef>\n\
-                                          \x20        <ast:GPSLatitude rdf:parseType=\"Resource\">\n\
-                                          \x20           <rdf:value>{}</rdf:value>\n\
-                                          \x20           <astype:Type>Double</astype:Type>\n\
-                                          \x20        </ast:GPSLatitude>\n\
-                                          \x20        <ast:GPSLongitudeRef rdf:parseType=\"Resource\">\n\
-                                          \x20           <rdf:value>{}</rdf:value>\n\
-                                          \x20           <astype:Type>Long</astype:Type>\n\
-                                          \x20        </ast:GPSLongitudeRef>\n\
-                                          \x20        <ast:GPSLongitude rdf:parseType=\"Resource\">\n\
-                                          \x20           <rdf:value>{}</rdf:value>\n\
-                                          \x20           <astype:Type>Double</astype:Type>\n\
-                                          \x20        </ast:GPSLongitude>\n\
-                                          \x20        <ast:GPSMapDatum rdf:parseType=\"Resource\">\n\
-                                          \x20           <rdf:value>WGS-84</rdf:value>\n\
-                                          \x20           <astype:Type>Ascii</astype:Type>\n\
-                                          \x20        </ast:GPSMapDatum>",
-                                          Location.GPSLatitudeRef,
-                                          Location.GPSLatitude,
-                                          Location.GPSLongitudeRef,
-                                          Location.GPSLongitude);
-
-              if (Location.GPSAltitude!="")
-                {
-                  xml.push_str(&format!("\n         <ast:GPSAltitudeRef rdf:parseType=\"Resource\">\n\
-                                        \x20           <rdf:value>AA==</rdf:value>\n\
-                                        \x20           <astype:Type>Binary</astype:Type>\n\
-                                        \x20        </ast:GPSAltitudeRef>\n\
-                                        \x20        <ast:GPSAltitude rdf:parseType=\"Resource\">\n\
-                                        \x20           <rdf:value>{}</rdf:value>\n\
-                                        \x20           <astype:Type>Double</astype:Type>\n\
-                                        \x20        </ast:GPSAltitude>",Location.GPSAltitude));
-                }
+fn privacy_a_file(nef_path: &Path, search_extension: &str, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool,
+                  i_want_to_see_everything: bool, privacy_options: &PrivacyOptions)
+{
+  let mut column_width:usize = 39;
 
-              if (Location.GPSDateStamp!="")
-                {
-                  xml.push_str(&format!("\n         <ast:GPSDateStamp rdf:parseType=\"Resource\">\n\
-                                        \x20           <rdf:value>{}</rdf:value>\n\
-                                        \x20           <astype:Type>Ascii</astype:Type>\n\
-                                        \x20        </ast:GPSDateStamp>",Location.GPSDateStamp));
-                }
+  if let Some((w, _h)) = term_size::dimensions()
+    {
+      column_width=w.saturating_sub(24).max(3);                                // fit_name_in needs room for its "..."
+    }
 
-              if (Location.GPSTimeStamp!="")
-                {
-                  xml.push_str(&format!("\n        <ast:GPSTimeStamp rdf:parseType=\"Resource\">\n\
-                                        \x20           <rdf:value>{}</rdf:value>\n\
-                                        \x20           <astype:Type>Double</astype:Type>\n\
-                                        \x20        </ast:GPSTimeStamp>",Location.GPSTimeStamp));
-                }
-                
-              if there_is_a_GPSVersionID_in_nksc==false
-                {
-                  xml.push_str("\n         <ast:GPSVersionID rdf:parseType=\"Resource\">\n\
-                                       \x20           <rdf:value>AgIAAA==</rdf:value>\n\
-                                       \x20           <astype:Type>Binary</astype:Type>\n\
-                                       \x20        </ast:GPSVersionID>");
-                }
+  if (format!(".{:?}",nef_path.extension().expect("Hoped to find some NEF files, but I could not.")).to_lowercase().replace("\\\\","\\").replace("\"","")!=search_extension)
+    {
+      return;
+    }
 
-              nksc.insert_str(i+1,&xml);
-              break;
-            }
-        }
+  let mut nksc_path:String=format!("{:?}\\\\NKSC_PARAM\\\\{:?}.nksc",nef_path.parent().unwrap(), nef_path.file_name().unwrap());
+  nksc_path=nksc_path.replace("\\\\","\\").replace("\"","");
+  let nksc_Path=Path::new(&nksc_path);
+
+  if !nksc_Path.exists()
+    {
+      return;
     }
 
-  /*
-   * Back up the old file next
-   * But if there is already a backup, we won't. The logic is the original backup will be the original file, and I don't really
-   * want to loose the original original file. Besides, this isn't the sort of thing that we'd be doing more than once anyway.
-   */
+  let mut nksc = match NkscDocument::load(nksc_Path)
+    {
+      Ok(nksc) => nksc,
+      Err(e) => { println!("{}", e); return; }
+    };
+  let gps_properties: Vec<String> = nksc.properties().iter().filter(|p| p.name.starts_with("GPS")).map(|p| p.name.clone()).collect();
 
-  if i_want_to_save_the_original_file
+  if gps_properties.is_empty()
     {
-      let backup:String=format!("{}.original",file.display());
-      let backup_Path=Path::new(&backup);
-      if (!backup_Path.exists())
+      if i_want_to_see_everything
         {
-          fs::rename(file.to_str().unwrap(), backup).expect("backing up a file failed");
+          println!("Privacy:              {} ",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nksc_path,column_width)));
         }
+      return;
     }
 
+  let mut Location = LocationData::default();
+  let (position, unreadable) = match get_location_data_from_nksc(&nksc, &mut Location)
+    {
+      Ok(()) => (Location.latitude().zip(Location.longitude()), None),
+      Err(e) => (None, Some(e)),
+    };
+
   /*
-   * Write the contents of our reformatted buffer to disk
+   * Work out what to do: strip it, strip it because it is inside a fence (or we can't tell whether it is), round it, or leave it be.
+   * Rounding the position is no use if the altitude, the heading, the sublocation or the time, speed and course still pin it down, so
+   * those go too.
    */
-  let mut output = File::create(format!("{}",file.display())).expect("Create file failed");
-  output.write_all(nksc.as_bytes()).expect("write failed");
-  drop(output);
-}
-
-
-/** fit_name_in
-  fn fit_name_in(path: &String,nChars: usize) -> String
-    path: &String = string to truncate or pad
-    nChars: usize = number of characters to pad or truncate to
+  let mut strip_because = String::new();
+  let mut rounded: Option<(f64, f64)> = None;
+  let mut precise: Vec<String> = Vec::new();
 
-  Function takes a path and number of character as a parameter and either truncate the file name to
-  fit with the space, or pads it out.
-**/
-fn fit_name_in(path: &String,nChars: usize) -> String
-{
-  let mut me = path.clone().to_owned();
-  
-  if (me.len()>(nChars))
+  if privacy_options.strip
     {
-      me.replace_range(..(me.len()-nChars+3), "...");
-      return me;
+      strip_because = "Location removed".to_string();
     }
-  else if (me.len()<(nChars))
+  else if let Some(e) = unreadable
     {
-      for _i in 0..(nChars)-me.len()
+      strip_because = format!("The location can't be read ({}), so it is removed rather than risk leaving it as it is", e);
+    }
+  else if let Some(place) = privacy_options.geofence.as_ref().zip(position).and_then(|(fence, (latitude, longitude))| fence.containing(latitude, longitude))
+    {
+      strip_because = format!("Inside {}, location removed", place.name);
+    }
+  else if let Some(((latitude, longitude), precision)) = position.zip(privacy_options.precision)
+    {
+      let round = |degrees: f64| (degrees / precision).round() * precision;
+
+      rounded = Some((round(latitude), round(longitude)));
+      precise = gps_properties.iter()
+                              .filter(|name| PRECISE_GPS_PROPERTIES.iter().any(|p| name.starts_with(p)))
+                              .cloned()
+                              .collect();
+      if nksc.text_property(IPTC_CORE_NAMESPACE, "Location").is_some()
         {
-          me.push(' ');
+          precise.push("Iptc4xmpCore:Location".to_string());
         }
-      return me;
-    }
-    return path.to_string()
-}
 
+      if ((round(latitude) - latitude).abs() <= 1e-9) && ((round(longitude) - longitude).abs() <= 1e-9) && precise.is_empty()
+        {
+          rounded = None;                                                      // already as vague as we were asked for
+        }
+    }
 
-/**  WalkDirectory
-  fn WalkDirectory(WhichDirectory: &PathBuf, search_extension: &str, recursive: bool, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool,
-                 i_want_to_see_everything: bool, enable_geo_sync: bool, astro: bool, best_quality: bool, edge: bool)
+  if strip_because.is_empty() && rounded.is_none()
+    {
+      if i_want_to_see_everything
+        {
+          println!("Privacy:              {} ",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nksc_path,column_width)));
+        }
+      return;
+    }
 
-    WhichDirectory: &PathBuf = directory to search in
-    search_extension: &str, = the file extension to look for (default .nef)
-    recursive: bool = flag to indicate if a recursive search is to be performed
-    i_want_to_save_changes: bool = flag to indicate if we are doing a "listing" run without actually processing teh file
-    i_want_to_save_the_original_file: bool = flag to indicate if we wish to save a back up of the original file before modifying it
-    i_want_to_see_everything: bool = flag which basically says if I want to see all files, whether they are in sync or not.
-    enable_geo_sync: bool = let it do the geo sync
-    astro: bool = process astro flag
-    best_quality: bool = process best_quality flag
-    edge: bool = set edge noise reduction on
+  println!("Privacy:              {} ",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nksc_path,column_width)));
 
-  Function which walks through a given directory and basically does all of the work.
-*/
-fn WalkDirectory(WhichDirectory: &PathBuf, search_extension: &str, recursive: bool, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool,
-                 i_want_to_see_everything: bool, enable_geo_sync: bool,astro: bool, best_quality: bool, edge: bool)
-{
-  if WhichDirectory.is_dir() // sanity check, probably not necessary, but this is Rust and Rust is all about "safety"
+  if !strip_because.is_empty()
     {
-      let paths = fs::read_dir(WhichDirectory).expect("Could not scan the directory");
+      println!("        {}", strip_because);
 
-      println!("Processing: {}", WhichDirectory.display());
-      for each_path in paths
+      if i_want_to_save_changes
         {
-          let nef_path = each_path.unwrap();
+          let stripped = gps_properties.iter().try_for_each(|name| nksc.remove(name).map(|_| ()))
+                                       .and_then(|_| remove_place_names(&mut nksc))
+                                       .and_then(|_| nksc.save(nksc_Path, i_want_to_save_the_original_file));
 
-          if (nef_path.path().is_file())
-            {
-              if (enable_geo_sync==true)
-                {
-                  geo_sync_a_file(&nef_path.path().to_path_buf(), search_extension, i_want_to_save_changes,i_want_to_save_the_original_file,i_want_to_see_everything);
-                }
-              
-              if (astro==true || best_quality==true || edge==true)
-                {
-                  set_noise_reduction_in_a_file(&nef_path.path().to_path_buf(), search_extension, i_want_to_save_changes,
-                                                  i_want_to_save_the_original_file,i_want_to_see_everything, astro, best_quality, edge);
-                }
-            }
-          else // Directory
+          if let Err(e) = stripped
             {
-              if (recursive == true) && (nef_path.path().file_stem() != Some(OsStr::new("NKSC_PARAM")))
-                {
-                  verbose!("DIR: {}", nef_path.path().display());
-                  WalkDirectory(&nef_path.path().to_path_buf(), search_extension, recursive, i_want_to_save_changes,i_want_to_save_the_original_file,
-                                i_want_to_see_everything,enable_geo_sync,astro,best_quality, edge);
-                }
+              println!("        Could not update the NKSC: {}", e);
             }
         }
     }
-  else
-    {
-      println!("Something went gravely wrong: {:?}", WhichDirectory.file_name());
-    }
-}
-
-/**  geo_sync_a_file
-  fn geo_sync_a_file(nef_path: &PathBuf, search_extension: &str, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, i_want_to_see_everything: bool)
-
-    nef_path = path to file 
-    search_extension = file extension 
-    i_want_to_save_changes = save changes, as opposed to just walking through the files and seeing what is going on inside them
-    i_want_to_save_the_original_file = make a back up of the original file before making changes
-    i_want_to_see_everything = be quite verbose in the information we print out
-
-  Function which processes an individual file.
-*/
-fn geo_sync_a_file(nef_path: &PathBuf, search_extension: &str, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, i_want_to_see_everything: bool)
-{
-  let mut column_width:usize = 39;
-  
-  /*
-   * Work out how wide our terminal is, so we can use as much real estate as we possibly can 
-   */
-
-    if let Some((w, _h)) = term_size::dimensions() 
-      {
-        column_width=(w-10)/2;
-      } 
-
-  /*
-   * Process the Nikon Sidecar File and the NEF to see if there is any GEO data and sync them if there is
-   */
-  if (format!(".{:?}",nef_path.extension().expect("Hoped to find some NEF files, but I could not.")).to_lowercase().replace("\\\\","\\").replace("\"","")==search_extension)
+  else if let (Some((latitude, longitude)), Some((was_latitude, was_longitude))) = (rounded, position)
     {
-      let mut nksc_path:String=format!("{:?}\\\\NKSC_PARAM\\\\{:?}.nksc",nef_path.parent().unwrap(), nef_path.file_name().unwrap());
-      nksc_path=nksc_path.replace("\\\\","\\").replace("\"","");
-      let nksc_Path=Path::new(&nksc_path);
-
-      if nksc_Path.exists()
+      println!("        Rounded to {}°: {:.6} {:.6} (was {:.6} {:.6})", privacy_options.precision.unwrap_or_default(), latitude, longitude, was_latitude, was_longitude);
+      if !precise.is_empty()
         {
-          let mut Location = LocationData{GPSLatitudeRef: "".to_string(),GPSLatitude: "".to_string(),GPSLongitudeRef: "".to_string(),GPSLongitude: "".to_string(),
-                                          GPSAltitude:"".to_string(),GPSDateStamp: "".to_string(),GPSTimeStamp: "".to_string()};
-          let nef:String=format!("{}",nef_path.display());
-                    
-          /*
-            * Check to see if the nksc file doesn't already have location data, if it doesn't, then we will
-            * see if there is any location data in the NEF file, if there is, we will then try to extract
-            * that data and update the nksc file with the data from the exif tag in the NEF.
-            */
-
-          let there_is_location_data_in_nksc:bool = check_if_this_is_already_in(&nksc_Path.to_path_buf(),"GPSLatitude rdf:parseType");
-          let there_is_a_GPSVersionID_in_nksc:bool = check_if_this_is_already_in(&nksc_Path.to_path_buf(),"GPSVersionID");
-          let there_is_location_data_in_nef:bool = check_if_there_is_location_data_in(&nef_path);
-
-          if there_is_location_data_in_nksc==false
-            {
-              if (there_is_location_data_in_nef)||(i_want_to_see_everything)
-                  {
-                    print!("Geo:  {}  ",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nksc_path,column_width)));
-                  }
+          println!("        Removed {}", precise.join(", "));
+        }
 
-              if there_is_location_data_in_nef
-                {
-                  if i_want_to_save_changes
-                    {
-                      print!("{}",Colour::Black.on(Colour::Yellow).paint(fit_name_in(&nef,column_width)));
+      if i_want_to_save_changes
+        {
+          let coarse = LocationData::from_position(latitude, longitude, None);
 
-                      get_location_data_from_exif(&nef_path,&mut Location);
-                      create_new_nksc_file(&nksc_Path,&mut Location, i_want_to_save_the_original_file,there_is_a_GPSVersionID_in_nksc );
+          let coarsened = nksc.set_value("GPSLatitudeRef", &AsValue::Long(nksc_ref_from_exif(&coarse.GPSLatitudeRef)))
+                              .and_then(|_| nksc.set_value("GPSLatitude", &AsValue::Double(coarse.GPSLatitude.clone())))
+                              .and_then(|_| nksc.set_value("GPSLongitudeRef", &AsValue::Long(nksc_ref_from_exif(&coarse.GPSLongitudeRef))))
+                              .and_then(|_| nksc.set_value("GPSLongitude", &AsValue::Double(coarse.GPSLongitude.clone())))
+                              .and_then(|_| precise.iter().try_for_each(|name| nksc.remove(name).map(|_| ())))
+                              .and_then(|_| nksc.remove_text_property(IPTC_CORE_NAMESPACE, "Location"))
+                              .and_then(|_| nksc.save(nksc_Path, i_want_to_save_the_original_file));
 
-                      for _i in 0..(column_width*2)+2 {print!("\x08")}; // Erase the contents of the line from the screen
-                      println!("Geo:  {}  {}",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nksc_path,column_width)),Colour::Blue.on(Colour::Green).paint(fit_name_in(&nef,column_width)));
-                    }
-                  else
-                    {
-                      get_location_data_from_exif(&nef_path,&mut Location);
-                      println!("{}",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nef,column_width)));
-                    }
-                }
-              else
-                {
-                  if (there_is_location_data_in_nef)||(i_want_to_see_everything)
-                      {
-                      println!("{}",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nef,column_width)));
-                      }
-                }
-            }
-          else  // there IS already location data in the NKSC_PARAM file
+          if let Err(e) = coarsened
             {
-              if (!there_is_location_data_in_nef)||(i_want_to_see_everything)
-                {
-                  print!("Geo:  {}  ",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nksc_path,column_width)));
-                  get_location_data_from_exif(&nef_path,&mut Location);
-                  if there_is_location_data_in_nef
-                    {
-                      println!("{}",Colour::Blue.on(Colour::Green).paint(fit_name_in(&nef,column_width)));
-                    }
-                  else
-                    {
-                      println!("{}",Colour::Yellow.on(Colour::Red).paint(fit_name_in(&nef,column_width)));
-                    }
-                }
+              println!("        Could not update the NKSC: {}", e);
             }
         }
     }
 }
 
+
 /**  set_noise_reduction_in_a_file
   fn set_noise_reduction_in_a_file(nef_path: &PathBuf, search_extension: &str, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, 
                                     i_want_to_see_everything: bool,astro: bool, best_quality: bool)
@@ -837,6 +2879,7 @@ fn geo_sync_a_file(nef_path: &PathBuf, search_extension: &str, i_want_to_save_ch
 
   Function which processes an individual file settings its astro, edge, or best quality flag for noise reduction
 */
+#[allow(clippy::too_many_arguments, clippy::ptr_arg, clippy::bool_comparison)]
 fn set_noise_reduction_in_a_file(nef_path: &PathBuf, search_extension: &str, i_want_to_save_changes: bool, i_want_to_save_the_original_file: bool, 
                                   i_want_to_see_everything: bool, astro: bool, best_quality: bool, edge: bool)
 {
@@ -859,17 +2902,21 @@ fn set_noise_reduction_in_a_file(nef_path: &PathBuf, search_extension: &str, i_w
       let mut nksc_path:String=format!("{:?}\\\\NKSC_PARAM\\\\{:?}.nksc",nef_path.parent().unwrap(), nef_path.file_name().unwrap());
       nksc_path=nksc_path.replace("\\\\","\\").replace("\"","");
       let nksc_Path=Path::new(&nksc_path);
-      let mut nksc_PathBuf=PathBuf::new();
-      nksc_PathBuf.push(nksc_Path);
       let mut go_astro:bool = false;
       let mut go_best_quality:bool = false;
       let mut go_edge:bool = false;
 
       if nksc_Path.exists() // See if the file actually exists, which it should
         {
+          let mut nksc = match NkscDocument::load(nksc_Path)
+            {
+              Ok(nksc) => nksc,
+              Err(e) => { println!("{}", e); return; }
+            };
+
           if astro==true
             {
-              if check_if_this_is_already_in(&nksc_PathBuf,"NoiseReduction.chkSpike\"&gt;1&lt;")
+              if nksc.setting("NoiseReduction.chkSpike").as_deref()==Some("1")
                 {
                   if i_want_to_see_everything
                     {
@@ -885,7 +2932,7 @@ fn set_noise_reduction_in_a_file(nef_path: &PathBuf, search_extension: &str, i_w
 
           if best_quality==true
             {
-              if check_if_this_is_already_in(&nksc_PathBuf,"NoiseReduction.cbMethod\"&gt;1&lt;")
+              if nksc.setting("NoiseReduction.cbMethod").as_deref()==Some("1")
                 {
                   if i_want_to_see_everything
                     {
@@ -901,7 +2948,7 @@ fn set_noise_reduction_in_a_file(nef_path: &PathBuf, search_extension: &str, i_w
           
           if edge==true
             {
-              if check_if_this_is_already_in(&nksc_PathBuf,"NoiseReduction.chkEdge\"&gt;1&lt;")
+              if nksc.setting("NoiseReduction.chkEdge").as_deref()==Some("1")
                 {
                   if i_want_to_see_everything
                     {
@@ -918,54 +2965,40 @@ fn set_noise_reduction_in_a_file(nef_path: &PathBuf, search_extension: &str, i_w
             if (go_best_quality==true || go_astro==true || go_edge ==true) && (i_want_to_save_changes==true)
               {
                 
-                /*
-                 * Read the file into memory, once there we'll do a rather grotesque replacement of the strings which control the astro setting
-                 * or the best_quality quality setting. After that we will save the buffer back to disk.
-                 */
-
-                let mut fr = File::open(nksc_Path).expect(format!("Could not open {:?}",nksc_Path.file_name()).as_str());
-                let mut body = String::new();
-
-                fr.read_to_string(&mut body).expect("Unable to read from the file");
-                drop(fr);
-                
-               /*
-                * Back up the old file next
-                * But if there is already a backup, we won't. The logic is the original backup will be the original file, and I don't really
-                * want to loose the original original file. Besides, this isn't the sort of thing that we'd be doing more than once anyway.
-                */
+                let mut changed:usize = 0;
+                let mut missing: Vec<&str> = Vec::new();
+                let mut saved: Result<(), String> = Ok(());
 
-                if i_want_to_save_the_original_file
+                for (go, key) in [(go_astro, "NoiseReduction.chkSpike"), (go_best_quality, "NoiseReduction.cbMethod"), (go_edge, "NoiseReduction.chkEdge")]
                   {
-                    let backup:String=format!("{}.original",nksc_Path.display());
-                    let backup_Path=Path::new(&backup);
-                    if (!backup_Path.exists())
+                    if go
                       {
-                        fs::rename(nksc_Path.to_str().unwrap(), backup).expect("backing up a file failed");
+                        match nksc.set_setting(key, "1")
+                          {
+                            Ok(true) => changed += 1,
+                            Ok(false) => missing.push(key),
+                            Err(e) => { saved = Err(e); break; },
+                          }
                       }
                   }
 
-                if go_astro
+                /*
+                 * A sidecar without the setting at all is left alone rather than saved as though it had been changed
+                 */
+                if !missing.is_empty()
                   {
-                    body=body.replace("NoiseReduction.chkSpike\"&gt;0&lt;","NoiseReduction.chkSpike\"&gt;1&lt;");
+                    println!("        The NKSC has no {} setting to change, so it is left as it is", missing.join(" or "));
                   }
 
-                if go_best_quality
+                if changed > 0
                   {
-                    body=body.replace("NoiseReduction.cbMethod\"&gt;0&lt;","NoiseReduction.cbMethod\"&gt;1&lt;");
+                    saved = saved.and_then(|_| nksc.save(nksc_Path, i_want_to_save_the_original_file));
                   }
-    
-                if go_edge
+
+                if let Err(e) = saved
                   {
-                    body=body.replace("NoiseReduction.chkEdge\"&gt;0&lt;","NoiseReduction.chkEdge\"&gt;1&lt;");
+                    println!("        Could not update the NKSC: {}", e);
                   }
-
-                /*
-                 * Write the contents of our reformatted buffer to disk
-                 */
-                let mut output = File::create(format!("{}",nksc_Path.display())).expect("Create file failed");
-                output.write_all(body.as_bytes()).expect("Write failed");
-                drop(output);
               }
           }
     }
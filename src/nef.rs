@@ -0,0 +1,691 @@
+/*
+ * nef.rs
+ * In-place editing of the GPS IFD of an NEF (or any other TIFF based file).
+ *
+ * An NEF is a TIFF file, so rewriting it properly means moving IFDs and fixing up every offset in the file, including the ones inside
+ * Nikon's makernote. We don't go anywhere near that. Where the NEF already has a position we only overwrite the bytes of GPS tags which
+ * are there with a value of exactly the same type and size, so the layout of the file never changes. Where it doesn't, as with a body
+ * without a GPS, nothing already in the file is moved either: a new GPS IFD is added to the end, and IFD0 is copied after it with a GPS
+ * IFD pointer added, the header then pointing at the copy. Every offset in the file stays where it was and still points at what it did.
+ */
+
+use std::convert::TryFrom;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::LocationData;
+
+const GPS_IFD_POINTER: u16 = 34853;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+const TAG_VERSION_ID: u16 = 0;
+const TAG_LATITUDE_REF: u16 = 1;
+const TAG_LATITUDE: u16 = 2;
+const TAG_LONGITUDE_REF: u16 = 3;
+const TAG_LONGITUDE: u16 = 4;
+const TAG_ALTITUDE_REF: u16 = 5;
+const TAG_ALTITUDE: u16 = 6;
+const TAG_TIME_STAMP: u16 = 7;
+const TAG_MAP_DATUM: u16 = 18;
+const TAG_DATE_STAMP: u16 = 29;
+
+// Define Structures
+
+type Patch = (u64, Vec<u8>);                                                   // Bytes to write over the file, and where
+type Field = (u16, u16, u32, Vec<u8>);                                         // The tag, type, count and value bytes of a new IFD entry
+type Changes = (Vec<Patch>, Vec<u8>, Vec<&'static str>);                       // The patches, the bytes to append before them, and what can't be written
+
+struct Entry
+  {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    offset: u64,                                                               // Where in the file the value itself lives
+  }
+
+struct Tiff
+  {
+    little_endian: bool,
+    ifd0: usize,                                                               // Where IFD0 starts
+    gps_pointer: Option<usize>,                                                // Where IFD0's GPS IFD pointer keeps its offset, if it has one
+    gps: Vec<Entry>,
+  }
+
+
+/** write_location
+  fn write_location(file: &Path, Location: &LocationData, i_want_to_save_the_original_file: bool) -> Result<Vec<&'static str>, String>
+    file: &Path = path to the NEF
+    Location: &LocationData = location to write
+    i_want_to_save_the_original_file: bool = copy the NEF to .original before touching it
+
+  Overwrites the position in the GPS IFD of the NEF, where the latitude and longitude (and their refs) are already there; altitude, date and
+  time are then written if both the NEF and the location have them. Our locations are always WGS-84, so a GPSMapDatum in the NEF is
+  relabelled to match. A NEF without a position gets a new GPS IFD with all of them in it instead. Returns the names of anything we had
+  but couldn't write.
+**/
+pub fn write_location(file: &Path, Location: &LocationData, i_want_to_save_the_original_file: bool) -> Result<Vec<&'static str>, String>
+{
+  let data = fs::read(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+  let (patches, appended, skipped) = changes(&data, Location)?;
+
+  backup(file, i_want_to_save_the_original_file)?;
+
+  let mut output = OpenOptions::new().write(true).open(file).map_err(|e| format!("Could not open {} for writing: {}", file.display(), e))?;
+  if !appended.is_empty()
+    {
+      output.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+      output.write_all(&appended).map_err(|e| e.to_string())?;
+    }
+  patch(&mut output, patches)?;                                                // Any appended IFDs are in place before anything points at them
+
+  Ok(skipped)
+}
+
+
+/** changes
+  fn changes(data: &[u8], Location: &LocationData) -> Result<Changes, String>
+    data: &[u8] = the whole NEF
+    Location: &LocationData = location to write
+
+  Works out what write_location has to do to the NEF: the bytes to append to it (none unless it needs a new GPS IFD), the patches to
+  write over it once they are there, and the names of anything we had but couldn't write.
+**/
+fn changes(data: &[u8], Location: &LocationData) -> Result<Changes, String>
+{
+  let tiff = parse(data)?;
+  let mut skipped: Vec<&'static str> = Vec::new();
+
+  let mut patches: Vec<Patch> = match [tiff.ascii(TAG_LATITUDE_REF, &Location.GPSLatitudeRef),
+                                       tiff.rationals(TAG_LATITUDE, &Location.GPSLatitude),
+                                       tiff.ascii(TAG_LONGITUDE_REF, &Location.GPSLongitudeRef),
+                                       tiff.rationals(TAG_LONGITUDE, &Location.GPSLongitude)]
+    {
+      [Some(latitude_ref), Some(latitude), Some(longitude_ref), Some(longitude)] => vec![latitude_ref, latitude, longitude_ref, longitude],
+      _ =>
+        {
+          let (patches, appended) = tiff.new_gps_ifd(data, Location)?;
+          return Ok((patches, appended, skipped));
+        },
+    };
+
+  if let Some(altitude) = Location.altitude()
+    {
+      match (tiff.rationals(TAG_ALTITUDE, &[altitude.abs()]), tiff.bytes(TAG_ALTITUDE_REF, &[if altitude < 0.0 { 1 } else { 0 }]))
+        {
+          (Some(altitude), Some(altitude_ref)) => { patches.push(altitude); patches.push(altitude_ref); },
+          _ => skipped.push("GPSAltitude"),
+        }
+    }
+
+  if !Location.GPSDateStamp.is_empty()
+    {
+      match tiff.ascii(TAG_DATE_STAMP, &Location.GPSDateStamp)
+        {
+          Some(patch) => patches.push(patch),
+          None => skipped.push("GPSDateStamp"),
+        }
+    }
+
+  if !Location.GPSTimeStamp.is_empty()
+    {
+      match tiff.rationals(TAG_TIME_STAMP, &Location.GPSTimeStamp)
+        {
+          Some(patch) => patches.push(patch),
+          None => skipped.push("GPSTimeStamp"),
+        }
+    }
+
+  /*
+   * "WGS-84" needs 7 bytes with its NUL, but the "TOKYO" of the old Japanese receivers only has 6, so fall back on "WGS84" there
+   */
+  if let Some(entry) = tiff.gps.iter().find(|e| e.tag == TAG_MAP_DATUM && e.field_type == TYPE_ASCII)
+    {
+      let mut label = if entry.count >= 7 { b"WGS-84".to_vec() } else { b"WGS84".to_vec() };
+
+      if (entry.count as usize) > label.len()
+        {
+          label.resize(entry.count as usize, 0);
+          patches.push((entry.offset, label));
+        }
+      else
+        {
+          skipped.push("GPSMapDatum");
+        }
+    }
+
+  Ok((patches, Vec::new(), skipped))
+}
+
+
+/** backup
+  fn backup(file: &Path, i_want_to_save_the_original_file: bool) -> Result<(), String>
+
+  Backs up the NEF, unless there already is a backup. Unlike the sidecar we copy rather than rename, since we change the file in place.
+**/
+fn backup(file: &Path, i_want_to_save_the_original_file: bool) -> Result<(), String>
+{
+  if i_want_to_save_the_original_file
+    {
+      let backup:String=format!("{}.original",file.display());
+      let backup_Path=Path::new(&backup);
+      if (!backup_Path.exists())
+        {
+          fs::copy(file, backup_Path).map_err(|e| format!("backing up {} failed: {}", file.display(), e))?;
+        }
+    }
+
+  Ok(())
+}
+
+
+/** patch
+  fn patch(output: &mut fs::File, patches: Vec<Patch>) -> Result<(), String>
+
+  Writes each set of bytes over the file at its offset.
+**/
+fn patch(output: &mut fs::File, patches: Vec<Patch>) -> Result<(), String>
+{
+  for (offset, bytes) in patches
+    {
+      output.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+      output.write_all(&bytes).map_err(|e| e.to_string())?;
+    }
+
+  Ok(())
+}
+
+
+/** parse
+  fn parse(data: &[u8]) -> Result<Tiff, String>
+
+  Reads the TIFF header, follows IFD0 to the GPS IFD, if there is one, and notes where each GPS tag's value lives.
+**/
+fn parse(data: &[u8]) -> Result<Tiff, String>
+{
+  let little_endian = match data.get(0..4)
+    {
+      Some(b"II*\0") => true,
+      Some(b"MM\0*") => false,
+      _ => return Err("not a TIFF based file".to_string()),
+    };
+
+  let ifd0 = read_u32(data, 4, little_endian)? as usize;
+  let mut gps_pointer: Option<usize> = None;
+
+  for (position, tag, _field_type, _count) in entries(data, ifd0, little_endian)?
+    {
+      if tag == GPS_IFD_POINTER
+        {
+          gps_pointer = Some(position + 8);
+        }
+    }
+
+  let gps_ifd = match gps_pointer
+    {
+      Some(pointer) => Some(read_u32(data, pointer, little_endian)? as usize),
+      None => None,
+    };
+  let mut gps = Vec::new();
+
+  for (position, tag, field_type, count) in gps_ifd.map(|ifd| entries(data, ifd, little_endian)).transpose()?.unwrap_or_default()
+    {
+      let size = type_size(field_type) * count as usize;
+      let offset = if size <= 4 { position + 8 } else { read_u32(data, position + 8, little_endian)? as usize };
+
+      if offset + size > data.len()
+        {
+          return Err(format!("GPS tag {} points past the end of the file", tag));
+        }
+
+      gps.push(Entry { tag, field_type, count, offset: offset as u64 });
+    }
+
+  Ok(Tiff { little_endian, ifd0, gps_pointer, gps })
+}
+
+
+/** entries
+  fn entries(data: &[u8], ifd: usize, little_endian: bool) -> Result<Vec<(usize, u16, u16, u32)>, String>
+
+  Returns the position, tag, type and count of each 12 byte entry in an IFD.
+**/
+fn entries(data: &[u8], ifd: usize, little_endian: bool) -> Result<Vec<(usize, u16, u16, u32)>, String>
+{
+  let count = read_u16(data, ifd, little_endian)? as usize;
+  let mut entries = Vec::with_capacity(count);
+
+  for i in 0..count
+    {
+      let position = ifd + 2 + i * 12;
+      entries.push((position,
+                    read_u16(data, position, little_endian)?,
+                    read_u16(data, position + 2, little_endian)?,
+                    read_u32(data, position + 4, little_endian)?));
+    }
+
+  Ok(entries)
+}
+
+
+impl Tiff
+{
+  /** new_gps_ifd
+    fn new_gps_ifd(&self, data: &[u8], Location: &LocationData) -> Result<(Vec<Patch>, Vec<u8>), String>
+      data: &[u8] = the whole NEF
+      Location: &LocationData = location to write
+
+    Builds a GPS IFD with the whole location in it, to go on the end of the file. If IFD0 already points at a GPS IFD, one with no position
+    in it, the pointer is changed to the new one; otherwise a copy of IFD0 with a pointer added goes on the end as well, and the header is
+    changed to point at that. Returns the patches and the bytes to append.
+  **/
+  fn new_gps_ifd(&self, data: &[u8], Location: &LocationData) -> Result<(Vec<Patch>, Vec<u8>), String>
+  {
+    let three_rationals = |dms: &[f64]| dms.iter().map(|v| to_rational(*v)).collect::<Option<Vec<_>>>().filter(|r| r.len() == 3);
+    let latitude = three_rationals(&Location.GPSLatitude).ok_or("the latitude can't be written as three rationals")?;
+    let longitude = three_rationals(&Location.GPSLongitude).ok_or("the longitude can't be written as three rationals")?;
+
+    let mut gps: Vec<Field> = vec![
+        (TAG_VERSION_ID, TYPE_BYTE, 4, crate::GPS_VERSION_ID.to_vec()),
+        self.ascii_entry(TAG_LATITUDE_REF, &Location.GPSLatitudeRef),
+        self.rational_entry(TAG_LATITUDE, &latitude),
+        self.ascii_entry(TAG_LONGITUDE_REF, &Location.GPSLongitudeRef),
+        self.rational_entry(TAG_LONGITUDE, &longitude),
+        self.ascii_entry(TAG_MAP_DATUM, "WGS-84"),
+      ];
+
+    if let Some(altitude) = Location.altitude().and_then(|a| to_rational(a.abs()).map(|r| (a, r)))
+      {
+        gps.push((TAG_ALTITUDE_REF, TYPE_BYTE, 1, vec![if altitude.0 < 0.0 { 1 } else { 0 }]));
+        gps.push(self.rational_entry(TAG_ALTITUDE, &[altitude.1]));
+      }
+
+    if let Some(time) = three_rationals(&Location.GPSTimeStamp)
+      {
+        gps.push(self.rational_entry(TAG_TIME_STAMP, &time));
+      }
+
+    if !Location.GPSDateStamp.is_empty()
+      {
+        gps.push(self.ascii_entry(TAG_DATE_STAMP, &Location.GPSDateStamp));
+      }
+
+    let gps_ifd = data.len() + data.len() % 2;                                 // IFDs start on a word boundary
+    let mut appended = vec![0; gps_ifd - data.len()];
+
+    appended.extend(self.ifd(gps_ifd, gps, 0)?);
+
+    if let Some(pointer) = self.gps_pointer
+      {
+        let offset = self.offset(gps_ifd)?;
+        return Ok((vec![(pointer as u64, self.u32_bytes(offset).to_vec())], appended));
+      }
+
+    /*
+     * The entries of IFD0 are copied as they are, bytes and all, since anything they point at stays where it is
+     */
+    let mut ifd0: Vec<Field> = Vec::new();
+
+    for (position, tag, field_type, count) in entries(data, self.ifd0, self.little_endian)?
+      {
+        ifd0.push((tag, field_type, count, data[position + 8..position + 12].to_vec()));
+      }
+    ifd0.push((GPS_IFD_POINTER, TYPE_LONG, 1, self.u32_bytes(self.offset(gps_ifd)?).to_vec()));
+
+    let next = read_u32(data, self.ifd0 + 2 + (ifd0.len() - 1) * 12, self.little_endian)?;
+    let new_ifd0 = data.len() + appended.len();
+
+    appended.extend(self.ifd(new_ifd0, ifd0, next)?);
+
+    Ok((vec![(4, self.u32_bytes(self.offset(new_ifd0)?).to_vec())], appended))
+  }
+
+
+  /** ifd
+    fn ifd(&self, at: usize, entries: Vec<Field>, next: u32) -> Result<Vec<u8>, String>
+      at: usize = where in the file the IFD is going
+      entries: Vec<Field> = the entries, in any order
+      next: u32 = the offset of the next IFD, 0 for none
+
+    Lays out an IFD, its entries in tag order, with any value over four bytes after it.
+  **/
+  fn ifd(&self, at: usize, mut entries: Vec<Field>, next: u32) -> Result<Vec<u8>, String>
+  {
+    entries.sort_by_key(|e| e.0);
+
+    let mut values_at = at + 2 + entries.len() * 12 + 4;
+    let mut ifd = self.u16_bytes(entries.len() as u16).to_vec();
+    let mut values: Vec<u8> = Vec::new();
+
+    for (tag, field_type, count, mut bytes) in entries
+      {
+        ifd.extend_from_slice(&self.u16_bytes(tag));
+        ifd.extend_from_slice(&self.u16_bytes(field_type));
+        ifd.extend_from_slice(&self.u32_bytes(count));
+        if bytes.len() <= 4
+          {
+            bytes.resize(4, 0);
+            ifd.extend(bytes);
+          }
+        else
+          {
+            ifd.extend_from_slice(&self.u32_bytes(self.offset(values_at)?));
+            values_at += bytes.len() + bytes.len() % 2;
+            values.extend(&bytes);
+            values.resize(values.len() + bytes.len() % 2, 0);
+          }
+      }
+
+    ifd.extend_from_slice(&self.u32_bytes(next));
+    ifd.extend(values);
+    Ok(ifd)
+  }
+
+
+  fn ascii_entry(&self, tag: u16, value: &str) -> Field
+  {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+
+    (tag, TYPE_ASCII, bytes.len() as u32, bytes)
+  }
+
+
+  fn rational_entry(&self, tag: u16, values: &[(u32, u32)]) -> Field
+  {
+    let bytes = values.iter().flat_map(|(numerator, denominator)| [self.u32_bytes(*numerator), self.u32_bytes(*denominator)].concat()).collect();
+
+    (tag, TYPE_RATIONAL, values.len() as u32, bytes)
+  }
+
+
+  /** offset
+    fn offset(&self, position: usize) -> Result<u32, String>
+
+    A TIFF offset is only 32 bits, so anything we add has to start within the first 4 GB.
+  **/
+  fn offset(&self, position: usize) -> Result<u32, String>
+  {
+    u32::try_from(position).map_err(|_| "the NEF is too big to add a GPS IFD to".to_string())
+  }
+
+
+  /** find
+    fn find(&self, tag: u16, field_type: u16, count: usize) -> Option<&Entry>
+
+    Returns the GPS entry with the given tag, but only if it has the type and count we want to write.
+  **/
+  fn find(&self, tag: u16, field_type: u16, count: usize) -> Option<&Entry>
+  {
+    self.gps.iter().find(|e| e.tag == tag && e.field_type == field_type && e.count as usize == count)
+  }
+
+
+  /** rationals
+    fn rationals(&self, tag: u16, values: &[f64]) -> Option<(u64, Vec<u8>)>
+
+    Encodes values as unsigned rationals for an existing tag, returning where to write them and the bytes to write.
+  **/
+  fn rationals(&self, tag: u16, values: &[f64]) -> Option<(u64, Vec<u8>)>
+  {
+    let entry = self.find(tag, TYPE_RATIONAL, values.len())?;
+    let mut bytes = Vec::with_capacity(values.len() * 8);
+
+    for value in values
+      {
+        let (numerator, denominator) = to_rational(*value)?;
+        bytes.extend_from_slice(&self.u32_bytes(numerator));
+        bytes.extend_from_slice(&self.u32_bytes(denominator));
+      }
+
+    Some((entry.offset, bytes))
+  }
+
+
+  /** ascii
+    fn ascii(&self, tag: u16, value: &str) -> Option<(u64, Vec<u8>)>
+
+    Encodes a NUL terminated string for an existing tag of exactly the same length.
+  **/
+  fn ascii(&self, tag: u16, value: &str) -> Option<(u64, Vec<u8>)>
+  {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+
+    let entry = self.find(tag, TYPE_ASCII, bytes.len())?;
+    Some((entry.offset, bytes))
+  }
+
+
+  /** bytes
+    fn bytes(&self, tag: u16, value: &[u8]) -> Option<(u64, Vec<u8>)>
+
+    Encodes raw bytes for an existing BYTE tag of exactly the same length.
+  **/
+  fn bytes(&self, tag: u16, value: &[u8]) -> Option<(u64, Vec<u8>)>
+  {
+    let entry = self.find(tag, TYPE_BYTE, value.len())?;
+    Some((entry.offset, value.to_vec()))
+  }
+
+
+  fn u16_bytes(&self, value: u16) -> [u8; 2]
+  {
+    if self.little_endian { value.to_le_bytes() } else { value.to_be_bytes() }
+  }
+
+
+  fn u32_bytes(&self, value: u32) -> [u8; 4]
+  {
+    if self.little_endian { value.to_le_bytes() } else { value.to_be_bytes() }
+  }
+}
+
+
+/** to_rational
+  fn to_rational(value: f64) -> Option<(u32, u32)>
+
+  Turns a non-negative value into a rational, using the biggest power of ten denominator (up to a million) that still lets the numerator fit.
+**/
+fn to_rational(value: f64) -> Option<(u32, u32)>
+{
+  if !value.is_finite() || value < 0.0
+    {
+      return None;
+    }
+
+  let mut denominator: u32 = 1_000_000;
+  while denominator > 1 && value * denominator as f64 > u32::MAX as f64
+    {
+      denominator /= 10;
+    }
+
+  let numerator = (value * denominator as f64).round();
+  if numerator > u32::MAX as f64
+    {
+      return None;
+    }
+
+  Some((numerator as u32, denominator))
+}
+
+
+fn type_size(field_type: u16) -> usize
+{
+  match field_type
+    {
+      1 | 2 | 6 | 7 => 1,
+      3 | 8 => 2,
+      4 | 9 | 11 => 4,
+      _ => 8,
+    }
+}
+
+
+fn read_u16(data: &[u8], position: usize, little_endian: bool) -> Result<u16, String>
+{
+  let bytes = data.get(position..position + 2).ok_or("the TIFF structure runs past the end of the file")?;
+
+  Ok(if little_endian { u16::from_le_bytes([bytes[0], bytes[1]]) } else { u16::from_be_bytes([bytes[0], bytes[1]]) })
+}
+
+
+fn read_u32(data: &[u8], position: usize, little_endian: bool) -> Result<u32, String>
+{
+  let bytes = data.get(position..position + 4).ok_or("the TIFF structure runs past the end of the file")?;
+
+  Ok(if little_endian { u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) } else { u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) })
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+  use exif::{In, Reader, Tag, Value};
+
+  const TAG_MAKE: u16 = 271;
+  const TAG_ORIENTATION: u16 = 274;
+  const TYPE_SHORT: u16 = 3;
+
+  /*
+   * A TIFF with an IFD0 holding a Make (long enough to live outside its entry) and an Orientation, and after it the GPS IFD, if given
+   */
+  fn tiff(little_endian: bool, gps: Option<Vec<Field>>) -> Vec<u8>
+  {
+    let layout = Tiff { little_endian, ifd0: 8, gps_pointer: None, gps: Vec::new() };
+    let mut ifd0 = vec![layout.ascii_entry(TAG_MAKE, "NIKON CORPORATION"), (TAG_ORIENTATION, TYPE_SHORT, 1, layout.u16_bytes(1).to_vec())];
+    let mut data = if little_endian { b"II*\0".to_vec() } else { b"MM\0*".to_vec() };
+
+    data.extend_from_slice(&layout.u32_bytes(8));
+    match gps
+      {
+        Some(gps) =>
+          {
+            ifd0.push((GPS_IFD_POINTER, TYPE_LONG, 1, vec![0; 4]));
+            let gps_ifd = 8 + layout.ifd(8, ifd0.clone(), 0).unwrap().len();
+
+            ifd0.last_mut().unwrap().3 = layout.u32_bytes(gps_ifd as u32).to_vec();
+            data.extend(layout.ifd(8, ifd0, 0).unwrap());
+            data.extend(layout.ifd(gps_ifd, gps, 0).unwrap());
+          },
+        None => data.extend(layout.ifd(8, ifd0, 0).unwrap()),
+      }
+    data
+  }
+
+  fn location() -> LocationData
+  {
+    LocationData
+      {
+        GPSDateStamp: "2021:05:03".to_string(),
+        GPSTimeStamp: vec![1.0, 2.0, 3.0],
+        ..LocationData::from_position(-33.859833, 151.208333, Some(12.5))
+      }
+  }
+
+  /*
+   * Does to the bytes what write_location does to the file
+   */
+  fn write(mut data: Vec<u8>) -> (Vec<u8>, Vec<&'static str>)
+  {
+    let (patches, appended, skipped) = changes(&data, &location()).unwrap();
+
+    data.extend(appended);
+    for (offset, bytes) in patches
+      {
+        data[offset as usize..offset as usize + bytes.len()].copy_from_slice(&bytes);
+      }
+    (data, skipped)
+  }
+
+  /*
+   * Reads the location back with kamadak-exif, checking IFD0 still has its Make on the way
+   */
+  fn check(data: Vec<u8>, datum: &str)
+  {
+    let exif = Reader::new().read_raw(data).unwrap();
+    let text = |tag: Tag| match exif.get_field(tag, In::PRIMARY).map(|f| &f.value)
+      {
+        Some(Value::Ascii(text)) => String::from_utf8_lossy(&text[0]).to_string(),
+        other => panic!("{} is {:?}", tag, other),
+      };
+    let numbers = |tag: Tag| match exif.get_field(tag, In::PRIMARY).map(|f| &f.value)
+      {
+        Some(Value::Rational(values)) => values.iter().map(|v| v.to_f64()).collect::<Vec<f64>>(),
+        other => panic!("{} is {:?}", tag, other),
+      };
+    let degrees = |tag: Tag| numbers(tag)[0] + numbers(tag)[1] / 60.0 + numbers(tag)[2] / 3600.0;
+
+    assert_eq!(text(Tag::Make), "NIKON CORPORATION");
+    assert_eq!((text(Tag::GPSLatitudeRef), text(Tag::GPSLongitudeRef)), ("S".to_string(), "E".to_string()));
+    assert!((degrees(Tag::GPSLatitude) - 33.859833).abs() < 1e-6);
+    assert!((degrees(Tag::GPSLongitude) - 151.208333).abs() < 1e-6);
+    assert_eq!(numbers(Tag::GPSAltitude), vec![12.5]);
+    assert_eq!(numbers(Tag::GPSTimeStamp), vec![1.0, 2.0, 3.0]);
+    assert_eq!(text(Tag::GPSDateStamp), "2021:05:03");
+    assert_eq!(text(Tag::GPSMapDatum), datum);
+  }
+
+  #[test]
+  fn an_existing_position_is_overwritten_in_place()
+  {
+    for little_endian in [true, false]
+      {
+        let layout = Tiff { little_endian, ifd0: 8, gps_pointer: None, gps: Vec::new() };
+        let data = tiff(little_endian, Some(vec![(TAG_VERSION_ID, TYPE_BYTE, 4, vec![2, 2, 0, 0]),
+                                                  layout.ascii_entry(TAG_LATITUDE_REF, "N"),
+                                                  layout.rational_entry(TAG_LATITUDE, &[(0, 1); 3]),
+                                                  layout.ascii_entry(TAG_LONGITUDE_REF, "W"),
+                                                  layout.rational_entry(TAG_LONGITUDE, &[(0, 1); 3]),
+                                                  (TAG_ALTITUDE_REF, TYPE_BYTE, 1, vec![1]),
+                                                  layout.rational_entry(TAG_ALTITUDE, &[(0, 1)]),
+                                                  layout.rational_entry(TAG_TIME_STAMP, &[(0, 1); 3]),
+                                                  layout.ascii_entry(TAG_MAP_DATUM, "TOKYO"),
+                                                  layout.ascii_entry(TAG_DATE_STAMP, "2000:01:01")]));
+        let size = data.len();
+        let (data, skipped) = write(data);
+
+        assert_eq!(data.len(), size);
+        assert!(skipped.is_empty(), "{:?}", skipped);
+        check(data, "WGS84");
+      }
+  }
+
+  #[test]
+  fn a_gps_ifd_is_added_where_there_was_none()
+  {
+    for little_endian in [true, false]
+      {
+        let data = tiff(little_endian, None);
+        let size = data.len();
+        let (data, _) = write(data);
+
+        assert!(data.len() > size);
+        check(data, "WGS-84");
+      }
+  }
+
+  /*
+   * A GPS IFD with no position in it can't be written over, so the pointer moves to a new one
+   */
+  #[test]
+  fn a_gps_ifd_without_a_position_is_replaced()
+  {
+    for little_endian in [true, false]
+      {
+        let layout = Tiff { little_endian, ifd0: 8, gps_pointer: None, gps: Vec::new() };
+        let data = tiff(little_endian, Some(vec![(TAG_VERSION_ID, TYPE_BYTE, 4, vec![2, 2, 0, 0]), layout.ascii_entry(TAG_MAP_DATUM, "WGS-84")]));
+        let (size, pointer) = (data.len(), parse(&data).unwrap().gps_pointer.unwrap());
+        let (data, _) = write(data);
+
+        assert!(read_u32(&data, pointer, little_endian).unwrap() as usize >= size);
+        check(data, "WGS-84");
+      }
+  }
+}
@@ -0,0 +1,163 @@
+/*
+ * neighbours.rs
+ * Filling in locations from the frames either side.
+ *
+ * When only some frames of a sequence have a location, because SnapBridge dropped the link or the GP-1 hadn't got a fix yet, the ones
+ * that do tell us roughly where the others were taken. For an untagged NEF we find the nearest geotagged NEFs in the same directory shot
+ * before and after it, and if both are close enough in time interpolate between them, otherwise take whichever one is.
+ *
+ * All of the times are straight off the camera's clock, so no clock offset is needed, as long as the frames all came from the one body.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+use crate::LocationData;
+use crate::track::{self, TrackPoint};
+use crate::validate;
+
+// Define Structures
+
+pub type Used<'a> = Vec<(&'a Neighbour, i64)>;                                // The neighbours a position came from, and how many seconds from the NEF each was
+
+pub struct Neighbour
+  {
+    pub name: String,                                                          // File name of the geotagged NEF
+    taken: NaiveDateTime,                                                      // Its DateTimeOriginal
+    point: TrackPoint,                                                         // Its location, timed by the camera's clock rather than UTC
+    utc_offset: Option<i64>,                                                   // Seconds from the camera's clock to its GPS time, if it has one
+  }
+
+
+/** find
+  fn find(directory: &Path, search_extension: &str) -> (Vec<Neighbour>, Vec<String>)
+    directory: &Path = the directory to look in
+    search_extension: &str = the extension of the files we are interested in, e.g. ".nef"
+
+  Reads the location and time of every geotagged NEF in a directory, sorted by time. A NEF whose EXIF can't be read is left out, and
+  what was wrong with it comes back alongside.
+**/
+pub fn find(directory: &Path, search_extension: &str) -> (Vec<Neighbour>, Vec<String>)
+{
+  let mut neighbours = Vec::new();
+  let mut unreadable = Vec::new();
+  let entries = match fs::read_dir(directory)
+    {
+      Ok(entries) => entries,
+      Err(_) => return (neighbours, unreadable),
+    };
+
+  for path in entries.flatten().map(|e| e.path())
+    {
+      let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy().to_lowercase()));
+
+      if !path.is_file() || extension.as_deref() != Some(search_extension)
+        {
+          continue;
+        }
+
+      let mut Location = LocationData::default();
+      let geotagged = crate::try_check_if_there_is_location_data_in(&path)
+                        .and_then(|there_is| if there_is { crate::try_get_location_data_from_exif(&path, &mut Location).map(|_| true) } else { Ok(false) });
+
+      match geotagged
+        {
+          Ok(true) => {},
+          Ok(false) => continue,
+          Err(e) =>
+            {
+              unreadable.push(format!("{}: not used to fill gaps, {}", path.display(), e));
+              continue;
+            },
+        }
+
+      if Location.move_to_wgs84().is_err() || !validate::problems(&Location, None, None).is_empty()
+        {
+          continue;                                                            // on a datum we don't know, or nonsense, so no use to anyone else
+        }
+
+      if let (Ok(taken), Some(latitude), Some(longitude)) = (crate::get_date_time_original(&path), Location.latitude(), Location.longitude())
+        {
+          let camera_time = DateTime::from_naive_utc_and_offset(taken, Utc);
+
+          neighbours.push(Neighbour
+            {
+              name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+              taken,
+              point: TrackPoint { time: camera_time, latitude, longitude, altitude: Location.altitude() },
+              utc_offset: Location.gps_time().map(|gps| (gps - camera_time).num_seconds()),
+            });
+        }
+    }
+
+  neighbours.sort_by_key(|n| n.taken);
+  (neighbours, unreadable)
+}
+
+
+/** position_at
+  fn position_at(neighbours: &[Neighbour], taken: NaiveDateTime, window: f64) -> Result<(TrackPoint, Used<'_>), String>
+    neighbours: &[Neighbour] = the geotagged NEFs in the directory
+    taken: NaiveDateTime = the DateTimeOriginal of the NEF we want a location for
+    window: f64 = how many seconds away a neighbour can have been shot and still be used
+
+  Works out where a NEF was taken from its neighbours, returning the position, with its time in UTC if the neighbours tell us how far
+  the camera's clock was out (otherwise the camera's time), and the neighbours used along with how many seconds from the NEF each was shot.
+**/
+pub fn position_at(neighbours: &[Neighbour], taken: NaiveDateTime, window: f64) -> Result<(TrackPoint, Used<'_>), String>
+{
+  let idx = neighbours.partition_point(|n| n.taken < taken);
+  let seconds = |n: &Neighbour| (n.taken - taken).num_seconds();
+  let before = idx.checked_sub(1).map(|i| &neighbours[i]).filter(|n| (seconds(n).abs() as f64) <= window);
+  let after = neighbours.get(idx).filter(|n| (seconds(n).abs() as f64) <= window);
+  let camera_time = DateTime::from_naive_utc_and_offset(taken, Utc);
+
+  let (mut point, used) = match (before, after)
+    {
+      (Some(before), Some(after)) if after.taken > before.taken =>
+        {
+          let fraction = (taken - before.taken).num_milliseconds() as f64 / (after.taken - before.taken).num_milliseconds() as f64;
+          (track::interpolate(&before.point, &after.point, fraction, camera_time), vec![(before, seconds(before)), (after, seconds(after))])
+        },
+      (_, Some(nearest)) | (Some(nearest), None) => (nearest.point.clone(), vec![(nearest, seconds(nearest))]),
+      (None, None) => return Err(format!("there is no geotagged frame within {} s of it", window)),
+    };
+
+  /*
+   * If the nearest neighbour has a GPS time, it tells us how far out the camera's clock was, so we can give this one a GPS time too
+   */
+  point.time = camera_time;
+  if let Some(offset) = used.iter().min_by_key(|(_, s)| s.abs()).and_then(|(n, _)| n.utc_offset)
+    {
+      point.time = camera_time + Duration::seconds(offset);
+    }
+
+  Ok((point, used))
+}
+
+
+/** has_gps_time
+  fn has_gps_time(used: &[(&Neighbour, i64)]) -> bool
+
+  Returns true if the nearest of the neighbours used had a GPS time, and so the position's time is in UTC.
+**/
+pub fn has_gps_time(used: &[(&Neighbour, i64)]) -> bool
+{
+  used.iter().min_by_key(|(_, s)| s.abs()).map(|(n, _)| n.utc_offset.is_some()).unwrap_or(false)
+}
+
+
+/** describe
+  fn describe(used: &[(&Neighbour, i64)]) -> String
+
+  Lists the neighbours a position came from, e.g. "DSC_0001.NEF (12 s before) and DSC_0004.NEF (40 s after)".
+**/
+pub fn describe(used: &[(&Neighbour, i64)]) -> String
+{
+  used.iter()
+      .map(|(n, s)| if *s < 0 { format!("{} ({} s before)", n.name, -s) } else if *s > 0 { format!("{} ({} s after)", n.name, s) } else { format!("{} (same time)", n.name) })
+      .collect::<Vec<String>>()
+      .join(" and ")
+}
@@ -0,0 +1,773 @@
+/*
+ * nksc.rs
+ * A small document model for Nikon sidecar (NKSC) files.
+ *
+ * An NKSC file is RDF/XML. The bits we care about are the ast:* properties hanging directly off the rdf:Description, each of which looks like
+ *
+ *        <ast:GPSLatitude rdf:parseType="Resource">
+ *            <rdf:value>AAAAAACAQkAAAAAAAAAAAAAAAAAAAAAA</rdf:value>
+ *            <astype:Type>Double</astype:Type>
+ *        </ast:GPSLatitude>
+ *
+ * Alongside them we can also keep plain XMP text properties, such as the IPTC photoshop:City, in the same rdf:Description. Those are
+ * all there is in an ordinary .xmp sidecar, so the same model does for reading and writing those too.
+ *
+ * The file is parsed with roxmltree, but we never re-serialise the tree. Instead we remember where in the original text each property lives
+ * and splice our changes into the text, so everything we don't touch comes back out exactly the way NX Studio wrote it.
+ */
+
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+
+use crate::astype::AsValue;
+
+// Define Structures
+
+pub struct Property
+  {
+    pub name: String,                                                          // Property name without the ast: prefix, e.g. "GPSLatitude"
+    pub value: String,                                                         // Contents of <rdf:value>
+    pub astype: String,                                                        // Contents of <astype:Type>, e.g. Long, Double, Binary or Ascii
+    lines: Range<usize>,                                                       // The whole lines the property occupies in the file
+    value_range: Range<usize>,                                                 // Where the raw (escaped) rdf:value text lives in the file
+    astype_range: Range<usize>,                                                // Where the raw astype:Type text lives in the file
+  }
+
+pub struct NkscDocument
+  {
+    text: String,                                                              // The file exactly as we read it, plus any edits we have spliced in
+    properties: Vec<Property>,                                                 // Every ast:* property of the rdf:Description
+    insert_at: usize,                                                          // Start of the line holding </rdf:Description>, where new properties go
+    indent: String,                                                            // Indentation used for a property
+    child_indent: String,                                                      // Indentation used for the rdf:value and astype:Type inside a property
+    newline: &'static str,
+    self_closing: Option<Range<usize>>,                                        // Where the rdf:Description is, if it is an empty <rdf:Description/>
+  }
+
+
+impl NkscDocument
+{
+  /** load
+    fn load(file: &Path) -> Result<NkscDocument, String>
+      file: &Path = path to an nksc file
+
+    Reads a sidecar file from disk and parses it.
+  **/
+  pub fn load(file: &Path) -> Result<NkscDocument, String>
+  {
+    let text = fs::read_to_string(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+
+    NkscDocument::parse(text).map_err(|e| format!("Could not parse {}: {}", file.display(), e))
+  }
+
+
+  /** parse
+    fn parse(text: String) -> Result<NkscDocument, String>
+      text: String = contents of an nksc file
+
+    Parses the XML, finds the rdf:Description which holds the ast:* properties (or the first one if there are none yet) and records
+    where each property lives in the text.
+  **/
+  pub fn parse(text: String) -> Result<NkscDocument, String>
+  {
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut properties = Vec::new();
+    let insert_at;
+    let self_closing;
+    let mut indent = String::new();
+    let mut child_indent = String::new();
+
+    {
+      let xml = Document::parse(&text).map_err(|e| e.to_string())?;
+
+      /*
+       * Find our rdf:Description. Most sidecars only have the one that matters, but if there are several we want the one that already
+       * has ast:* properties in it, since that is where NX Studio will look for more of them.
+       */
+      let description = find_description(&xml).ok_or("no rdf:Description element")?;
+
+      for child in description.children().filter(|c| is_ast_property(c))
+        {
+          let value = child.children().find(|c| c.is_element() && c.tag_name().name() == "value");
+          let astype = child.children().find(|c| c.is_element() && c.tag_name().name() == "Type");
+
+          if let (Some(value), Some(astype)) = (value, astype)
+            {
+              if indent.is_empty()
+                {
+                  indent = indentation_before(&text, child.range().start);
+                  child_indent = indentation_before(&text, value.range().start);
+                }
+
+              properties.push(Property
+                {
+                  name: child.tag_name().name().to_string(),
+                  value: value.text().unwrap_or("").to_string(),
+                  astype: astype.text().unwrap_or("").to_string(),
+                  lines: whole_lines(&text, child.range()),
+                  value_range: inner_range(&text, value.range()),
+                  astype_range: inner_range(&text, astype.range()),
+                });
+            }
+        }
+
+      /*
+       * New properties go on the line before </rdf:Description>. If the description has no children we can copy the indentation
+       * from we fall back on whatever its closing tag is indented by, plus a bit. One written as <rdf:Description .../> has no closing
+       * tag to go before, so it has to be opened up before anything is added to it.
+       */
+      self_closing = Some(description.range()).filter(|r| text[r.clone()].ends_with("/>"));
+
+      let end_tag = inner_range(&text, description.range()).end;
+      insert_at = text[..end_tag].rfind('\n').map(|i| i + 1).unwrap_or(end_tag);
+
+      if indent.is_empty()
+        {
+          indent = description.children()
+                              .find(|c| c.is_element())
+                              .map(|c| indentation_before(&text, c.range().start))
+                              .unwrap_or_else(|| format!("{}  ", indentation_before(&text, end_tag)));
+        }
+    }
+
+    if child_indent.is_empty()
+      {
+        child_indent = format!("{}    ", indent);
+      }
+
+    Ok(NkscDocument { text, properties, insert_at, indent, child_indent, newline, self_closing })
+  }
+
+
+  /** has
+    fn has(&self, name: &str) -> bool
+      name: &str = property name without the ast: prefix
+
+    Returns true if the sidecar has the given ast:* property.
+  **/
+  pub fn has(&self, name: &str) -> bool
+  {
+    self.get(name).is_some()
+  }
+
+
+  /** get
+    fn get(&self, name: &str) -> Option<&Property>
+      name: &str = property name without the ast: prefix
+
+    Returns the given ast:* property if the sidecar has it.
+  **/
+  pub fn get(&self, name: &str) -> Option<&Property>
+  {
+    self.properties.iter().find(|p| p.name == name)
+  }
+
+
+  /** get_value
+    fn get_value(&self, name: &str) -> Option<Result<AsValue, String>>
+      name: &str = property name without the ast: prefix
+
+    Returns the decoded value of the given ast:* property if the sidecar has it, or an error if the value doesn't match its astype:Type.
+  **/
+  pub fn get_value(&self, name: &str) -> Option<Result<AsValue, String>>
+  {
+    self.get(name).map(|p| AsValue::decode(&p.astype, &p.value).map_err(|e| format!("{}: {}", p.name, e)))
+  }
+
+
+  /** properties
+    fn properties(&self) -> &[Property]
+
+    Returns every ast:* property of the sidecar in the order they appear in the file.
+  **/
+  pub fn properties(&self) -> &[Property]
+  {
+    &self.properties
+  }
+
+
+  /** set_value
+    fn set_value(&mut self, name: &str, value: &AsValue) -> Result<(), String>
+      name: &str = property name without the ast: prefix
+      value: &AsValue = the new value
+
+    Encodes a value and stores it in the given property, adding the property if need be.
+  **/
+  pub fn set_value(&mut self, name: &str, value: &AsValue) -> Result<(), String>
+  {
+    self.set(name, value.astype(), &value.encode())
+  }
+
+
+  /** set
+    fn set(&mut self, name: &str, astype: &str, value: &str) -> Result<(), String>
+      name: &str = property name without the ast: prefix
+      astype: &str = the astype:Type of the value, e.g. Long or Double
+      value: &str = the rdf:value
+
+    Changes the value of an existing property in place, or adds a new property at the end of the rdf:Description if it isn't there yet.
+    If earlier runs have left more than one copy of the property behind, only the first survives. If the edit would leave the sidecar
+    unreadable, it is left as it was and the reason returned.
+  **/
+  fn set(&mut self, name: &str, astype: &str, value: &str) -> Result<(), String>
+  {
+    self.remove_copies(name, true)?;
+    self.open_description()?;
+
+    let before = self.text.clone();
+
+    if let Some(property) = self.get(name)
+      {
+        let value_range = property.value_range.clone();
+        let astype_range = property.astype_range.clone();
+
+        /*
+         * Splice from the back of the file towards the front so the first range is still good after the second edit
+         */
+        if astype_range.start > value_range.start
+          {
+            self.text.replace_range(astype_range, &escape(astype));
+            self.text.replace_range(value_range, &escape(value));
+          }
+        else
+          {
+            self.text.replace_range(value_range, &escape(value));
+            self.text.replace_range(astype_range, &escape(astype));
+          }
+      }
+    else
+      {
+        let xml = format!("{i}<ast:{name} rdf:parseType=\"Resource\">{nl}\
+                           {c}<rdf:value>{value}</rdf:value>{nl}\
+                           {c}<astype:Type>{astype}</astype:Type>{nl}\
+                           {i}</ast:{name}>{nl}",
+                           i = self.indent, c = self.child_indent, nl = self.newline,
+                           name = name, value = escape(value), astype = escape(astype));
+
+        self.text.insert_str(self.insert_at, &xml);
+      }
+
+    self.reparse(before)
+  }
+
+
+  /** remove
+    fn remove(&mut self, name: &str) -> Result<bool, String>
+      name: &str = property name without the ast: prefix
+
+    Removes every copy of the given property from the sidecar. Returns true if anything was removed.
+  **/
+  pub fn remove(&mut self, name: &str) -> Result<bool, String>
+  {
+    self.remove_copies(name, false)
+  }
+
+
+  /** remove_copies
+    fn remove_copies(&mut self, name: &str, keep_first: bool) -> Result<bool, String>
+
+    Removes copies of a property, either all of them or all but the first. Returns true if anything was removed.
+  **/
+  fn remove_copies(&mut self, name: &str, keep_first: bool) -> Result<bool, String>
+  {
+    let skip = if keep_first { 1 } else { 0 };
+    let lines: Vec<Range<usize>> = self.properties.iter().filter(|p| p.name == name).skip(skip).map(|p| p.lines.clone()).collect();
+
+    if lines.is_empty()
+      {
+        return Ok(false);
+      }
+
+    let before = self.text.clone();
+
+    for range in lines.into_iter().rev()                                       // properties are in file order, so work from the back
+      {
+        self.text.replace_range(range, "");
+      }
+
+    self.reparse(before)?;
+    Ok(true)
+  }
+
+
+  /** set_text_property
+    fn set_text_property(&mut self, prefix: &str, namespace: &str, name: &str, value: &str) -> Result<(), String>
+      prefix: &str = the prefix to declare for the namespace if the file doesn't already have one, e.g. "photoshop"
+      namespace: &str = the namespace URI of the property
+      name: &str = property name without a prefix
+      value: &str = the new text
+
+    Changes the text of a plain XMP property of the rdf:Description in place, or adds it (and declares its namespace on the
+    rdf:Description) if it isn't there yet. Any extra copies are removed, including one written as an attribute of the rdf:Description,
+    the shorthand Lightroom uses.
+  **/
+  pub fn set_text_property(&mut self, prefix: &str, namespace: &str, name: &str, value: &str) -> Result<(), String>
+  {
+    self.open_description()?;
+
+    let before = self.text.clone();
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+
+    if let Ok(xml) = Document::parse(&self.text)
+      {
+        if let Some(description) = find_description(&xml)
+          {
+            edits.extend(attribute_ranges(&self.text, &description, namespace, name).into_iter().map(|r| (r, String::new())));
+
+            let copies: Vec<Node> = description.children()
+                                               .filter(|c| c.is_element() && c.tag_name().namespace() == Some(namespace) && c.tag_name().name() == name)
+                                               .collect();
+
+            if let Some(first) = copies.first()
+              {
+                edits.push((inner_range(&self.text, first.range()), escape(value)));
+                edits.extend(copies[1..].iter().map(|c| (whole_lines(&self.text, c.range()), String::new())));
+              }
+            else
+              {
+                let prefix = match description.lookup_prefix(namespace)
+                  {
+                    Some(existing) => existing.to_string(),
+                    None =>
+                      {
+                        let after_attributes = description.attributes().map(|a| a.range().end).max().unwrap_or(description.range().start);
+                        let start_tag_end = after_attributes + self.text[after_attributes..].find('>').unwrap_or(0);
+                        edits.push((start_tag_end..start_tag_end, format!(" xmlns:{}=\"{}\"", prefix, namespace)));
+                        prefix.to_string()
+                      },
+                  };
+
+                edits.push((self.insert_at..self.insert_at, format!("{i}<{p}:{n}>{v}</{p}:{n}>{nl}", i = self.indent, p = prefix, n = name, v = escape(value), nl = self.newline)));
+              }
+          }
+      }
+
+    edits.sort_by_key(|(range, _)| range.start);
+
+    for (range, text) in edits.into_iter().rev()                               // edits are in file order, so work from the back
+      {
+        self.text.replace_range(range, &text);
+      }
+
+    self.reparse(before)
+  }
+
+
+  /** remove_text_property
+    fn remove_text_property(&mut self, namespace: &str, name: &str) -> Result<(), String>
+
+    Removes every copy of a plain XMP property from the rdf:Description, whether an element or an attribute.
+  **/
+  pub fn remove_text_property(&mut self, namespace: &str, name: &str) -> Result<(), String>
+  {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+
+    if let Ok(xml) = Document::parse(&self.text)
+      {
+        if let Some(description) = find_description(&xml)
+          {
+            ranges.extend(attribute_ranges(&self.text, &description, namespace, name));
+            ranges.extend(description.children()
+                                     .filter(|c| c.is_element() && c.tag_name().namespace() == Some(namespace) && c.tag_name().name() == name)
+                                     .map(|c| whole_lines(&self.text, c.range())));
+          }
+      }
+
+    if ranges.is_empty()
+      {
+        return Ok(());
+      }
+
+    let before = self.text.clone();
+
+    for range in ranges.into_iter().rev()
+      {
+        self.text.replace_range(range, "");
+      }
+
+    self.reparse(before)
+  }
+
+
+  /** text_property
+    fn text_property(&self, namespace: &str, name: &str) -> Option<String>
+
+    Returns the text of a plain XMP property of the rdf:Description, written either as an element or as an attribute.
+  **/
+  pub fn text_property(&self, namespace: &str, name: &str) -> Option<String>
+  {
+    let xml = Document::parse(&self.text).ok()?;
+    let description = find_description(&xml)?;
+
+    if let Some(value) = description.attribute((namespace, name))
+      {
+        return Some(value.to_string());
+      }
+
+    description.children()
+               .find(|c| c.is_element() && c.tag_name().namespace() == Some(namespace) && c.tag_name().name() == name)
+               .map(|c| c.text().unwrap_or("").to_string())
+  }
+
+
+  /** setting
+    fn setting(&self, key: &str) -> Option<String>
+      key: &str = name of an NX Studio adjustment, e.g. "NoiseReduction.chkSpike"
+
+    Some of the adjustments NX Studio makes are not ast:* properties but live in an escaped XML packet embedded inside one. In the file they look like
+    NoiseReduction.chkSpike"&gt;1&lt; and once the XML is unescaped that becomes NoiseReduction.chkSpike">1<. This returns the value of the named one,
+    or None if the copies of it in the sidecar don't all agree.
+  **/
+  pub fn setting(&self, key: &str) -> Option<String>
+  {
+    let xml = Document::parse(&self.text).ok()?;
+    let pattern = format!("{}\">", key);
+    let mut values: Vec<String> = Vec::new();
+
+    for text in xml.descendants().filter(|n| n.is_text()).filter_map(|n| n.text())
+      {
+        for (idx, _) in text.match_indices(&pattern)
+          {
+            let rest = &text[idx + pattern.len()..];
+            values.push(rest[..rest.find('<').unwrap_or(rest.len())].to_string());
+          }
+      }
+
+    match values.split_first()
+      {
+        Some((first, rest)) if rest.iter().all(|v| v == first) => Some(first.clone()),
+        _ => None,                                                             // Copies which disagree are as good as not being set
+      }
+  }
+
+
+  /** set_setting
+    fn set_setting(&mut self, key: &str, value: &str) -> Result<bool, String>
+      key: &str = name of an NX Studio adjustment, e.g. "NoiseReduction.chkSpike"
+      value: &str = the new value
+
+    Changes the value of every copy of an embedded adjustment (see setting). Returns false if the sidecar doesn't have it.
+  **/
+  pub fn set_setting(&mut self, key: &str, value: &str) -> Result<bool, String>
+  {
+    let mut edits: Vec<Range<usize>> = Vec::new();
+
+    if let Ok(xml) = Document::parse(&self.text)
+      {
+        let pattern = format!("{}\"&gt;", key);
+
+        for node in xml.descendants().filter(|n| n.is_text())
+          {
+            let range = node.range();
+            for (idx, _) in self.text[range.clone()].match_indices(&pattern)
+              {
+                let start = range.start + idx + pattern.len();
+                let length = self.text[start..range.end].find("&lt;").unwrap_or(range.end - start);
+                edits.push(start..start + length);
+              }
+          }
+      }
+
+    if edits.is_empty()
+      {
+        return Ok(false);
+      }
+
+    let before = self.text.clone();
+
+    for range in edits.into_iter().rev()                                       // Back to front, so the earlier ranges still line up
+      {
+        self.text.replace_range(range, &escape(value));
+      }
+    self.reparse(before)?;
+    Ok(true)
+  }
+
+
+  /** save
+    fn save(&self, file: &Path, i_want_to_save_the_original_file: bool) -> Result<(), String>
+      file: &Path = path to write the sidecar to
+      i_want_to_save_the_original_file: bool = rename the existing file to .original before writing
+
+    Writes the sidecar back to disk, or says why it couldn't, e.g. because the folder is read-only.
+    If there is already a backup, we won't make another. The logic is the original backup will be the original file, and I don't really
+    want to loose the original original file. Besides, this isn't the sort of thing that we'd be doing more than once anyway.
+  **/
+  pub fn save(&self, file: &Path, i_want_to_save_the_original_file: bool) -> Result<(), String>
+  {
+    if i_want_to_save_the_original_file
+      {
+        let backup:String=format!("{}.original",file.display());
+        let backup_Path=Path::new(&backup);
+        if (!backup_Path.exists())
+          {
+            fs::rename(file, backup_Path).map_err(|e| format!("backing up {} failed: {}", file.display(), e))?;
+          }
+      }
+
+    fs::write(file, self.text.as_bytes()).map_err(|e| format!("writing {} failed: {}", file.display(), e))
+  }
+
+
+  /** open_description
+    fn open_description(&mut self) -> Result<(), String>
+
+    Turns an empty <rdf:Description .../> into <rdf:Description ...></rdf:Description>, with the closing tag on a line of its own, so
+    there is somewhere to put new properties. Anything else is left alone.
+  **/
+  fn open_description(&mut self) -> Result<(), String>
+  {
+    if let Some(range) = self.self_closing.clone()
+      {
+        let before = self.text.clone();
+        let tag: String = self.text[range.start + 1..].chars().take_while(|c| !c.is_whitespace() && *c != '/' && *c != '>').collect();
+        let closing = format!(">{}{}</{}>", self.newline, indentation_before(&self.text, range.start), tag);
+
+        self.text.replace_range(range.end - 2..range.end, &closing);
+        self.reparse(before)?;
+      }
+
+    Ok(())
+  }
+
+
+  /** reparse
+    fn reparse(&mut self, before: String) -> Result<(), String>
+      before: String = the text as it was before the edit
+
+    After an edit the ranges we have remembered are stale, so parse the text again to pick up the new ones. The files are tiny so
+    this costs next to nothing, and it means the model always agrees with what will be written. If the edit has left something we can't
+    parse, e.g. a value with a character XML can't hold, the text goes back to how it was and the edit is refused.
+  **/
+  fn reparse(&mut self, before: String) -> Result<(), String>
+  {
+    let text = std::mem::take(&mut self.text);
+
+    match NkscDocument::parse(text)
+      {
+        Ok(parsed) =>
+          {
+            *self = parsed;
+            Ok(())
+          },
+        Err(e) =>
+          {
+            self.text = before;
+            Err(format!("the change would leave the sidecar unreadable ({})", e))
+          },
+      }
+  }
+}
+
+
+/** find_description
+  fn find_description<'a, 'input>(xml: &'a Document<'input>) -> Option<Node<'a, 'input>>
+
+  Finds our rdf:Description. Most sidecars only have the one that matters, but if there are several we want the one that already has
+  ast:* properties in it, since that is where NX Studio will look for more of them.
+**/
+fn find_description<'a, 'input>(xml: &'a Document<'input>) -> Option<Node<'a, 'input>>
+{
+  let descriptions: Vec<Node> = xml.descendants().filter(|n| n.is_element() && n.tag_name().name() == "Description").collect();
+
+  descriptions.iter().find(|d| d.children().any(|c| is_ast_property(&c))).or(descriptions.first()).copied()
+}
+
+
+/** is_ast_property
+  fn is_ast_property(node: &Node) -> bool
+
+  Returns true if the node is an ast:* element, i.e. one in whatever namespace the file binds to the ast: prefix.
+**/
+fn is_ast_property(node: &Node) -> bool
+{
+  node.is_element() && node.tag_name().namespace().is_some() && node.tag_name().namespace() == node.lookup_namespace_uri(Some("ast"))
+}
+
+
+/** attribute_ranges
+  fn attribute_ranges(text: &str, description: &Node, namespace: &str, name: &str) -> Vec<Range<usize>>
+
+  Returns where a property written as an attribute of the rdf:Description lives, along with the space before it, so it can be cut out.
+**/
+fn attribute_ranges(text: &str, description: &Node, namespace: &str, name: &str) -> Vec<Range<usize>>
+{
+  description.attributes()
+             .filter(|a| a.namespace() == Some(namespace) && a.name() == name)
+             .map(|a| text[..a.range().start].trim_end().len()..a.range().end)
+             .collect()
+}
+
+
+/** inner_range
+  fn inner_range(text: &str, element: Range<usize>) -> Range<usize>
+
+  Takes the range of a whole element, start tag to end tag, and returns the range of what lies between the tags.
+**/
+fn inner_range(text: &str, element: Range<usize>) -> Range<usize>
+{
+  let start = element.start + text[element.clone()].find('>').map(|i| i + 1).unwrap_or(0);
+  let end = element.start + text[element.clone()].rfind("</").unwrap_or(element.end - element.start);
+
+  if end < start { start..start } else { start..end }
+}
+
+
+/** whole_lines
+  fn whole_lines(text: &str, element: Range<usize>) -> Range<usize>
+
+  Widens the range of an element to take in its indentation and the line break after it, so removing it doesn't leave a blank line.
+  If something other than whitespace shares the line the range is left alone.
+**/
+fn whole_lines(text: &str, element: Range<usize>) -> Range<usize>
+{
+  let line_start = text[..element.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+  let line_end = text[element.end..].find('\n').map(|i| element.end + i + 1).unwrap_or(text.len());
+
+  if text[line_start..element.start].trim().is_empty() && text[element.end..line_end].trim().is_empty()
+    {
+      return line_start..line_end;
+    }
+  element
+}
+
+
+/** indentation_before
+  fn indentation_before(text: &str, idx: usize) -> String
+
+  Returns the whitespace between the start of the line and the given position.
+**/
+fn indentation_before(text: &str, idx: usize) -> String
+{
+  let line_start = text[..idx].rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+  text[line_start..idx].chars().take_while(|c| *c == ' ' || *c == '\t').collect()
+}
+
+
+/** escape
+  fn escape(value: &str) -> String
+
+  Escapes the few characters that can't appear as-is in XML text.
+**/
+fn escape(value: &str) -> String
+{
+  value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  const SIDECAR: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+                         \x20 <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+                         \x20   <rdf:Description rdf:about=\"\" xmlns:ast=\"http://ns.nikon.com/asteroid/1.0/\" xmlns:astype=\"http://ns.nikon.com/asteroid/1.0/type/\">\n\
+                         \x20     <ast:XMLPackets>&lt;x key=\"NoiseReduction.chkSpike\"&gt;0&lt;/x&gt;</ast:XMLPackets>\n\
+                         \x20     <ast:Rating rdf:parseType=\"Resource\">\n\
+                         \x20       <rdf:value>AAAAAA==</rdf:value>\n\
+                         \x20       <astype:Type>Long</astype:Type>\n\
+                         \x20     </ast:Rating>\n\
+                         \x20   </rdf:Description>\n\
+                         \x20 </rdf:RDF>\n\
+                         </x:xmpmeta>\n";
+
+  const PHOTOSHOP: &str = "http://ns.adobe.com/photoshop/1.0/";
+
+  fn rating(nksc: &NkscDocument) -> Option<u32>
+  {
+    match nksc.get_value("Rating")
+      {
+        Some(Ok(AsValue::Long(rating))) => Some(rating),
+        _ => None,
+      }
+  }
+
+  #[test]
+  fn set_and_remove_leave_the_rest_of_the_file_alone()
+  {
+    let mut nksc = NkscDocument::parse(SIDECAR.to_string()).unwrap();
+
+    nksc.set_value("Rating", &AsValue::Long(3)).unwrap();
+    nksc.set_value("GPSAltitude", &AsValue::Double(vec![12.5])).unwrap();
+    nksc.set_text_property("photoshop", PHOTOSHOP, "City", "Sydney & Parramatta").unwrap();
+    assert_eq!(rating(&nksc), Some(3));
+    assert!(nksc.has("GPSAltitude"));
+    assert_eq!(nksc.text_property(PHOTOSHOP, "City").as_deref(), Some("Sydney & Parramatta"));
+
+    let reread = NkscDocument::parse(nksc.text.clone()).unwrap();
+    assert_eq!(reread.text_property(PHOTOSHOP, "City").as_deref(), Some("Sydney & Parramatta"));
+
+    nksc.set_value("Rating", &AsValue::Long(0)).unwrap();
+    assert!(nksc.remove("GPSAltitude").unwrap());
+    assert!(!nksc.remove("GPSAltitude").unwrap());
+    nksc.remove_text_property(PHOTOSHOP, "City").unwrap();
+    assert_eq!(nksc.text.replace(" xmlns:photoshop=\"http://ns.adobe.com/photoshop/1.0/\"", ""), SIDECAR);
+  }
+
+  #[test]
+  fn crlf_files_stay_crlf()
+  {
+    let mut nksc = NkscDocument::parse(SIDECAR.replace('\n', "\r\n")).unwrap();
+
+    nksc.set_value("GPSLatitude", &AsValue::Double(vec![33.0, 51.0, 35.4])).unwrap();
+    nksc.set_text_property("photoshop", PHOTOSHOP, "City", "Sydney").unwrap();
+    nksc.set_setting("NoiseReduction.chkSpike", "1").unwrap();
+
+    assert!(nksc.has("GPSLatitude"));
+    assert_eq!(nksc.text.matches('\n').count(), nksc.text.matches("\r\n").count());
+  }
+
+  #[test]
+  fn duplicate_properties_are_removed()
+  {
+    let copy = "      <ast:Rating rdf:parseType=\"Resource\">\n        <rdf:value>5</rdf:value>\n        <astype:Type>Long</astype:Type>\n      </ast:Rating>\n";
+    let city = "      <photoshop:City>Perth</photoshop:City>\n";
+    let text = SIDECAR.replace("    </rdf:Description>", &format!("{}{}{}    </rdf:Description>", copy, city, city))
+                      .replace("xmlns:ast=", "xmlns:photoshop=\"http://ns.adobe.com/photoshop/1.0/\" xmlns:ast=");
+    let mut nksc = NkscDocument::parse(text).unwrap();
+
+    assert_eq!(nksc.properties().iter().filter(|p| p.name == "Rating").count(), 2);
+    nksc.set_value("Rating", &AsValue::Long(4)).unwrap();
+    nksc.set_text_property("photoshop", PHOTOSHOP, "City", "Sydney").unwrap();
+
+    assert_eq!(nksc.properties().iter().filter(|p| p.name == "Rating").count(), 1);
+    assert_eq!(rating(&nksc), Some(4));
+    assert_eq!(nksc.text.matches("<photoshop:City>").count(), 1);
+    assert_eq!(nksc.text_property(PHOTOSHOP, "City").as_deref(), Some("Sydney"));
+  }
+
+  #[test]
+  fn an_empty_description_is_opened_up()
+  {
+    let text = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+                \x20<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+                \x20 <rdf:Description rdf:about=\"\" xmlns:ast=\"http://ns.nikon.com/asteroid/1.0/\" xmlns:astype=\"http://ns.nikon.com/asteroid/1.0/type/\"/>\n\
+                \x20</rdf:RDF>\n\
+                </x:xmpmeta>\n";
+    let mut nksc = NkscDocument::parse(text.to_string()).unwrap();
+
+    nksc.set_text_property("photoshop", PHOTOSHOP, "City", "Sydney").unwrap();
+    nksc.set_value("Rating", &AsValue::Long(2)).unwrap();
+
+    assert_eq!(nksc.text_property(PHOTOSHOP, "City").as_deref(), Some("Sydney"));
+    assert_eq!(rating(&nksc), Some(2));
+    assert!(nksc.text.contains("  </rdf:Description>\n </rdf:RDF>"));
+  }
+
+  #[test]
+  fn a_value_xml_cant_hold_is_refused()
+  {
+    let mut nksc = NkscDocument::parse(SIDECAR.to_string()).unwrap();
+
+    assert!(nksc.set_text_property("photoshop", PHOTOSHOP, "City", "Syd\u{1}ney").is_err());
+    assert_eq!(nksc.text_property(PHOTOSHOP, "City"), None);
+    assert!(!nksc.text.contains("xmlns:photoshop"));
+    assert_eq!(rating(&nksc), Some(0));
+  }
+}
@@ -0,0 +1,240 @@
+/*
+ * places.rs
+ * Named location bookmarks.
+ *
+ * We keep going back to the same places, so rather than type the position in every time, keep them in a little text file, one a line:
+ *
+ *   # name, latitude, longitude, altitude (m), radius (m)
+ *   Studio, -33.8568, 151.2153, 12, 50
+ *   Govetts Leap, -33.6284, 150.3109
+ *
+ * The altitude and radius can be left off. The radius is how far from the bookmark still counts as being there. A name with a comma in
+ * it goes in double quotes, with any double quotes of its own doubled up, as a spreadsheet would write it: "Circular Quay, Wharf 5"
+ */
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::geo::distance_between;
+
+// Define Structures
+
+#[derive(Debug, Clone)]
+pub struct Place
+  {
+    pub name: String,
+    pub latitude: f64,                                                         // Signed decimal degrees
+    pub longitude: f64,                                                        // Signed decimal degrees
+    pub altitude: Option<f64>,                                                 // Metres
+    pub radius: Option<f64>,                                                   // Metres
+  }
+
+#[derive(Default)]
+pub struct Places
+  {
+    places: Vec<Place>,
+  }
+
+
+impl Places
+{
+  /** load
+    fn load(file: &Path) -> Result<Places, String>
+      file: &Path = path to the bookmarks file
+
+    Reads the bookmarks. A file which doesn't exist yet just means we have no bookmarks.
+  **/
+  pub fn load(file: &Path) -> Result<Places, String>
+  {
+    let mut places = Places::default();
+
+    if !file.exists()
+      {
+        return Ok(places);
+      }
+
+    let text = fs::read_to_string(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+
+    for (number, line) in text.lines().enumerate()
+      {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#')
+          {
+            continue;
+          }
+
+        let fields = split_fields(line).ok_or_else(|| format!("Line {} of {} has a quote which is never closed", number + 1, file.display()))?;
+        let number_at = |i: usize| -> Result<Option<f64>, String>
+          {
+            match fields.get(i).filter(|f| !f.is_empty())
+              {
+                Some(field) => field.parse::<f64>().map(Some).map_err(|_| format!("Line {} of {}: \"{}\" is not a number", number + 1, file.display(), field)),
+                None => Ok(None),
+              }
+          };
+
+        if fields.len() < 3 || fields.len() > 5 || fields[0].is_empty()
+          {
+            return Err(format!("Line {} of {} is not \"name, latitude, longitude[, altitude[, radius]]\"", number + 1, file.display()));
+          }
+
+        let coordinate_at = |i: usize, what: &str| -> Result<f64, String>
+          {
+            number_at(i)?.ok_or_else(|| format!("Line {} of {}: {} has no {}", number + 1, file.display(), fields[0], what))
+          };
+
+        places.places.push(Place
+          {
+            name: fields[0].to_string(),
+            latitude: coordinate_at(1, "latitude")?,
+            longitude: coordinate_at(2, "longitude")?,
+            altitude: number_at(3)?,
+            radius: number_at(4)?,
+          });
+      }
+
+    Ok(places)
+  }
+
+
+  /** save
+    fn save(&self, file: &Path) -> Result<(), String>
+
+    Writes all of the bookmarks back out.
+  **/
+  pub fn save(&self, file: &Path) -> Result<(), String>
+  {
+    let mut text = String::from("# name, latitude, longitude, altitude (m), radius (m)\n");
+
+    for place in &self.places
+      {
+        let optional = |value: Option<f64>| value.map(|v| format!("{}", v)).unwrap_or_default();
+        let line = format!("{}, {:.6}, {:.6}, {}, {}", quote(&place.name), place.latitude, place.longitude, optional(place.altitude), optional(place.radius));
+
+        text.push_str(line.trim_end_matches([',', ' ']));
+        text.push('\n');
+      }
+
+    fs::write(file, text).map_err(|e| format!("Could not write {}: {}", file.display(), e))
+  }
+
+
+  /** find
+    fn find(&self, name: &str) -> Option<&Place>
+
+    Looks up a bookmark by name, ignoring case.
+  **/
+  pub fn find(&self, name: &str) -> Option<&Place>
+  {
+    self.places.iter().find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+  }
+
+
+  /** add
+    fn add(&mut self, place: Place) -> bool
+
+    Adds a bookmark, replacing any with the same name (but keeping its radius, if the new one has none). Returns true if one was replaced.
+  **/
+  pub fn add(&mut self, place: Place) -> bool
+  {
+    match self.places.iter_mut().find(|p| p.name.eq_ignore_ascii_case(&place.name))
+      {
+        Some(existing) => { *existing = Place { radius: place.radius.or(existing.radius), ..place }; true },
+        None => { self.places.push(place); false },
+      }
+  }
+
+
+  /** containing
+    fn containing(&self, latitude: f64, longitude: f64) -> Option<&Place>
+
+    Returns the nearest bookmark whose radius takes in a position.
+  **/
+  pub fn containing(&self, latitude: f64, longitude: f64) -> Option<&Place>
+  {
+    self.places.iter()
+               .filter_map(|p| p.radius.map(|r| (p, r, distance_between(latitude, longitude, p.latitude, p.longitude))))
+               .filter(|(_, radius, distance)| distance <= radius)
+               .min_by(|a, b| a.2.total_cmp(&b.2))
+               .map(|(p, _, _)| p)
+  }
+
+
+  /** count_with_radius
+    fn count_with_radius(&self) -> usize
+
+    Returns how many of the bookmarks have a radius, and so can be used as geofences.
+  **/
+  pub fn count_with_radius(&self) -> usize
+  {
+    self.places.iter().filter(|p| p.radius.is_some()).count()
+  }
+
+
+  pub fn names(&self) -> Vec<&str>
+  {
+    self.places.iter().map(|p| p.name.as_str()).collect()
+  }
+}
+
+
+/** split_fields
+  fn split_fields(line: &str) -> Option<Vec<String>>
+
+  Splits a line of the bookmarks file at its commas, except those inside double quotes, and trims each field. Returns None if a quote
+  is left open.
+**/
+fn split_fields(line: &str) -> Option<Vec<String>>
+{
+  let mut fields = Vec::new();
+  let mut field = String::new();
+  let mut quoted = false;
+  let mut chars = line.chars().peekable();
+
+  while let Some(c) = chars.next()
+    {
+      match (c, quoted)
+        {
+          ('"', true) if chars.peek() == Some(&'"') => { field.push('"'); chars.next(); },
+          ('"', _) => quoted = !quoted,
+          (',', false) => fields.push(std::mem::take(&mut field).trim().to_string()),
+          _ => field.push(c),
+        }
+    }
+
+  if quoted
+    {
+      return None;
+    }
+  fields.push(field.trim().to_string());
+  Some(fields)
+}
+
+
+/** quote
+  fn quote(name: &str) -> String
+
+  Puts a name in double quotes if it has anything in it split_fields would otherwise take the wrong way.
+**/
+fn quote(name: &str) -> String
+{
+  if name.contains([',', '"']) || name.starts_with('#')
+    {
+      format!("\"{}\"", name.replace('"', "\"\""))
+    }
+  else
+    {
+      name.to_string()
+    }
+}
+
+
+/** default_file
+  fn default_file() -> PathBuf
+
+  Where the bookmarks live unless we are told otherwise, .nkscgeosync_places in the user's home directory.
+**/
+pub fn default_file() -> PathBuf
+{
+  crate::home_directory().join(".nkscgeosync_places")
+}
@@ -0,0 +1,149 @@
+/*
+ * timezone.rs
+ * Turning the camera's local time into UTC.
+ *
+ * DateTimeOriginal is whatever the camera's clock said, with no zone, while every track log is in UTC. We can be told the zone (--tz),
+ * the camera may have recorded it (OffsetTimeOriginal), or failing both we can look up which zone the track was in, from the boundaries
+ * built into tzf-rs, so nothing has to be downloaded. A named zone knows its daylight saving rules, so a photo is converted with whatever
+ * offset was in force when it was taken, not the one in force now.
+ */
+
+use chrono::{DateTime, Duration, FixedOffset, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use tzf_rs::DefaultFinder;
+
+// Define Structures
+
+#[derive(Debug, Clone)]
+pub enum Zone
+  {
+    Fixed(FixedOffset),                                                        // e.g. +10:00, as OffsetTimeOriginal gives it
+    Named(Tz),                                                                 // e.g. Australia/Sydney, with its daylight saving rules
+  }
+
+
+impl Zone
+{
+  /** parse
+    fn parse(text: &str) -> Option<Zone>
+      text: &str = a zone name like Australia/Sydney, UTC, or an offset like +10:00
+
+    Parses a zone given on the command line or read from the EXIF.
+  **/
+  pub fn parse(text: &str) -> Option<Zone>
+  {
+    let text = text.trim();
+
+    if let Ok(zone) = text.parse::<Tz>()
+      {
+        return Some(Zone::Named(zone));
+      }
+
+    let sign = match text.chars().next()?
+      {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+      };
+    let (hours, minutes) = text[1..].split_once(':').unwrap_or((&text[1..], "0"));
+    let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+
+    FixedOffset::east_opt(sign * seconds).map(Zone::Fixed)
+  }
+
+
+  /** to_utc
+    fn to_utc(&self, local: NaiveDateTime) -> (DateTime<Utc>, Option<&'static str>)
+      local: NaiveDateTime = a time on a clock set to this zone
+
+    Converts a local time to UTC, returning a note if the local time was one daylight saving makes awkward. In the hour repeated when
+    the clocks go back we can't know which of the two was meant, so take the first. The hour skipped when they go forward never
+    happened, so the clock can't have been changed yet, and we use the offset from before the change.
+  **/
+  pub fn to_utc(&self, local: NaiveDateTime) -> (DateTime<Utc>, Option<&'static str>)
+  {
+    match self
+      {
+        Zone::Fixed(offset) => (DateTime::from_naive_utc_and_offset(local - Duration::seconds(offset.local_minus_utc() as i64), Utc), None),
+        Zone::Named(zone) => match zone.from_local_datetime(&local)
+          {
+            LocalResult::Single(time) => (time.with_timezone(&Utc), None),
+            LocalResult::Ambiguous(earliest, _) => (earliest.with_timezone(&Utc), Some("falls in the hour repeated when daylight saving ended, taking the first")),
+            LocalResult::None =>
+              {
+                let before = zone.from_local_datetime(&(local - Duration::hours(3))).earliest().map(|t| t.offset().fix())
+                               .unwrap_or_else(|| zone.offset_from_utc_datetime(&local).fix());
+                (DateTime::from_naive_utc_and_offset(local - Duration::seconds(before.local_minus_utc() as i64), Utc),
+                 Some("falls in the hour skipped when daylight saving started, taking the clock as not yet changed"))
+              },
+          },
+      }
+  }
+
+
+  pub fn name(&self) -> String
+  {
+    match self
+      {
+        Zone::Fixed(offset) => offset.to_string(),
+        Zone::Named(zone) => zone.name().to_string(),
+      }
+  }
+}
+
+
+/** zone_at
+  fn zone_at(finder: &DefaultFinder, latitude: f64, longitude: f64) -> Option<Zone>
+
+  Looks up which time zone a position is in. Out at sea there may be no named zone, in which case None.
+**/
+pub fn zone_at(finder: &DefaultFinder, latitude: f64, longitude: f64) -> Option<Zone>
+{
+  finder.get_tz_name(longitude, latitude).parse::<Tz>().ok().map(Zone::Named)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn utc(local: &str, zone: &str) -> (String, bool)
+  {
+    let local = NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M:%S").unwrap();
+    let (time, note) = Zone::parse(zone).unwrap().to_utc(local);
+
+    (time.format("%Y-%m-%d %H:%M:%S").to_string(), note.is_some())
+  }
+
+  #[test]
+  fn ordinary_times_are_converted()
+  {
+    assert_eq!(utc("2021-05-03 11:02:03", "Australia/Sydney"), ("2021-05-03 01:02:03".to_string(), false));
+    assert_eq!(utc("2021-01-03 11:02:03", "Australia/Sydney"), ("2021-01-03 00:02:03".to_string(), false));
+    assert_eq!(utc("2021-05-03 11:02:03", "+10:00"), ("2021-05-03 01:02:03".to_string(), false));
+    assert_eq!(utc("2021-05-03 01:02:03", "-05:30"), ("2021-05-03 06:32:03".to_string(), false));
+  }
+
+  /*
+   * Sydney went from +11:00 back to +10:00 at 03:00 on the 4th of April 2021, so 02:30 happened twice. The first was at 15:30 UTC
+   */
+  #[test]
+  fn the_repeated_hour_is_taken_as_the_first()
+  {
+    assert_eq!(utc("2021-04-04 02:30:00", "Australia/Sydney"), ("2021-04-03 15:30:00".to_string(), true));
+    assert_eq!(utc("2021-04-04 03:30:00", "Australia/Sydney"), ("2021-04-03 17:30:00".to_string(), false));
+  }
+
+  /*
+   * Sydney went from +10:00 forward to +11:00 at 02:00 on the 3rd of October 2021, so 02:30 never happened and is taken to be on +10:00
+   * (and New York's 02:30 on the 14th of March on -05:00, a zone west of UTC as well as one east of it)
+   */
+  #[test]
+  fn the_skipped_hour_is_taken_as_before_the_change()
+  {
+    assert_eq!(utc("2021-10-03 02:30:00", "Australia/Sydney"), ("2021-10-02 16:30:00".to_string(), true));
+    assert_eq!(utc("2021-10-03 03:30:00", "Australia/Sydney"), ("2021-10-02 16:30:00".to_string(), false));
+    assert_eq!(utc("2021-03-14 02:30:00", "America/New_York"), ("2021-03-14 07:30:00".to_string(), true));
+  }
+}
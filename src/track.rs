@@ -0,0 +1,228 @@
+/*
+ * track.rs
+ * Track logs as a source of locations.
+ *
+ * Most of our bodies have no GPS of their own, but something in the bag (a phone, a handheld logger) was recording where we were.
+ * Every track format gets boiled down to the same thing, a list of timestamped positions, and all of the lists we are given are
+ * merged into the one track sorted by time. Looking up where we were at a given moment is then the same whatever the logs came from.
+ */
+
+pub mod fit;
+pub mod geojson;
+pub mod gpx;
+pub mod kml;
+pub mod nmea;
+pub mod photos;
+pub mod takeout;
+pub mod tcx;
+
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+// Define Structures
+
+pub type Reader = fn(&Path) -> Result<Vec<TrackPoint>, String>;                // Every track format has a reader that looks like this
+
+#[derive(Debug, Clone)]
+pub struct TrackPoint
+  {
+    pub time: DateTime<Utc>,
+    pub latitude: f64,                                                         // Signed decimal degrees, negative in the southern hemisphere
+    pub longitude: f64,                                                        // Signed decimal degrees, negative west of Greenwich
+    pub altitude: Option<f64>,                                                 // Metres above sea level, if the log has it
+  }
+
+#[derive(Debug, Clone)]
+pub struct Stay
+  {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub latitude: f64,                                                         // Where we were for the whole of it, in signed decimal degrees
+    pub longitude: f64,
+  }
+
+#[derive(Default)]
+pub struct Track
+  {
+    points: Vec<TrackPoint>,                                                   // Always kept sorted by time
+    stays: Vec<Stay>,                                                          // Spells we know we were in the one place, however long
+  }
+
+
+impl Track
+{
+  /** add
+    fn add(&mut self, points: Vec<TrackPoint>)
+      points: Vec<TrackPoint> = points read from a track log
+
+    Adds the points from a log to the track. Logs can be given in any order and can overlap, the track is re-sorted afterwards.
+  **/
+  pub fn add(&mut self, points: Vec<TrackPoint>)
+  {
+    self.points.extend(points);
+    self.points.sort_by_key(|p| p.time);
+  }
+
+
+  /** add_stays
+    fn add_stays(&mut self, stays: Vec<Stay>)
+      stays: Vec<Stay> = spells in the one place, such as the place visits of a Google location history
+
+    Adds spells where we stayed put. Any time inside one is at that place, however far apart the points either side of it are.
+  **/
+  pub fn add_stays(&mut self, stays: Vec<Stay>)
+  {
+    self.stays.extend(stays);
+  }
+
+
+  /** is_empty
+    fn is_empty(&self) -> bool
+
+    Returns true if no track logs have been given.
+  **/
+  pub fn is_empty(&self) -> bool
+  {
+    self.points.is_empty()
+  }
+
+
+  /** first
+    fn first(&self) -> Option<&TrackPoint>
+
+    Returns the earliest point of the track.
+  **/
+  pub fn first(&self) -> Option<&TrackPoint>
+  {
+    self.points.first()
+  }
+
+
+  /** position_at
+    fn position_at(&self, time: DateTime<Utc>, max_gap: f64) -> Result<TrackPoint, String>
+      time: DateTime<Utc> = the moment we want a position for
+      max_gap: f64 = the most seconds we are prepared to bridge between two points, or to reach past either end of the track
+
+    Finds where we were at a given moment. If the time falls between two points of the track, the position is interpolated between them,
+    as long as they are no more than max_gap apart; any wider and we can't really know where we were, so no position is returned. A time
+    just before the start or just after the end of the track takes the position of the nearest end, within the same limit. A time during
+    a stay is at the place of the stay, whatever the gap.
+  **/
+  pub fn position_at(&self, time: DateTime<Utc>, max_gap: f64) -> Result<TrackPoint, String>
+  {
+    if self.points.is_empty()
+      {
+        return Err("the track is empty".to_string());
+      }
+
+    if let Some(stay) = self.stays.iter().find(|s| (s.from <= time) && (time <= s.to))
+      {
+        return Ok(TrackPoint { time, latitude: stay.latitude, longitude: stay.longitude, altitude: None });
+      }
+
+    let idx = self.points.partition_point(|p| p.time < time);                  // first point at or after our time
+
+    if idx < self.points.len() && self.points[idx].time == time
+      {
+        return Ok(self.points[idx].clone());
+      }
+
+    if idx == 0 || idx == self.points.len()
+      {
+        let nearest = if idx == 0 { &self.points[0] } else { &self.points[idx - 1] };
+        let gap = seconds_between(nearest.time, time).abs();
+
+        if gap > max_gap
+          {
+            return Err(format!("{} is {:.0} s outside the track", time.format("%Y-%m-%d %H:%M:%S UTC"), gap));
+          }
+
+        let mut point = nearest.clone();
+        point.time = time;
+        return Ok(point);
+      }
+
+    let before = &self.points[idx - 1];
+    let after = &self.points[idx];
+    let gap = seconds_between(before.time, after.time);
+
+    if gap > max_gap
+      {
+        return Err(format!("{} falls in a {:.0} s gap in the track", time.format("%Y-%m-%d %H:%M:%S UTC"), gap));
+      }
+
+    let fraction = seconds_between(before.time, time) / gap;
+
+    Ok(interpolate(before, after, fraction, time))
+  }
+}
+
+
+/** interpolate
+  fn interpolate(before: &TrackPoint, after: &TrackPoint, fraction: f64, time: DateTime<Utc>) -> TrackPoint
+
+  Returns the point a given fraction of the way from one point to another. Over the distances between two track points a straight line
+  in degrees is as good as a great circle, the only thing to watch out for is a track crossing the 180th meridian.
+**/
+pub fn interpolate(before: &TrackPoint, after: &TrackPoint, fraction: f64, time: DateTime<Utc>) -> TrackPoint
+{
+  let mut delta_longitude = after.longitude - before.longitude;
+
+  if delta_longitude > 180.0
+    {
+      delta_longitude -= 360.0;
+    }
+  else if delta_longitude < -180.0
+    {
+      delta_longitude += 360.0;
+    }
+
+  let mut longitude = before.longitude + delta_longitude * fraction;
+  if longitude > 180.0
+    {
+      longitude -= 360.0;
+    }
+  else if longitude < -180.0
+    {
+      longitude += 360.0;
+    }
+
+  TrackPoint
+    {
+      time,
+      latitude: before.latitude + (after.latitude - before.latitude) * fraction,
+      longitude,
+      altitude: match (before.altitude, after.altitude)
+        {
+          (Some(a), Some(b)) => Some(a + (b - a) * fraction),
+          (a, b) => a.or(b),
+        },
+    }
+}
+
+
+/** seconds_between
+  fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64
+
+  Returns the (signed) number of seconds from one time to another.
+**/
+fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64
+{
+  (to - from).num_milliseconds() as f64 / 1000.0
+}
+
+
+/** parse_time
+  fn parse_time(text: &str) -> Option<DateTime<Utc>>
+    text: &str = an ISO 8601 / RFC 3339 time, e.g. 2021-05-03T01:02:03Z
+
+  Parses a time from a track log. Times without a zone are taken to be UTC, which is what the logs we've seen mean by them.
+**/
+pub fn parse_time(text: &str) -> Option<DateTime<Utc>>
+{
+  let text = text.trim();
+
+  DateTime::parse_from_rfc3339(text).map(|t| t.with_timezone(&Utc)).ok()
+    .or_else(|| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f").ok().map(|t| DateTime::from_naive_utc_and_offset(t, Utc)))
+}
@@ -0,0 +1,381 @@
+/*
+ * fit.rs
+ * Reader for Garmin FIT activity files.
+ *
+ * FIT is a compact binary format. After a 12 or 14 byte header comes a stream of records, each starting with a one byte header:
+ *
+ *   a definition message, which says what the data messages of a "local message type" (0-15) will look like: which global message
+ *   they are, whether they are little or big endian, and the number, size and type of each field
+ *   a data message, whose fields are laid out as the last definition for its local type said they would be
+ *   a compressed timestamp data message, a data message whose header also carries the bottom 5 bits of its time
+ *
+ * All we want are the "record" messages (global message 20), which is where the device logs where it was every second or so. Of their
+ * fields we only need the timestamp (253, seconds since 1989-12-31 UTC), position_lat and position_long (0 and 1, in semicircles) and
+ * the altitude (2, or enhanced_altitude 78, in fifths of a metre offset by 500 m). On devices with a barometric altimeter the altitude is
+ * the barometric one, which is much steadier than the GPS's. Everything else is skipped over by size, which is the beauty of the format.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+use super::TrackPoint;
+
+const FIT_EPOCH: i64 = 631_065_600;                                            // 1989-12-31 00:00:00 UTC as a Unix time
+const RECORD: u16 = 20;                                                        // The global message number of a record message
+
+const FIELD_LATITUDE: u8 = 0;
+const FIELD_LONGITUDE: u8 = 1;
+const FIELD_ALTITUDE: u8 = 2;
+const FIELD_ENHANCED_ALTITUDE: u8 = 78;
+const FIELD_TIMESTAMP: u8 = 253;
+
+// Define Structures
+
+#[derive(Clone)]
+struct Definition
+  {
+    little_endian: bool,
+    global: u16,
+    fields: Vec<(u8, usize)>,                                                  // Field number and size of each field, in order
+    developer_size: usize,                                                     // Total size of the developer fields, which we skip
+  }
+
+
+/** read
+  fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+    file: &Path = path to a .fit file
+
+  Reads every record message with a position and a time out of a FIT file.
+**/
+pub fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+{
+  let data = fs::read(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+
+  parse(&data).map_err(|e| format!("{} {}", file.display(), e))
+}
+
+
+/** parse
+  fn parse(data: &[u8]) -> Result<Vec<TrackPoint>, &'static str>
+    data: &[u8] = the contents of a .fit file
+
+  Reads every record message with a position and a time out of FIT data. Errors say what is wrong with the file, to follow its name.
+**/
+fn parse(data: &[u8]) -> Result<Vec<TrackPoint>, &'static str>
+{
+  if data.len() < 12 || &data[8..12] != b".FIT"
+    {
+      return Err("is not a FIT file");
+    }
+
+  let header_size = data[0] as usize;
+  let data_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+  let end = (header_size + data_size).min(data.len());
+  let mut definitions: Vec<Option<Definition>> = vec![None; 16];
+  let mut last_timestamp: Option<u32> = None;
+  let mut points = Vec::new();
+  let mut position = header_size;
+
+  while position < end
+    {
+      let header = data[position];
+      position += 1;
+
+      if header & 0x80 != 0
+        {
+          /*
+           * Compressed timestamp header, the time is the last one we saw with its bottom 5 bits replaced (rolling over if they went backwards)
+           */
+          let local = ((header >> 5) & 0x03) as usize;
+          let offset = (header & 0x1f) as u32;
+          let timestamp = last_timestamp.map(|last| if offset >= (last & 0x1f) { (last & !0x1f) + offset } else { (last & !0x1f) + offset + 0x20 });
+          let definition = definitions[local].clone().ok_or("uses a message type before defining it")?;
+
+          position = data_message(data, position, &definition, timestamp, &mut last_timestamp, &mut points)
+                       .ok_or("is truncated")?;
+        }
+      else if header & 0x40 != 0
+        {
+          let local = (header & 0x0f) as usize;
+          let (definition, next) = definition_message(data, position, header & 0x20 != 0).ok_or("is truncated")?;
+
+          definitions[local] = Some(definition);
+          position = next;
+        }
+      else
+        {
+          let local = (header & 0x0f) as usize;
+          let definition = definitions[local].clone().ok_or("uses a message type before defining it")?;
+
+          position = data_message(data, position, &definition, None, &mut last_timestamp, &mut points)
+                       .ok_or("is truncated")?;
+        }
+    }
+
+  if points.is_empty()
+    {
+      return Err("has no records with a position");
+    }
+
+  Ok(points)
+}
+
+
+/** definition_message
+  fn definition_message(data: &[u8], position: usize, has_developer_fields: bool) -> Option<(Definition, usize)>
+
+  Reads a definition message, returning it and where the next record starts.
+**/
+fn definition_message(data: &[u8], position: usize, has_developer_fields: bool) -> Option<(Definition, usize)>
+{
+  let little_endian = *data.get(position + 1)? == 0;
+  let global_bytes = [*data.get(position + 2)?, *data.get(position + 3)?];
+  let global = if little_endian { u16::from_le_bytes(global_bytes) } else { u16::from_be_bytes(global_bytes) };
+  let count = *data.get(position + 4)? as usize;
+  let mut position = position + 5;
+  let mut fields = Vec::with_capacity(count);
+
+  for _ in 0..count
+    {
+      fields.push((*data.get(position)?, *data.get(position + 1)? as usize));
+      position += 3;
+    }
+
+  let mut developer_size = 0;
+  if has_developer_fields
+    {
+      let count = *data.get(position)? as usize;
+      position += 1;
+
+      for _ in 0..count
+        {
+          developer_size += *data.get(position + 1)? as usize;
+          position += 3;
+        }
+    }
+
+  Some((Definition { little_endian, global, fields, developer_size }, position))
+}
+
+
+/** data_message
+  fn data_message(data: &[u8], position: usize, definition: &Definition, timestamp: Option<u32>, last_timestamp: &mut Option<u32>,
+                  points: &mut Vec<TrackPoint>) -> Option<usize>
+
+  Reads a data message laid out as its definition says, adding a point if it is a record with a position. Returns where the next record
+  starts.
+**/
+fn data_message(data: &[u8], position: usize, definition: &Definition, timestamp: Option<u32>, last_timestamp: &mut Option<u32>,
+                points: &mut Vec<TrackPoint>) -> Option<usize>
+{
+  let mut position = position;
+  let mut timestamp = timestamp;
+  let mut latitude: Option<i32> = None;
+  let mut longitude: Option<i32> = None;
+  let mut altitude: Option<f64> = None;
+
+  for (number, size) in &definition.fields
+    {
+      let bytes = data.get(position..position + size)?;
+      position += size;
+
+      let value = match size
+        {
+          2 => Some(if definition.little_endian { u16::from_le_bytes([bytes[0], bytes[1]]) as u32 } else { u16::from_be_bytes([bytes[0], bytes[1]]) as u32 }),
+          4 => Some(if definition.little_endian { u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) }
+                    else { u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) }),
+          _ => None,
+        };
+
+      match (*number, *size, value)
+        {
+          (FIELD_TIMESTAMP, 4, Some(v)) if v != u32::MAX => timestamp = Some(v),
+          (FIELD_LATITUDE, 4, Some(v)) if v != 0x7fff_ffff => latitude = Some(v as i32),
+          (FIELD_LONGITUDE, 4, Some(v)) if v != 0x7fff_ffff => longitude = Some(v as i32),
+          (FIELD_ALTITUDE, 2, Some(v)) if v != 0xffff && altitude.is_none() => altitude = Some(v as f64 / 5.0 - 500.0),
+          (FIELD_ENHANCED_ALTITUDE, 4, Some(v)) if v != u32::MAX => altitude = Some(v as f64 / 5.0 - 500.0),
+          _ => {},
+        }
+    }
+
+  position += definition.developer_size;
+  if position > data.len()
+    {
+      return None;
+    }
+
+  if timestamp.is_some()
+    {
+      *last_timestamp = timestamp;
+    }
+
+  if definition.global == RECORD
+    {
+      if let (Some(timestamp), Some(latitude), Some(longitude)) = (timestamp, latitude, longitude)
+        {
+          if let Some(time) = DateTime::<Utc>::from_timestamp(FIT_EPOCH + timestamp as i64, 0)
+            {
+              points.push(TrackPoint { time, latitude: semicircles_to_degrees(latitude), longitude: semicircles_to_degrees(longitude), altitude });
+            }
+        }
+    }
+
+  Some(position)
+}
+
+
+/** semicircles_to_degrees
+  fn semicircles_to_degrees(semicircles: i32) -> f64
+
+  FIT stores angles in semicircles, where 2^31 of them make 180 degrees.
+**/
+fn semicircles_to_degrees(semicircles: i32) -> f64
+{
+  semicircles as f64 * 180.0 / 2_147_483_648.0
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  const START: u32 = 988_938_110;                                              // 2021-05-03 01:01:50 UTC, whose bottom 5 bits are 30
+
+  fn fit(records: &[Vec<u8>]) -> Vec<u8>
+  {
+    let records = records.concat();
+    let mut data = vec![12, 0x10, 0x08, 0x08];
+
+    data.extend_from_slice(&(records.len() as u32).to_le_bytes());
+    data.extend_from_slice(b".FIT");
+    data.extend_from_slice(&records);
+    data.extend_from_slice(&[0, 0]);                                           // The CRC, which we don't check
+    data
+  }
+
+  fn definition(local: u8, little_endian: bool, global: u16, fields: &[(u8, u8)], developer_fields: &[u8]) -> Vec<u8>
+  {
+    let header = 0x40 | local | if developer_fields.is_empty() { 0 } else { 0x20 };
+    let mut record = vec![header, 0, if little_endian { 0 } else { 1 }];
+
+    record.extend_from_slice(&if little_endian { global.to_le_bytes() } else { global.to_be_bytes() });
+    record.push(fields.len() as u8);
+    for (number, size) in fields
+      {
+        record.extend_from_slice(&[*number, *size, 0]);
+      }
+    if !developer_fields.is_empty()
+      {
+        record.push(developer_fields.len() as u8);
+        for (i, size) in developer_fields.iter().enumerate()
+          {
+            record.extend_from_slice(&[i as u8, *size, 0]);
+          }
+      }
+    record
+  }
+
+  fn semicircles(degrees: f64) -> u32
+  {
+    (degrees * 2_147_483_648.0 / 180.0).round() as i32 as u32
+  }
+
+  fn values(header: u8, little_endian: bool, values: &[u32], sizes: &[usize]) -> Vec<u8>
+  {
+    let mut record = vec![header];
+
+    for (value, size) in values.iter().zip(sizes)
+      {
+        let bytes = if little_endian { value.to_le_bytes() } else { value.to_be_bytes() };
+        record.extend_from_slice(if little_endian { &bytes[..*size] } else { &bytes[4 - size..] });
+      }
+    record
+  }
+
+  fn check(points: &[TrackPoint], seconds: &[u32])
+  {
+    let times: Vec<u32> = points.iter().map(|p| (p.time.timestamp() - FIT_EPOCH) as u32 - START).collect();
+
+    assert_eq!(times, seconds);
+    for point in points
+      {
+        assert!((point.latitude - -33.859833).abs() < 1e-6);
+        assert!((point.longitude - 151.208333).abs() < 1e-6);
+      }
+  }
+
+  #[test]
+  fn little_endian_records_are_read()
+  {
+    let fields = [(FIELD_TIMESTAMP, 4), (FIELD_LATITUDE, 4), (FIELD_LONGITUDE, 4), (FIELD_ALTITUDE, 2)];
+    let data = fit(&[definition(0, true, RECORD, &fields, &[]),
+                     values(0x00, true, &[START, semicircles(-33.859833), semicircles(151.208333), 2560], &[4, 4, 4, 2])]);
+    let points = parse(&data).unwrap();
+
+    check(&points, &[0]);
+    assert_eq!(points[0].altitude, Some(12.0));
+  }
+
+  #[test]
+  fn big_endian_records_are_read()
+  {
+    let fields = [(FIELD_TIMESTAMP, 4), (FIELD_LATITUDE, 4), (FIELD_LONGITUDE, 4), (FIELD_ALTITUDE, 2), (FIELD_ENHANCED_ALTITUDE, 4)];
+    let data = fit(&[definition(3, false, RECORD, &fields, &[]),
+                     values(0x03, false, &[START, semicircles(-33.859833), semicircles(151.208333), 0xffff, 2565], &[4, 4, 4, 2, 4])]);
+    let points = parse(&data).unwrap();
+
+    check(&points, &[0]);
+    assert_eq!(points[0].altitude, Some(13.0));
+  }
+
+  /*
+   * The last full timestamp ends in 30, so an offset of 31 is a second later and one of 2 has rolled over to four seconds later
+   */
+  #[test]
+  fn compressed_timestamps_follow_the_last_full_one()
+  {
+    let position = [semicircles(-33.859833), semicircles(151.208333)];
+    let data = fit(&[definition(0, true, RECORD, &[(FIELD_TIMESTAMP, 4), (FIELD_LATITUDE, 4), (FIELD_LONGITUDE, 4)], &[]),
+                     definition(1, true, RECORD, &[(FIELD_LATITUDE, 4), (FIELD_LONGITUDE, 4)], &[]),
+                     values(0x00, true, &[START, position[0], position[1]], &[4, 4, 4]),
+                     values(0x80 | 1 << 5 | 31, true, &position, &[4, 4]),
+                     values(0x80 | 1 << 5 | 2, true, &position, &[4, 4])]);
+
+    check(&parse(&data).unwrap(), &[0, 1, 4]);
+  }
+
+  /*
+   * Developer fields and messages other than records are skipped over, leaving the record after them where it should be
+   */
+  #[test]
+  fn developer_fields_and_other_messages_are_skipped()
+  {
+    let fields = [(FIELD_TIMESTAMP, 4), (FIELD_LATITUDE, 4), (FIELD_LONGITUDE, 4)];
+    let position = [semicircles(-33.859833), semicircles(151.208333)];
+    let mut first = values(0x00, true, &[START, position[0], position[1]], &[4, 4, 4]);
+
+    first.extend_from_slice(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee]);
+    let data = fit(&[definition(0, true, RECORD, &fields, &[2, 3]),
+                     first,
+                     definition(1, false, 21, &[(FIELD_TIMESTAMP, 4), (3, 1)], &[]),
+                     vec![0x01, 0x3b, 0x8e, 0x4a, 0x8f, 0x00],
+                     definition(0, true, RECORD, &fields, &[]),
+                     values(0x00, true, &[START + 5, position[0], position[1]], &[4, 4, 4])]);
+
+    check(&parse(&data).unwrap(), &[0, 5]);
+  }
+
+  #[test]
+  fn files_that_are_not_fit_or_end_early_are_refused()
+  {
+    let mut data = fit(&[definition(0, true, RECORD, &[(FIELD_TIMESTAMP, 4), (FIELD_LATITUDE, 4), (FIELD_LONGITUDE, 4)], &[]),
+                         values(0x00, true, &[START, 1, 2], &[4, 4, 4])]);
+
+    data.truncate(data.len() - 4);
+    assert_eq!(parse(&data).unwrap_err(), "is truncated");
+    assert_eq!(parse(b"not a fit file at all").unwrap_err(), "is not a FIT file");
+  }
+}
@@ -0,0 +1,135 @@
+/*
+ * geojson.rs
+ * Reader for GeoJSON tracks.
+ *
+ * GeoJSON has no standard place for times, so we go with the conventions the apps we've come across use:
+ *
+ *   a LineString (or MultiLineString) feature with a "coordTimes" or "times" property, an array with a time for each position
+ *   (an array of arrays for a MultiLineString), as written by togeojson and friends
+ *   a Point feature with a "time" or "timestamp" property
+ *
+ * Times can be ISO 8601 strings or numbers of seconds (or milliseconds) since 1970.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use super::{parse_time, TrackPoint};
+
+
+/** read
+  fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+    file: &Path = path to a .geojson or .json file
+
+  Reads every timestamped position out of a GeoJSON file.
+**/
+pub fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+{
+  let text = fs::read_to_string(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+  let json: Value = serde_json::from_str(&text).map_err(|e| format!("Could not parse {}: {}", file.display(), e))?;
+  let mut points = Vec::new();
+
+  let features: Vec<&Value> = match json["type"].as_str()
+    {
+      Some("FeatureCollection") => json["features"].as_array().map(|f| f.iter().collect()).unwrap_or_default(),
+      Some("Feature") => vec![&json],
+      _ => Vec::new(),
+    };
+
+  for feature in features
+    {
+      let geometry = &feature["geometry"];
+      let properties = &feature["properties"];
+      let times = if properties["coordTimes"].is_array() { &properties["coordTimes"] } else { &properties["times"] };
+
+      match geometry["type"].as_str()
+        {
+          Some("LineString") => add_line(&geometry["coordinates"], times, &mut points),
+          Some("MultiLineString") =>
+            {
+              if let Some(lines) = geometry["coordinates"].as_array()
+                {
+                  for (i, line) in lines.iter().enumerate()
+                    {
+                      add_line(line, &times[i], &mut points);
+                    }
+                }
+            },
+          Some("Point") =>
+            {
+              let time = if properties["time"].is_null() { &properties["timestamp"] } else { &properties["time"] };
+
+              if let (Some(time), Some((latitude, longitude, altitude))) = (json_time(time), position(&geometry["coordinates"]))
+                {
+                  points.push(TrackPoint { time, latitude, longitude, altitude });
+                }
+            },
+          _ => {},
+        }
+    }
+
+  if points.is_empty()
+    {
+      return Err(format!("{} has no timestamped positions", file.display()));
+    }
+
+  Ok(points)
+}
+
+
+/** add_line
+  fn add_line(coordinates: &Value, times: &Value, points: &mut Vec<TrackPoint>)
+
+  Pairs up the positions of a line with their times.
+**/
+fn add_line(coordinates: &Value, times: &Value, points: &mut Vec<TrackPoint>)
+{
+  if let (Some(coordinates), Some(times)) = (coordinates.as_array(), times.as_array())
+    {
+      for (coordinate, time) in coordinates.iter().zip(times.iter())
+        {
+          if let (Some(time), Some((latitude, longitude, altitude))) = (json_time(time), position(coordinate))
+            {
+              points.push(TrackPoint { time, latitude, longitude, altitude });
+            }
+        }
+    }
+}
+
+
+/** position
+  fn position(coordinate: &Value) -> Option<(f64, f64, Option<f64>)>
+
+  Returns the latitude, longitude and altitude of a GeoJSON position, which is written longitude first.
+**/
+fn position(coordinate: &Value) -> Option<(f64, f64, Option<f64>)>
+{
+  let longitude = coordinate.get(0)?.as_f64()?;
+  let latitude = coordinate.get(1)?.as_f64()?;
+
+  Some((latitude, longitude, coordinate.get(2).and_then(|a| a.as_f64())))
+}
+
+
+/** json_time
+  fn json_time(time: &Value) -> Option<DateTime<Utc>>
+
+  Turns a time in a GeoJSON property, either a string or a number since 1970, into a UTC time.
+**/
+fn json_time(time: &Value) -> Option<DateTime<Utc>>
+{
+  match time
+    {
+      Value::String(text) => parse_time(text),
+      Value::Number(number) =>
+        {
+          let number = number.as_f64()?;
+          let milliseconds = if number > 1e11 { number } else { number * 1000.0 };   // 1e11 seconds is thousands of years away
+          DateTime::from_timestamp_millis(milliseconds as i64)
+        },
+      _ => None,
+    }
+}
@@ -0,0 +1,54 @@
+/*
+ * gpx.rs
+ * Reader for GPX track logs.
+ *
+ * We only want the track points, <trkpt lat=".." lon=".."> with a <time> and possibly an <ele>. Route points and waypoints generally
+ * don't have times, so they are no use for matching photos and are ignored, as are track points without a time.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use roxmltree::Document;
+
+use super::{parse_time, TrackPoint};
+
+
+/** read
+  fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+    file: &Path = path to a .gpx file
+
+  Reads every timestamped track point out of a GPX file.
+**/
+pub fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+{
+  let text = fs::read_to_string(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+  let xml = Document::parse(&text).map_err(|e| format!("Could not parse {}: {}", file.display(), e))?;
+  let mut points = Vec::new();
+
+  for trkpt in xml.descendants().filter(|n| n.is_element() && n.tag_name().name() == "trkpt")
+    {
+      let latitude = trkpt.attribute("lat").and_then(|v| v.trim().parse::<f64>().ok());
+      let longitude = trkpt.attribute("lon").and_then(|v| v.trim().parse::<f64>().ok());
+      let time = trkpt.children()
+                      .find(|c| c.is_element() && c.tag_name().name() == "time")
+                      .and_then(|c| c.text())
+                      .and_then(parse_time);
+      let altitude = trkpt.children()
+                          .find(|c| c.is_element() && c.tag_name().name() == "ele")
+                          .and_then(|c| c.text())
+                          .and_then(|t| t.trim().parse::<f64>().ok());
+
+      if let (Some(latitude), Some(longitude), Some(time)) = (latitude, longitude, time)
+        {
+          points.push(TrackPoint { time, latitude, longitude, altitude });
+        }
+    }
+
+  if points.is_empty()
+    {
+      return Err(format!("{} has no timestamped track points", file.display()));
+    }
+
+  Ok(points)
+}
@@ -0,0 +1,177 @@
+/*
+ * kml.rs
+ * Reader for KML and KMZ track logs.
+ *
+ * There are a few ways a timestamped track turns up in KML, and we take all of them:
+ *
+ *   <gx:Track>, where a list of <when> times goes hand in hand with a list of <gx:coord> positions (gx:MultiTrack is just several of these)
+ *   a <Placemark> holding a <Point> and a <TimeStamp>, one per position
+ *   a <Placemark> holding a <LineString> and a <TimeSpan>. The line has no times of its own, so its points are spread evenly over the span,
+ *   which is only as good as the app that wrote it was at logging at a steady rate.
+ *
+ * A KMZ is a zip file with the KML inside it, usually as doc.kml.
+ */
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use roxmltree::{Document, Node};
+
+use super::{parse_time, TrackPoint};
+
+
+/** read
+  fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+    file: &Path = path to a .kml or .kmz file
+
+  Reads every timestamped position out of a KML file, unzipping it first if it is a KMZ.
+**/
+pub fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+{
+  let bytes = fs::read(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+
+  let text = if bytes.starts_with(b"PK")
+    {
+      unzip_kml(&bytes).map_err(|e| format!("Could not unzip {}: {}", file.display(), e))?
+    }
+  else
+    {
+      String::from_utf8_lossy(&bytes).into_owned()
+    };
+
+  let xml = Document::parse(&text).map_err(|e| format!("Could not parse {}: {}", file.display(), e))?;
+  let mut points = Vec::new();
+
+  for node in xml.descendants().filter(|n| n.is_element())
+    {
+      match node.tag_name().name()
+        {
+          "Track" => read_gx_track(&node, &mut points),
+          "Placemark" => read_placemark(&node, &mut points),
+          _ => {},
+        }
+    }
+
+  if points.is_empty()
+    {
+      return Err(format!("{} has no timestamped positions", file.display()));
+    }
+
+  Ok(points)
+}
+
+
+/** unzip_kml
+  fn unzip_kml(bytes: &[u8]) -> Result<String, String>
+
+  Pulls the KML out of a KMZ, preferring doc.kml but settling for the first .kml in the archive.
+**/
+fn unzip_kml(bytes: &[u8]) -> Result<String, String>
+{
+  let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).map_err(|e| e.to_string())?;
+  let names: Vec<String> = archive.file_names().filter_map(|n| n.ok()).map(|n| n.to_string()).collect();
+  let name = names.iter()
+                  .find(|n| n.eq_ignore_ascii_case("doc.kml"))
+                  .or_else(|| names.iter().find(|n| n.to_lowercase().ends_with(".kml")))
+                  .ok_or("there is no .kml inside it")?;
+
+  let mut text = String::new();
+  archive.by_name(name).map_err(|e| e.to_string())?.read_to_string(&mut text).map_err(|e| e.to_string())?;
+  Ok(text)
+}
+
+
+/** read_gx_track
+  fn read_gx_track(track: &Node, points: &mut Vec<TrackPoint>)
+
+  Pairs up the <when> and <gx:coord> children of a gx:Track.
+**/
+fn read_gx_track(track: &Node, points: &mut Vec<TrackPoint>)
+{
+  let whens = track.children().filter(|c| c.is_element() && c.tag_name().name() == "when");
+  let coords = track.children().filter(|c| c.is_element() && c.tag_name().name() == "coord");
+
+  for (when, coord) in whens.zip(coords)
+    {
+      let time = when.text().and_then(parse_time);
+      let position = coord.text().and_then(|t| parse_position(t, ' '));
+
+      if let (Some(time), Some((latitude, longitude, altitude))) = (time, position)
+        {
+          points.push(TrackPoint { time, latitude, longitude, altitude });
+        }
+    }
+}
+
+
+/** read_placemark
+  fn read_placemark(placemark: &Node, points: &mut Vec<TrackPoint>)
+
+  Reads a Placemark with a timestamped Point, or a LineString spread over a TimeSpan.
+**/
+fn read_placemark(placemark: &Node, points: &mut Vec<TrackPoint>)
+{
+  let find = |name: &str| placemark.descendants().find(|n| n.is_element() && n.tag_name().name() == name);
+  let coordinates = |node: Node| -> Vec<(f64, f64, Option<f64>)>
+    {
+      node.descendants()
+          .find(|n| n.is_element() && n.tag_name().name() == "coordinates")
+          .and_then(|n| n.text())
+          .map(|t| t.split_whitespace().filter_map(|c| parse_position(c, ',')).collect())
+          .unwrap_or_default()
+    };
+
+  if let (Some(point), Some(when)) = (find("Point"), find("TimeStamp").and_then(|t| child_time(&t, "when")))
+    {
+      if let Some((latitude, longitude, altitude)) = coordinates(point).first()
+        {
+          points.push(TrackPoint { time: when, latitude: *latitude, longitude: *longitude, altitude: *altitude });
+        }
+    }
+  else if let (Some(line), Some(span)) = (find("LineString"), find("TimeSpan"))
+    {
+      let positions = coordinates(line);
+
+      if let (Some(begin), Some(end)) = (child_time(&span, "begin"), child_time(&span, "end"))
+        {
+          let step = if positions.len() > 1 { (end - begin) / (positions.len() as i32 - 1) } else { end - begin };
+
+          for (i, (latitude, longitude, altitude)) in positions.into_iter().enumerate()
+            {
+              points.push(TrackPoint { time: begin + step * i as i32, latitude, longitude, altitude });
+            }
+        }
+    }
+}
+
+
+/** child_time
+  fn child_time(node: &Node, name: &str) -> Option<DateTime<Utc>>
+
+  Returns the time held in the named child element.
+**/
+fn child_time(node: &Node, name: &str) -> Option<DateTime<Utc>>
+{
+  node.children().find(|c| c.is_element() && c.tag_name().name() == name).and_then(|c| c.text()).and_then(parse_time)
+}
+
+
+/** parse_position
+  fn parse_position(text: &str, separator: char) -> Option<(f64, f64, Option<f64>)>
+
+  Parses a KML position, which is longitude first, then latitude, then an optional altitude. gx:coord separates them with spaces,
+  <coordinates> with commas.
+**/
+fn parse_position(text: &str, separator: char) -> Option<(f64, f64, Option<f64>)>
+{
+  let values: Vec<f64> = text.trim().split(separator).filter(|v| !v.is_empty()).map(|v| v.parse::<f64>()).collect::<Result<_, _>>().ok()?;
+
+  match values.len()
+    {
+      2 => Some((values[1], values[0], None)),
+      3 => Some((values[1], values[0], Some(values[2]))),
+      _ => None,
+    }
+}
@@ -0,0 +1,277 @@
+/*
+ * nmea.rs
+ * Reader for NMEA 0183 logs, as written by the Nikon GP-1/GP-1A and the loggers built into some Coolpix and Z bodies.
+ *
+ * The logs are plain text, one sentence per line, e.g.
+ *
+ *   $GPRMC,013203.000,A,3351.5900,S,15112.5000,E,0.00,0.00,030521,,,A*6B
+ *   $GPGGA,013203.000,3351.5900,S,15112.5000,E,1,08,1.0,12.5,M,22.0,M,,*4F
+ *
+ * RMC gives us the date, time and position, GGA the time, position and altitude but no date. Anything else (and anything that fails its
+ * checksum) is skipped. Since GGA has no date of its own, it takes the date of the last RMC, and we roll the date over ourselves if the time
+ * goes backwards past midnight before the next RMC turns up.
+ */
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+
+use super::TrackPoint;
+
+// Define Structures
+
+struct Fix
+  {
+    time: NaiveTime,
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<f64>,
+  }
+
+
+/** read
+  fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+    file: &Path = path to an NMEA log
+
+  Reads every valid fix out of an NMEA log.
+**/
+pub fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+{
+  let bytes = fs::read(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+  let (points, bad_checksums) = parse(&String::from_utf8_lossy(&bytes));
+
+  if points.is_empty()
+    {
+      return Err(format!("{} has no dated GGA or RMC fixes ({} sentences failed their checksum)", file.display(), bad_checksums));
+    }
+
+  Ok(points)
+}
+
+
+/** parse
+  fn parse(text: &str) -> (Vec<TrackPoint>, usize)
+    text: &str = the contents of an NMEA log
+
+  Returns every valid fix in the log, and how many sentences failed their checksum.
+**/
+fn parse(text: &str) -> (Vec<TrackPoint>, usize)
+{
+  let mut points: BTreeMap<DateTime<Utc>, TrackPoint> = BTreeMap::new();
+  let mut pending: Vec<Fix> = Vec::new();                                      // GGA fixes seen before we know the date
+  let mut date: Option<NaiveDate> = None;
+  let mut last_time: Option<NaiveTime> = None;
+  let mut bad_checksums = 0;
+
+  for line in text.lines()
+    {
+      let line = line.trim();
+
+      if !line.starts_with('$')
+        {
+          continue;                                                            // e.g. the @Nikon header of a GP-1 log
+        }
+
+      let sentence = match checked(line)
+        {
+          Some(sentence) => sentence,
+          None => { bad_checksums += 1; continue; }
+        };
+      let fields: Vec<&str> = sentence.split(',').collect();
+
+      if fields[0].ends_with("RMC") && fields.len() >= 10
+        {
+          let time = match parse_time(fields[1]) { Some(time) => time, None => continue };
+          let rmc_date = match NaiveDate::parse_from_str(fields[9], "%d%m%y") { Ok(d) => d, Err(_) => continue };
+
+          /*
+           * Now we know the date, place any GGA fixes we've been holding on to. Any with a later time than this RMC must be from the day before.
+           */
+          for fix in pending.drain(..)
+            {
+              let fix_date = if fix.time > time { rmc_date - Duration::days(1) } else { rmc_date };
+              add(&mut points, fix_date, fix);
+            }
+
+          date = Some(rmc_date);
+          last_time = Some(time);
+
+          if fields[2] == "A"
+            {
+              if let (Some(latitude), Some(longitude)) = (parse_coordinate(fields[3], fields[4]), parse_coordinate(fields[5], fields[6]))
+                {
+                  add(&mut points, rmc_date, Fix { time, latitude, longitude, altitude: None });
+                }
+            }
+        }
+      else if fields[0].ends_with("GGA") && fields.len() >= 10
+        {
+          let time = match parse_time(fields[1]) { Some(time) => time, None => continue };
+
+          if fields[6].is_empty() || fields[6] == "0"                          // fix quality 0 means no fix
+            {
+              continue;
+            }
+
+          let fix = match (parse_coordinate(fields[2], fields[3]), parse_coordinate(fields[4], fields[5]))
+            {
+              (Some(latitude), Some(longitude)) => Fix { time, latitude, longitude, altitude: fields[9].parse::<f64>().ok() },
+              _ => continue,
+            };
+
+          match date
+            {
+              Some(mut current) =>
+                {
+                  if let Some(last) = last_time
+                    {
+                      if time < last && (last - time) > Duration::hours(12)   // gone past midnight
+                        {
+                          current += Duration::days(1);
+                          date = Some(current);
+                        }
+                    }
+                  last_time = Some(time);
+                  add(&mut points, current, fix);
+                },
+              None => pending.push(fix),
+            }
+        }
+    }
+
+  (points.into_values().collect(), bad_checksums)
+}
+
+
+/** add
+  fn add(points: &mut BTreeMap<DateTime<Utc>, TrackPoint>, date: NaiveDate, fix: Fix)
+
+  Adds a fix to the track. RMC and GGA sentences for the same second describe the same fix, so they are merged rather than added twice,
+  with the GGA providing the altitude.
+**/
+fn add(points: &mut BTreeMap<DateTime<Utc>, TrackPoint>, date: NaiveDate, fix: Fix)
+{
+  let time = DateTime::from_naive_utc_and_offset(date.and_time(fix.time), Utc);
+  let point = points.entry(time).or_insert(TrackPoint { time, latitude: fix.latitude, longitude: fix.longitude, altitude: None });
+
+  if fix.altitude.is_some()
+    {
+      point.altitude = fix.altitude;
+    }
+}
+
+
+/** checked
+  fn checked(line: &str) -> Option<&str>
+
+  Validates the checksum of a sentence, the XOR of everything between the $ and the *, and returns the sentence without the $ and checksum.
+**/
+fn checked(line: &str) -> Option<&str>
+{
+  let (sentence, checksum) = line[1..].split_once('*')?;
+  let expected = u8::from_str_radix(checksum.get(0..2)?, 16).ok()?;
+
+  if sentence.bytes().fold(0u8, |sum, b| sum ^ b) == expected { Some(sentence) } else { None }
+}
+
+
+/** parse_time
+  fn parse_time(field: &str) -> Option<NaiveTime>
+
+  Parses an NMEA time, hhmmss with optional fractions of a second.
+**/
+fn parse_time(field: &str) -> Option<NaiveTime>
+{
+  NaiveTime::parse_from_str(field, "%H%M%S%.f").ok().or_else(|| NaiveTime::parse_from_str(field, "%H%M%S").ok())
+}
+
+
+/** parse_coordinate
+  fn parse_coordinate(value: &str, hemisphere: &str) -> Option<f64>
+
+  Parses an NMEA latitude (ddmm.mmmm) or longitude (dddmm.mmmm) and its hemisphere into signed decimal degrees.
+**/
+fn parse_coordinate(value: &str, hemisphere: &str) -> Option<f64>
+{
+  let raw = value.parse::<f64>().ok()?;
+  let degrees = (raw / 100.0).trunc();
+  let decimal = degrees + (raw - degrees * 100.0) / 60.0;
+
+  match hemisphere
+    {
+      "N" | "E" => Some(decimal),
+      "S" | "W" => Some(-decimal),
+      _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+  use chrono::TimeZone;
+
+  fn log(bodies: &[&str]) -> String
+  {
+    bodies.iter().map(|body| format!("${}*{:02X}\n", body, body.bytes().fold(0u8, |sum, b| sum ^ b))).collect()
+  }
+
+  fn at(day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Utc>
+  {
+    Utc.with_ymd_and_hms(2021, 5, day, hour, minute, second).unwrap()
+  }
+
+  #[test]
+  fn sentences_that_fail_their_checksum_are_skipped()
+  {
+    let mut text = log(&["GPRMC,013203.000,A,3351.5900,S,15112.5000,E,0.00,0.00,030521,,,A",
+                         "GPRMC,013204.000,A,3351.5900,S,15112.5000,E,0.00,0.00,030521,,,A"]);
+    text = text.replacen("15112.5000,E,0.00,0.00,030521,,,A*", "15112.5001,E,0.00,0.00,030521,,,A*", 1);
+
+    let (points, bad_checksums) = parse(&format!("@Nikon GP-1\n{}", text));
+
+    assert_eq!(bad_checksums, 1);
+    assert_eq!(points.len(), 1);
+    assert_eq!(points[0].time, at(3, 1, 32, 4));
+    assert!((points[0].latitude - -33.859833).abs() < 1e-6);
+    assert!((points[0].longitude - 151.208333).abs() < 1e-6);
+  }
+
+  #[test]
+  fn gga_and_rmc_for_the_same_second_are_one_point()
+  {
+    let (points, _) = parse(&log(&["GPRMC,013203.000,A,3351.5900,S,15112.5000,E,0.00,0.00,030521,,,A",
+                                    "GPGGA,013203.000,3351.5900,S,15112.5000,E,1,08,1.0,12.5,M,22.0,M,,"]));
+
+    assert_eq!(points.len(), 1);
+    assert_eq!(points[0].altitude, Some(12.5));
+  }
+
+  #[test]
+  fn gga_before_the_first_rmc_takes_its_date()
+  {
+    let (points, _) = parse(&log(&["GPGGA,235958.000,3351.5900,S,15112.5000,E,1,08,1.0,12.5,M,22.0,M,,",
+                                    "GPGGA,000001.000,3351.5900,S,15112.5000,E,1,08,1.0,13.5,M,22.0,M,,",
+                                    "GPGGA,000002.000,3351.5900,S,15112.5000,E,0,00,,,M,,M,,",
+                                    "GPRMC,000003.000,A,3351.5900,S,15112.5000,E,0.00,0.00,040521,,,A"]));
+    let times: Vec<DateTime<Utc>> = points.iter().map(|p| p.time).collect();
+
+    assert_eq!(times, vec![at(3, 23, 59, 58), at(4, 0, 0, 1), at(4, 0, 0, 3)]);
+    assert_eq!(points[1].altitude, Some(13.5));
+  }
+
+  #[test]
+  fn the_date_rolls_over_at_midnight()
+  {
+    let (points, _) = parse(&log(&["GPRMC,235958.000,A,3351.5900,S,15112.5000,E,0.00,0.00,030521,,,A",
+                                    "GPGGA,235959.000,3351.5900,S,15112.5000,E,1,08,1.0,12.5,M,22.0,M,,",
+                                    "GPGGA,000000.000,3351.5900,S,15112.5000,E,1,08,1.0,12.5,M,22.0,M,,",
+                                    "GPGGA,000001.000,3351.5900,S,15112.5000,E,1,08,1.0,12.5,M,22.0,M,,"]));
+    let times: Vec<DateTime<Utc>> = points.iter().map(|p| p.time).collect();
+
+    assert_eq!(times, vec![at(3, 23, 59, 58), at(3, 23, 59, 59), at(4, 0, 0, 0), at(4, 0, 0, 1)]);
+  }
+}
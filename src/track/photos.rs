@@ -0,0 +1,112 @@
+/*
+ * photos.rs
+ * Phone photos as a track.
+ *
+ * If a phone was taking photos alongside the Nikon, every one of them with a location is a point on where we went. They are a much
+ * sparser track than a logger's, but over a day's walking around a town it is often good enough. The EXIF is read by the same code
+ * we read the NEFs with, kamadak-exif underneath, which handles JPEG and HEIC alike.
+ *
+ * Phones record the GPS date and time (UTC) along with the position, which is what we use. Failing that DateTimeOriginal is only any
+ * use if the phone also recorded its OffsetTimeOriginal; anything else is skipped, as we'd only be guessing when it was taken.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+use crate::LocationData;
+use super::TrackPoint;
+
+const EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "heic", "heif"];
+
+
+/** read
+  fn read(folder: &Path) -> Result<Vec<TrackPoint>, String>
+    folder: &Path = path to a folder of phone photos (sub folders are read too), or a single photo
+
+  Reads the location and time of every photo with a location.
+**/
+pub fn read(folder: &Path) -> Result<Vec<TrackPoint>, String>
+{
+  let mut points = Vec::new();
+  let mut without = 0;
+
+  read_path(folder, &mut points, &mut without)?;
+
+  if points.is_empty()
+    {
+      return Err(format!("{} has no photos with a location and time ({} without)", folder.display(), without));
+    }
+
+  Ok(points)
+}
+
+
+/** read_path
+  fn read_path(path: &Path, points: &mut Vec<TrackPoint>, without: &mut usize) -> Result<(), String>
+
+  Reads a photo, or every photo under a folder, counting the ones we can't use.
+**/
+fn read_path(path: &Path, points: &mut Vec<TrackPoint>, without: &mut usize) -> Result<(), String>
+{
+  if path.is_dir()
+    {
+      let entries = fs::read_dir(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+
+      for entry in entries.flatten()
+        {
+          let path = entry.path();
+          let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+          if path.is_dir() || EXTENSIONS.contains(&extension.as_str())
+            {
+              read_path(&path, points, without)?;
+            }
+        }
+      return Ok(());
+    }
+
+  match point(path)
+    {
+      Some(point) => points.push(point),
+      None => *without += 1,
+    }
+
+  Ok(())
+}
+
+
+/** point
+  fn point(path: &Path) -> Option<TrackPoint>
+
+  Turns the GPS tags of a photo into a track point, reading them the same way as a NEF's.
+**/
+fn point(path: &Path) -> Option<TrackPoint>
+{
+  let mut Location = LocationData::default();
+
+  crate::try_get_location_data_from_exif(path, &mut Location).ok()?;
+  Location.move_to_wgs84().ok()?;
+
+  Some(TrackPoint { time: time(path, &Location)?, latitude: Location.latitude()?, longitude: Location.longitude()?, altitude: Location.altitude() })
+}
+
+
+/** time
+  fn time(path: &Path, Location: &LocationData) -> Option<DateTime<Utc>>
+
+  Returns when a photo was taken in UTC, from its GPS date and time, or failing that its DateTimeOriginal and OffsetTimeOriginal.
+**/
+fn time(path: &Path, Location: &LocationData) -> Option<DateTime<Utc>>
+{
+  if let Some(time) = Location.gps_time()
+    {
+      return Some(time);
+    }
+
+  let taken = crate::get_date_time_original(path).ok()?;
+  let zone = crate::get_offset_time_original(path)?;
+
+  Some(zone.to_utc(taken).0)
+}
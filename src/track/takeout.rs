@@ -0,0 +1,301 @@
+/*
+ * takeout.rs
+ * Reader for Google location history, as exported by Google Takeout.
+ *
+ * Takeout gives us the history two ways, and we take either:
+ *
+ *   Records.json, every raw fix the phone reported, {"locations": [{"latitudeE7": .., "longitudeE7": .., "accuracy": .., "timestamp": ..}, ..]}
+ *   Semantic Location History, one file a month of "timelineObjects", each a placeVisit (where we stayed, and from when to when) or an
+ *   activitySegment (how we got from one place to the next, with a start, an end and sometimes a simplifiedRawPath of fixes in between)
+ *
+ * Coordinates are integers in units of 1e-7 degrees. Some older exports wrote southern and western ones as unsigned 32 bit numbers, so
+ * anything out of range is wrapped back round. Times are ISO 8601 ("timestamp") or milliseconds since 1970 in a string ("timestampMs").
+ *
+ * Fixes come with an accuracy radius in metres, and those from wifi or cell towers can be kilometres out, so fixes worse than a given
+ * accuracy can be skipped. Place visits have no radius, they are snapped to the place, and are always kept. A visit can last hours with no
+ * fixes in it at all, so it comes back as a stay rather than two points the track would refuse to bridge.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use super::{parse_time, Stay, TrackPoint};
+
+
+/** read
+  fn read(file: &Path, max_accuracy: f64) -> Result<(Vec<TrackPoint>, Vec<Stay>, usize), String>
+    file: &Path = path to Records.json, a Semantic Location History month, or a folder of them
+    max_accuracy: f64 = skip fixes with an accuracy radius (metres) bigger than this
+
+  Reads every timestamped position out of a Takeout location history. Given a folder, every .json file in it (and its sub folders, as
+  Semantic Location History is filed by year) is read. Returns the points, the place visits and how many fixes were skipped for being
+  too inaccurate.
+**/
+pub fn read(file: &Path, max_accuracy: f64) -> Result<(Vec<TrackPoint>, Vec<Stay>, usize), String>
+{
+  let mut points = Vec::new();
+  let mut stays = Vec::new();
+  let mut skipped = 0;
+
+  read_path(file, max_accuracy, &mut points, &mut stays, &mut skipped)?;
+
+  if points.is_empty()
+    {
+      return Err(format!("{} has no usable location history ({} fixes were less accurate than {} m)", file.display(), skipped, max_accuracy));
+    }
+
+  Ok((points, stays, skipped))
+}
+
+
+/** read_path
+  fn read_path(file: &Path, max_accuracy: f64, points: &mut Vec<TrackPoint>, stays: &mut Vec<Stay>, skipped: &mut usize) -> Result<(), String>
+
+  Reads a single history file, or every .json file under a folder.
+**/
+fn read_path(file: &Path, max_accuracy: f64, points: &mut Vec<TrackPoint>, stays: &mut Vec<Stay>, skipped: &mut usize) -> Result<(), String>
+{
+  if file.is_dir()
+    {
+      let entries = fs::read_dir(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+
+      for entry in entries.flatten()
+        {
+          let path = entry.path();
+
+          if path.is_dir() || path.extension().map(|e| e.eq_ignore_ascii_case("json")).unwrap_or(false)
+            {
+              read_path(&path, max_accuracy, points, stays, skipped)?;
+            }
+        }
+      return Ok(());
+    }
+
+  let text = fs::read_to_string(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+  let json: Value = serde_json::from_str(&text).map_err(|e| format!("Could not parse {}: {}", file.display(), e))?;
+
+  add_history(&json, max_accuracy, points, stays, skipped);
+  Ok(())
+}
+
+
+/** add_history
+  fn add_history(json: &Value, max_accuracy: f64, points: &mut Vec<TrackPoint>, stays: &mut Vec<Stay>, skipped: &mut usize)
+
+  Adds everything in one parsed history file, the locations of a Records.json or the timelineObjects of a Semantic Location History.
+**/
+fn add_history(json: &Value, max_accuracy: f64, points: &mut Vec<TrackPoint>, stays: &mut Vec<Stay>, skipped: &mut usize)
+{
+  if let Some(locations) = json["locations"].as_array()
+    {
+      for location in locations
+        {
+          add_fix(location, max_accuracy, points, skipped);
+        }
+    }
+
+  if let Some(objects) = json["timelineObjects"].as_array()
+    {
+      for object in objects
+        {
+          let visit = &object["placeVisit"];
+          let segment = &object["activitySegment"];
+
+          if visit.is_object()
+            {
+              /*
+               * We were at the place for the whole visit. Its ends also go in the track, so the times either side of it can be bridged
+               */
+              if let Some((latitude, longitude)) = position(&visit["location"])
+                {
+                  let (from, to) = (start_time(&visit["duration"]), end_time(&visit["duration"]));
+
+                  for time in [from, to].iter().flatten()
+                    {
+                      points.push(TrackPoint { time: *time, latitude, longitude, altitude: None });
+                    }
+                  if let (Some(from), Some(to)) = (from, to)
+                    {
+                      stays.push(Stay { from, to, latitude, longitude });
+                    }
+                }
+            }
+          else if segment.is_object()
+            {
+              if let (Some(time), Some((latitude, longitude))) = (start_time(&segment["duration"]), position(&segment["startLocation"]))
+                {
+                  points.push(TrackPoint { time, latitude, longitude, altitude: None });
+                }
+              if let (Some(time), Some((latitude, longitude))) = (end_time(&segment["duration"]), position(&segment["endLocation"]))
+                {
+                  points.push(TrackPoint { time, latitude, longitude, altitude: None });
+                }
+              if let Some(path) = segment["simplifiedRawPath"]["points"].as_array()
+                {
+                  for fix in path
+                    {
+                      add_fix(fix, max_accuracy, points, skipped);
+                    }
+                }
+            }
+        }
+    }
+}
+
+
+/** add_fix
+  fn add_fix(fix: &Value, max_accuracy: f64, points: &mut Vec<TrackPoint>, skipped: &mut usize)
+
+  Adds a single raw fix, from Records.json or a simplifiedRawPath, unless it is less accurate than we'll accept.
+**/
+fn add_fix(fix: &Value, max_accuracy: f64, points: &mut Vec<TrackPoint>, skipped: &mut usize)
+{
+  let (time, position) = match (timestamp(fix, "timestamp"), position(fix))
+    {
+      (Some(time), Some(position)) => (time, position),
+      _ => return,
+    };
+
+  let accuracy = ["accuracy", "accuracyMeters"].iter().find_map(|k| fix[*k].as_f64());
+  if accuracy.map(|a| a > max_accuracy).unwrap_or(false)
+    {
+      *skipped += 1;
+      return;
+    }
+
+  points.push(TrackPoint { time, latitude: position.0, longitude: position.1, altitude: fix["altitude"].as_f64() });
+}
+
+
+/** position
+  fn position(location: &Value) -> Option<(f64, f64)>
+
+  Returns the latitude and longitude of a location in decimal degrees. Records.json and the timeline use latitudeE7/longitudeE7,
+  raw paths latE7/lngE7.
+**/
+fn position(location: &Value) -> Option<(f64, f64)>
+{
+  let latitude = ["latitudeE7", "latE7"].iter().find_map(|k| location[*k].as_i64())?;
+  let longitude = ["longitudeE7", "lngE7"].iter().find_map(|k| location[*k].as_i64())?;
+
+  Some((from_e7(latitude, 90.0), from_e7(longitude, 180.0)))
+}
+
+
+/** from_e7
+  fn from_e7(value: i64, limit: f64) -> f64
+
+  Turns an E7 integer into degrees, undoing the unsigned wrap round of older exports.
+**/
+fn from_e7(value: i64, limit: f64) -> f64
+{
+  let degrees = value as f64 / 1e7;
+
+  if degrees > limit { (value - (1_i64 << 32)) as f64 / 1e7 } else { degrees }
+}
+
+
+fn start_time(duration: &Value) -> Option<DateTime<Utc>>
+{
+  timestamp(duration, "startTimestamp")
+}
+
+
+fn end_time(duration: &Value) -> Option<DateTime<Utc>>
+{
+  timestamp(duration, "endTimestamp")
+}
+
+
+/** timestamp
+  fn timestamp(object: &Value, key: &str) -> Option<DateTime<Utc>>
+
+  Returns the time held under key (ISO 8601), or under key + "Ms" (milliseconds since 1970, as a string or a number).
+**/
+fn timestamp(object: &Value, key: &str) -> Option<DateTime<Utc>>
+{
+  if let Some(time) = object[key].as_str().and_then(parse_time)
+    {
+      return Some(time);
+    }
+
+  let milliseconds = match &object[format!("{}Ms", key)]
+    {
+      Value::String(text) => text.trim().parse::<i64>().ok()?,
+      Value::Number(number) => number.as_i64()?,
+      _ => return None,
+    };
+
+  DateTime::from_timestamp_millis(milliseconds)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+  use chrono::TimeZone;
+
+  fn history(json: &str, max_accuracy: f64) -> (Vec<TrackPoint>, Vec<Stay>, usize)
+  {
+    let (mut points, mut stays, mut skipped) = (Vec::new(), Vec::new(), 0);
+
+    add_history(&serde_json::from_str(json).unwrap(), max_accuracy, &mut points, &mut stays, &mut skipped);
+    (points, stays, skipped)
+  }
+
+  /*
+   * -33.859833, 151.208333 with the latitude written as an unsigned 32 bit number, as older exports did
+   */
+  #[test]
+  fn unsigned_e7_coordinates_are_wrapped_back_round()
+  {
+    let (points, _, _) = history(r#"{"locations": [{"latitudeE7": 3956368966, "longitudeE7": 1512083330, "timestampMs": "1620003723000"}]}"#, 100.0);
+
+    assert_eq!(points.len(), 1);
+    assert!((points[0].latitude - -33.859833).abs() < 1e-6);
+    assert!((points[0].longitude - 151.208333).abs() < 1e-6);
+    assert_eq!(points[0].time, Utc.with_ymd_and_hms(2021, 5, 3, 1, 2, 3).unwrap());
+  }
+
+  #[test]
+  fn inaccurate_fixes_are_skipped()
+  {
+    let (points, _, skipped) = history(r#"{"locations": [
+        {"latitudeE7": -338598330, "longitudeE7": 1512083330, "accuracy": 20, "timestamp": "2021-05-03T01:02:03Z"},
+        {"latitudeE7": -338598330, "longitudeE7": 1512083330, "accuracy": 2500, "timestamp": "2021-05-03T01:03:03Z"},
+        {"latitudeE7": -338598330, "longitudeE7": 1512083330, "timestamp": "2021-05-03T01:04:03Z"}]}"#, 100.0);
+    let times: Vec<DateTime<Utc>> = points.iter().map(|p| p.time).collect();
+
+    assert_eq!(skipped, 1);
+    assert_eq!(times, vec![Utc.with_ymd_and_hms(2021, 5, 3, 1, 2, 3).unwrap(), Utc.with_ymd_and_hms(2021, 5, 3, 1, 4, 3).unwrap()]);
+  }
+
+  /*
+   * The visit is kept as a stay and its two ends, the segment as its ends and the one raw fix accurate enough to keep
+   */
+  #[test]
+  fn place_visits_and_activity_segments_are_read()
+  {
+    let (points, stays, skipped) = history(r#"{"timelineObjects": [
+        {"placeVisit": {"location": {"latitudeE7": -338598330, "longitudeE7": 1512083330},
+                        "duration": {"startTimestamp": "2021-05-03T01:00:00Z", "endTimestamp": "2021-05-03T03:00:00Z"}}},
+        {"activitySegment": {"startLocation": {"latitudeE7": -338598330, "longitudeE7": 1512083330},
+                             "endLocation": {"latitudeE7": -338688000, "longitudeE7": 1512093000},
+                             "duration": {"startTimestampMs": "1620010800000", "endTimestampMs": "1620012600000"},
+                             "simplifiedRawPath": {"points": [
+                               {"latE7": -338640000, "lngE7": 1512090000, "accuracyMeters": 10, "timestamp": "2021-05-03T03:15:00Z"},
+                               {"latE7": -338650000, "lngE7": 1512091000, "accuracyMeters": 900, "timestamp": "2021-05-03T03:20:00Z"}]}}}]}"#, 100.0);
+    let times: Vec<u32> = points.iter().map(|p| p.time.timestamp() as u32 - 1620003600).collect();
+
+    assert_eq!(stays.len(), 1);
+    assert_eq!((stays[0].from.timestamp(), stays[0].to.timestamp()), (1620003600, 1620010800));
+    assert!((stays[0].latitude - -33.859833).abs() < 1e-6);
+    assert_eq!(times, vec![0, 7200, 7200, 9000, 8100]);
+    assert!((points[3].latitude - -33.8688).abs() < 1e-6);
+    assert_eq!(skipped, 1);
+  }
+}
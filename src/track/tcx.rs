@@ -0,0 +1,60 @@
+/*
+ * tcx.rs
+ * Reader for Garmin Training Center (TCX) activity files.
+ *
+ * An activity is made of laps, each with a track of <Trackpoint>s, e.g.
+ *
+ *   <Trackpoint><Time>..</Time><Position><LatitudeDegrees>..</LatitudeDegrees><LongitudeDegrees>..</LongitudeDegrees></Position>
+ *   <AltitudeMeters>..</AltitudeMeters></Trackpoint>
+ *
+ * Track points without a <Position> (indoor activities, or before the watch got a fix) are no use to us and are skipped. On devices with
+ * a barometric altimeter <AltitudeMeters> comes from that rather than the GPS, which is all the better for us.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+
+use super::{parse_time, TrackPoint};
+
+
+/** read
+  fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+    file: &Path = path to a .tcx file
+
+  Reads every timestamped track point with a position out of a TCX file.
+**/
+pub fn read(file: &Path) -> Result<Vec<TrackPoint>, String>
+{
+  let text = fs::read_to_string(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+  let xml = Document::parse(text.trim_start()).map_err(|e| format!("Could not parse {}: {}", file.display(), e))?;
+  let mut points = Vec::new();
+
+  for trackpoint in xml.descendants().filter(|n| n.is_element() && n.tag_name().name() == "Trackpoint")
+    {
+      let time = child_text(&trackpoint, "Time").and_then(parse_time);
+      let position = trackpoint.children().find(|c| c.is_element() && c.tag_name().name() == "Position");
+      let latitude = position.and_then(|p| child_text(&p, "LatitudeDegrees")).and_then(|t| t.trim().parse::<f64>().ok());
+      let longitude = position.and_then(|p| child_text(&p, "LongitudeDegrees")).and_then(|t| t.trim().parse::<f64>().ok());
+      let altitude = child_text(&trackpoint, "AltitudeMeters").and_then(|t| t.trim().parse::<f64>().ok());
+
+      if let (Some(latitude), Some(longitude), Some(time)) = (latitude, longitude, time)
+        {
+          points.push(TrackPoint { time, latitude, longitude, altitude });
+        }
+    }
+
+  if points.is_empty()
+    {
+      return Err(format!("{} has no track points with a position", file.display()));
+    }
+
+  Ok(points)
+}
+
+
+fn child_text<'a>(node: &Node<'a, '_>, name: &str) -> Option<&'a str>
+{
+  node.children().find(|c| c.is_element() && c.tag_name().name() == name).and_then(|c| c.text())
+}
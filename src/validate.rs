@@ -0,0 +1,167 @@
+/*
+ * validate.rs
+ * Checking a location is plausible before it goes into a sidecar.
+ *
+ * A receiver without a fix, a firmware bug or a damaged file can leave a location which decodes without complaint but is nonsense: 0° 0'
+ * in the Gulf of Guinea, a rational with a zero denominator that comes out as infinity or NaN, a latitude of 95°, or no N/S or E/W at
+ * all, or an altitude of NaN or of thousands of kilometres. Once one of those is in the sidecar NX Studio puts it on the map as though it
+ * were real, so they are stopped here. So is a fix hours older than the photo, which units that hang on to their last position when they
+ * lose the signal are prone to.
+ */
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::LocationData;
+use crate::timezone::Zone;
+
+const NULL_ISLAND: f64 = 0.0001;                                               // Degrees either side of 0° 0' we take as no fix at all, about 10 m
+const STALE_AFTER: i64 = 3600;                                                 // Seconds a fix can be older than the photo before it is a stale one
+const LARGEST_UTC_OFFSET: i64 = 14 * 3600;                                     // The furthest ahead of UTC a camera's clock can be, Kiribati time
+const LOWEST_ALTITUDE: f64 = -1000.0;                                          // Metres, well below the shore of the Dead Sea
+const HIGHEST_ALTITUDE: f64 = 50_000.0;                                        // Metres, above any aircraft or weather balloon
+
+
+/** problems
+  fn problems(Location: &LocationData, taken: Option<NaiveDateTime>, zone: Option<&Zone>) -> Vec<String>
+    Location: &LocationData = the location we are about to write
+    taken: Option<NaiveDateTime> = the DateTimeOriginal of the NEF it came from, to check the fix against, None not to
+    zone: Option<&Zone> = the zone the camera's clock was set to, if we know it
+
+  Returns what is wrong with a location, one line each, or nothing if it looks like a real place. Without the camera's zone the photo
+  could have been taken anywhere up to fourteen hours either side of its clock, so the fix is only called stale if it is that much older.
+**/
+pub fn problems(Location: &LocationData, taken: Option<NaiveDateTime>, zone: Option<&Zone>) -> Vec<String>
+{
+  let mut problems = Vec::new();
+
+  if !matches!(Location.GPSLatitudeRef.as_str(), "N" | "S")
+    {
+      problems.push(reference_problem("GPSLatitudeRef", &Location.GPSLatitudeRef, "N or S"));
+    }
+
+  if !matches!(Location.GPSLongitudeRef.as_str(), "E" | "W")
+    {
+      problems.push(reference_problem("GPSLongitudeRef", &Location.GPSLongitudeRef, "E or W"));
+    }
+
+  let mut in_range = true;
+
+  for (name, dms, limit) in [("latitude", &Location.GPSLatitude, 90.0), ("longitude", &Location.GPSLongitude, 180.0)]
+    {
+      if let Some(problem) = coordinate_problem(name, dms, limit)
+        {
+          problems.push(problem);
+          in_range = false;
+        }
+    }
+
+  if let (true, Some(latitude), Some(longitude)) = (in_range, Location.latitude(), Location.longitude())
+    {
+      if (latitude.abs() < NULL_ISLAND) && (longitude.abs() < NULL_ISLAND)
+        {
+          problems.push("the location is 0° 0', where receivers without a fix put it".to_string());
+        }
+    }
+
+  if let Some(altitude) = Location.altitude()
+    {
+      if !altitude.is_finite()
+        {
+          problems.push(format!("the altitude is {}, which has a rational divided by zero in it", altitude));
+        }
+      else if !(LOWEST_ALTITUDE..=HIGHEST_ALTITUDE).contains(&altitude)
+        {
+          problems.push(format!("the altitude is {:.0} m, which is nowhere a camera has been", altitude));
+        }
+    }
+
+  if let (Some(taken), Some(fix)) = (taken, Location.gps_time())
+    {
+      let (taken_utc, slack) = match zone
+        {
+          Some(zone) => (zone.to_utc(taken).0, 0),
+          None => (DateTime::<Utc>::from_naive_utc_and_offset(taken, Utc), LARGEST_UTC_OFFSET),
+        };
+      let age = (taken_utc - fix).num_seconds();
+
+      if age - slack > STALE_AFTER
+        {
+          problems.push(format!("the GPS fix is from {}, {} before the photo was taken, so is most likely a stale one",
+                                fix.format("%Y-%m-%d %H:%M:%S UTC"), describe_age(age)));
+        }
+    }
+
+  problems
+}
+
+
+/** reference_problem
+  fn reference_problem(tag: &str, value: &str, expected: &str) -> String
+
+  Describes a missing or unreadable GPSLatitudeRef or GPSLongitudeRef.
+**/
+fn reference_problem(tag: &str, value: &str, expected: &str) -> String
+{
+  if value.is_empty()
+    {
+      format!("there is no {}, so no telling which hemisphere it is in", tag)
+    }
+  else
+    {
+      format!("the {} is \"{}\" rather than {}", tag, value, expected)
+    }
+}
+
+
+/** coordinate_problem
+  fn coordinate_problem(name: &str, dms: &[f64], limit: f64) -> Option<String>
+    name: &str = "latitude" or "longitude"
+    dms: &[f64] = its degrees, minutes and seconds
+    limit: f64 = the most degrees it can be, 90 or 180
+
+  Checks one coordinate is three finite numbers that make a real angle.
+**/
+fn coordinate_problem(name: &str, dms: &[f64], limit: f64) -> Option<String>
+{
+  if dms.len() != 3
+    {
+      return Some(format!("the {} has {} values rather than degrees, minutes and seconds", name, dms.len()));
+    }
+
+  if dms.iter().any(|v| !v.is_finite())
+    {
+      return Some(format!("the {} is {:?}, which has a rational divided by zero in it", name, dms));
+    }
+
+  if dms.iter().any(|v| *v < 0.0) || (dms[1] >= 60.0) || (dms[2] >= 60.0)
+    {
+      return Some(format!("the {} is {}° {}' {}\", which is not an angle", name, dms[0], dms[1], dms[2]));
+    }
+
+  let degrees = dms[0] + dms[1] / 60.0 + dms[2] / 3600.0;
+
+  if degrees > limit
+    {
+      return Some(format!("the {} is {:.6}°, beyond {}°", name, degrees, limit));
+    }
+
+  None
+}
+
+
+/** describe_age
+  fn describe_age(seconds: i64) -> String
+
+  Turns the age of a fix into something readable, e.g. "3.5 hours" or "12 days".
+**/
+fn describe_age(seconds: i64) -> String
+{
+  if seconds < 2 * 86400
+    {
+      format!("{:.1} hours", seconds as f64 / 3600.0)
+    }
+  else
+    {
+      format!("{} days", seconds / 86400)
+    }
+}
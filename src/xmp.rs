@@ -0,0 +1,289 @@
+/*
+ * xmp.rs
+ * The location in an ordinary .xmp sidecar.
+ *
+ * Nothing but NX Studio reads the NKSC. darktable, Lightroom, digiKam and the rest read the standard exif: GPS properties from an .xmp
+ * sidecar next to the raw file instead, where a position is written as degrees and decimal minutes with the hemisphere on the end, e.g.
+ * 33,51.590000S, rationals as "12500/1000", and the GPS date and time as one ISO 8601 timestamp. Lightroom and ExifTool call the sidecar
+ * DSC_0001.xmp, darktable and digiKam DSC_0001.NEF.xmp, so whichever of those are already there are updated, and if neither is we make
+ * a DSC_0001.xmp. The rest of an existing sidecar, ratings, keywords, edit histories, is left exactly as it was.
+ */
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Timelike};
+
+use crate::nksc::NkscDocument;
+use crate::{LocationData, ABOVE_SEA_LEVEL, BELOW_SEA_LEVEL};
+
+const EXIF_NAMESPACE: &str = "http://ns.adobe.com/exif/1.0/";
+
+const EMPTY_SIDECAR: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+                             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+                             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n  \
+                             <rdf:Description rdf:about=\"\"\n    \
+                             xmlns:exif=\"http://ns.adobe.com/exif/1.0/\">\n  \
+                             </rdf:Description>\n \
+                             </rdf:RDF>\n\
+                             </x:xmpmeta>\n\
+                             <?xpacket end=\"w\"?>\n";
+
+
+/** sidecars_for
+  fn sidecars_for(nef: &Path) -> Vec<PathBuf>
+    nef: &Path = path to the NEF
+
+  Returns the .xmp sidecars of a NEF which are already there, in either naming, or if there are none the one we would make.
+**/
+pub fn sidecars_for(nef: &Path) -> Vec<PathBuf>
+{
+  let stem = nef.with_extension("");
+  let name = nef.as_os_str().to_string_lossy().to_string();
+  let mut sidecars: Vec<PathBuf> = vec![format!("{}.xmp", stem.display()), format!("{}.XMP", stem.display()), format!("{}.xmp", name), format!("{}.XMP", name)]
+                                         .into_iter()
+                                         .map(PathBuf::from)
+                                         .filter(|p| p.is_file())
+                                         .collect();
+
+  sidecars.dedup_by(|a, b| a.to_string_lossy().eq_ignore_ascii_case(&b.to_string_lossy()));    // the same file, where case doesn't matter
+
+  if sidecars.is_empty()
+    {
+      return vec![nef.with_extension("xmp")];
+    }
+  sidecars
+}
+
+
+/** read_location
+  fn read_location(file: &Path) -> Result<LocationData, String>
+    file: &Path = path to the .xmp sidecar
+
+  Reads the position, altitude and GPS time from a sidecar. One which doesn't exist yet, or has no location, comes back empty.
+**/
+pub fn read_location(file: &Path) -> Result<LocationData, String>
+{
+  let mut Location = LocationData::default();
+
+  if !file.exists()
+    {
+      return Ok(Location);
+    }
+
+  let xmp = NkscDocument::load(file)?;
+
+  if let (Some(latitude), Some(longitude)) = (xmp.text_property(EXIF_NAMESPACE, "GPSLatitude"), xmp.text_property(EXIF_NAMESPACE, "GPSLongitude"))
+    {
+      (Location.GPSLatitudeRef, Location.GPSLatitude) = parse_coordinate(&latitude).ok_or_else(|| format!("\"{}\" is not a latitude", latitude))?;
+      (Location.GPSLongitudeRef, Location.GPSLongitude) = parse_coordinate(&longitude).ok_or_else(|| format!("\"{}\" is not a longitude", longitude))?;
+    }
+
+  if let Some(altitude) = xmp.text_property(EXIF_NAMESPACE, "GPSAltitude").and_then(|a| parse_rational(&a))
+    {
+      Location.GPSAltitudeRef = if xmp.text_property(EXIF_NAMESPACE, "GPSAltitudeRef").as_deref() == Some("1") { BELOW_SEA_LEVEL } else { ABOVE_SEA_LEVEL };
+      Location.GPSAltitude = Some(altitude);
+    }
+
+  if let Some(time) = xmp.text_property(EXIF_NAMESPACE, "GPSTimeStamp").and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+    {
+      Location.GPSDateStamp = time.format("%Y:%m:%d").to_string();
+      Location.GPSTimeStamp = vec![time.hour() as f64, time.minute() as f64, time.second() as f64];
+    }
+
+  Ok(Location)
+}
+
+
+/** write_location
+  fn write_location(file: &Path, Location: &LocationData, i_want_to_save_the_original_file: bool) -> Result<(), String>
+    file: &Path = path to the .xmp sidecar, which is made if it isn't there
+    Location: &LocationData = the location to write
+    i_want_to_save_the_original_file: bool = rename an existing sidecar to .original before writing
+
+  Writes a location into the exif: GPS properties of a sidecar. As in the NKSC, anything the location doesn't have is removed rather
+  than left over from an old one.
+**/
+pub fn write_location(file: &Path, Location: &LocationData, i_want_to_save_the_original_file: bool) -> Result<(), String>
+{
+  let exists = file.exists();
+  let mut xmp = if exists { NkscDocument::load(file)? } else { NkscDocument::parse(EMPTY_SIDECAR.to_string())? };
+  let (latitude, longitude) = match (Location.latitude(), Location.longitude())
+    {
+      (Some(latitude), Some(longitude)) => (latitude, longitude),
+      _ => return Err("there is no position to write".to_string()),
+    };
+
+  let mut properties: Vec<(&str, Option<String>)> = vec![
+      ("GPSVersionID", Some(xmp.text_property(EXIF_NAMESPACE, "GPSVersionID").unwrap_or_else(|| "2.3.0.0".to_string()))),
+      ("GPSLatitude", Some(format_coordinate(latitude, 'N', 'S'))),
+      ("GPSLongitude", Some(format_coordinate(longitude, 'E', 'W'))),
+      ("GPSAltitudeRef", Location.GPSAltitude.map(|_| Location.GPSAltitudeRef.to_string())),
+      ("GPSAltitude", Location.GPSAltitude.map(|a| format_rational(a.abs()))),
+      ("GPSTimeStamp", Location.gps_time().map(|t| format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", t.year(), t.month(), t.day(), t.hour(), t.minute(), t.second()))),
+      ("GPSMapDatum", Some(Location.GPSMapDatum.clone()).filter(|d| !d.is_empty())),
+      ("GPSDifferential", Location.GPSDifferential.map(|d| d.to_string())),
+      ("GPSProcessingMethod", Location.processing_method()),
+    ];
+
+  for (name, text) in [("GPSSatellites", &Location.GPSSatellites),
+                       ("GPSMeasureMode", &Location.GPSMeasureMode),
+                       ("GPSSpeedRef", &Location.GPSSpeedRef),
+                       ("GPSTrackRef", &Location.GPSTrackRef),
+                       ("GPSImgDirectionRef", &Location.GPSImgDirectionRef),
+                       ("GPSDestBearingRef", &Location.GPSDestBearingRef),
+                       ("GPSStatus", &Location.GPSStatus)]
+    {
+      properties.push((name, Some(text.clone()).filter(|t| !t.is_empty())));
+    }
+
+  for (name, number) in [("GPSDOP", Location.GPSDOP),
+                         ("GPSSpeed", Location.GPSSpeed),
+                         ("GPSTrack", Location.GPSTrack),
+                         ("GPSImgDirection", Location.GPSImgDirection),
+                         ("GPSDestBearing", Location.GPSDestBearing),
+                         ("GPSHPositioningError", Location.GPSHPositioningError)]
+    {
+      properties.push((name, number.map(format_rational)));
+    }
+
+  for (name, value) in properties
+    {
+      match value
+        {
+          Some(value) => xmp.set_text_property("exif", EXIF_NAMESPACE, name, &value)?,
+          None => xmp.remove_text_property(EXIF_NAMESPACE, name)?,
+        }
+    }
+
+  xmp.save(file, i_want_to_save_the_original_file && exists)
+}
+
+
+/** format_coordinate
+  fn format_coordinate(degrees: f64, positive: char, negative: char) -> String
+
+  Writes signed decimal degrees the XMP way, whole degrees, decimal minutes and the hemisphere, e.g. 33,51.590000S.
+**/
+fn format_coordinate(degrees: f64, positive: char, negative: char) -> String
+{
+  let hemisphere = if degrees < 0.0 { negative } else { positive };
+  let minutes = (degrees.abs().fract() * 60.0 * 1e6).round() / 1e6;
+  let (whole, minutes) = if minutes >= 60.0 { (degrees.abs().trunc() + 1.0, 0.0) } else { (degrees.abs().trunc(), minutes) };
+
+  format!("{},{:.6}{}", whole, minutes, hemisphere)
+}
+
+
+/** parse_coordinate
+  fn parse_coordinate(text: &str) -> Option<(String, Vec<f64>)>
+
+  Reads a coordinate written either as "DDD,MM.mmk" or "DDD,MM,SSk" into its ref and degrees, minutes and seconds.
+**/
+fn parse_coordinate(text: &str) -> Option<(String, Vec<f64>)>
+{
+  let text = text.trim();
+  let hemisphere = text.chars().last().filter(|c| "NSEW".contains(c.to_ascii_uppercase()))?;
+  let numbers: Vec<f64> = text[..text.len() - 1].split(',').map(|n| n.trim().parse::<f64>()).collect::<Result<_, _>>().ok()?;
+
+  match numbers[..]
+    {
+      [degrees, minutes] => Some((hemisphere.to_ascii_uppercase().to_string(), vec![degrees, minutes.trunc(), minutes.fract() * 60.0])),
+      [degrees, minutes, seconds] => Some((hemisphere.to_ascii_uppercase().to_string(), vec![degrees, minutes, seconds])),
+      _ => None,
+    }
+}
+
+
+/** format_rational
+  fn format_rational(value: f64) -> String
+
+  Writes a number as an XMP rational, to the nearest thousandth.
+**/
+fn format_rational(value: f64) -> String
+{
+  format!("{}/1000", (value * 1000.0).round() as i64)
+}
+
+
+/** parse_rational
+  fn parse_rational(text: &str) -> Option<f64>
+
+  Reads an XMP rational such as "12500/1000", or a plain number.
+**/
+fn parse_rational(text: &str) -> Option<f64>
+{
+  match text.split_once('/')
+    {
+      Some((numerator, denominator)) =>
+        {
+          let denominator = denominator.trim().parse::<f64>().ok().filter(|d| *d != 0.0)?;
+          Some(numerator.trim().parse::<f64>().ok()? / denominator)
+        },
+      None => text.trim().parse::<f64>().ok(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn signed(coordinate: &(String, Vec<f64>)) -> f64
+  {
+    let degrees = coordinate.1[0] + coordinate.1[1] / 60.0 + coordinate.1[2] / 3600.0;
+
+    if coordinate.0 == "S" || coordinate.0 == "W" { -degrees } else { degrees }
+  }
+
+  #[test]
+  fn coordinates_round_trip()
+  {
+    for degrees in [-33.859833, 151.208333, 0.0, -0.000001, 89.999999, -179.5, 35.654865]
+      {
+        let text = format_coordinate(degrees, 'N', 'S');
+        let parsed = parse_coordinate(&text).unwrap();
+
+        assert!((signed(&parsed) - degrees).abs() < 1e-7, "{} came back as {} from {}", degrees, signed(&parsed), text);
+      }
+  }
+
+  #[test]
+  fn minutes_that_round_up_carry_into_the_degrees()
+  {
+    assert_eq!(format_coordinate(-33.99999999999, 'N', 'S'), "34,0.000000S");
+    assert_eq!(format_coordinate(151.25, 'E', 'W'), "151,15.000000E");
+  }
+
+  #[test]
+  fn both_ways_of_writing_a_coordinate_are_read()
+  {
+    let (hemisphere, dms) = parse_coordinate("33,51.590000S").unwrap();
+    assert_eq!((hemisphere.as_str(), dms[0], dms[1]), ("S", 33.0, 51.0));
+    assert!((dms[2] - 35.4).abs() < 1e-9);
+    assert_eq!(parse_coordinate("151,12,30E"), Some(("E".to_string(), vec![151.0, 12.0, 30.0])));
+    assert_eq!(parse_coordinate("2,17.67w").map(|c| c.0), Some("W".to_string()));
+    assert_eq!(parse_coordinate("33.86"), None);
+    assert_eq!(parse_coordinate("33,51,35,1S"), None);
+    assert_eq!(parse_coordinate("north"), None);
+  }
+
+  #[test]
+  fn a_location_comes_back_out_of_the_sidecar()
+  {
+    let file = std::env::temp_dir().join(format!("nkscgeosync_test_{}.xmp", std::process::id()));
+    let mut Location = LocationData::from_position(-33.8568, 151.2153, Some(-12.5));
+    Location.GPSDateStamp = "2021:05:03".to_string();
+    Location.GPSTimeStamp = vec![1.0, 2.0, 3.0];
+
+    write_location(&file, &Location, false).unwrap();
+    let read = read_location(&file);
+    std::fs::remove_file(&file).unwrap();
+    let read = read.unwrap();
+
+    assert!((read.latitude().unwrap() - -33.8568).abs() < 1e-7);
+    assert!((read.longitude().unwrap() - 151.2153).abs() < 1e-7);
+    assert_eq!(read.altitude(), Some(-12.5));
+    assert_eq!(read.gps_time(), Location.gps_time());
+  }
+}